@@ -0,0 +1,58 @@
+//! Generates a typed struct per table in data.sql, see src/codegen.rs
+use std::{env, fs, path::PathBuf};
+
+// Both modules are linted as part of the binary
+#[allow(dead_code, clippy::all)]
+#[path = "src/sql.rs"]
+mod sql;
+
+#[allow(dead_code, clippy::all)]
+#[path = "src/codegen.rs"]
+mod codegen;
+
+fn main() {
+    println!("cargo:rerun-if-changed=data.sql");
+    println!("cargo:rerun-if-changed=src/sql.rs");
+    println!("cargo:rerun-if-changed=src/codegen.rs");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    // Without a valid data.sql the module is empty and the crate still builds,
+    // the commands reading data.sql report what is wrong with it
+    let tables = match fs::read_to_string("data.sql").map(|content| sql::parse_schema(&content)) {
+        Ok(Ok(schema)) => schema.tables,
+        Ok(Err(e)) => {
+            println!(
+                "cargo:warning=Invalid data.sql, no table structs generated: {}",
+                e
+            );
+            Vec::new()
+        }
+        Err(_) => Vec::new(),
+    };
+    // A table whose names collide once sanitized would not compile, skip it
+    let tables: Vec<sql::Table> = tables
+        .into_iter()
+        .filter(|table| match codegen::check_names(table) {
+            Ok(()) => true,
+            Err(e) => {
+                println!(
+                    "cargo:warning=No struct generated for {}: {}",
+                    table.name, e
+                );
+                false
+            }
+        })
+        .collect();
+    fs::write(
+        out_dir.join("tables.rs"),
+        codegen::rust_module(&tables).unwrap(),
+    )
+    .unwrap();
+
+    let example = sql::parse_schema(codegen::EXAMPLE_SQL).unwrap().tables;
+    fs::write(
+        out_dir.join("example_tables.rs"),
+        codegen::rust_module(&example).unwrap(),
+    )
+    .unwrap();
+}
@@ -1,17 +1,40 @@
+use crate::preflight::{Action, Check, Outcome};
+use crate::provision::{BucketState, Drift, Encryption, LifecycleRule, Setting};
+use crate::restore::{restore_state, RestoreState};
+use crate::store::{ObjectMeta, ObjectStore, Part, StorageClass, StoreFuture};
 use aws_config::meta::region::RegionProviderChain;
 use aws_config::profile::ProfileFileCredentialsProvider;
+use aws_config::retry::RetryConfig;
 use aws_config::BehaviorVersion;
 use aws_config::SdkConfig;
+use aws_sdk_s3::config::Credentials;
+use aws_sdk_s3::error::BuildError;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::builders::LifecycleRuleBuilder;
+use aws_sdk_s3::types::{
+    BucketLifecycleConfiguration, BucketLocationConstraint, BucketVersioningStatus,
+    CompletedMultipartUpload, CompletedPart, CreateBucketConfiguration, ExpirationStatus,
+    GlacierJobParameters, LifecycleExpiration, LifecycleRuleFilter, PublicAccessBlockConfiguration,
+    RestoreRequest, ServerSideEncryption, ServerSideEncryptionByDefault,
+    ServerSideEncryptionConfiguration, ServerSideEncryptionRule, StorageClass as S3StorageClass,
+    Tag, Tagging, Tier, Transition, TransitionStorageClass, VersioningConfiguration,
+};
 use aws_sdk_s3::Client as S3Client;
 use aws_types::region::Region;
 use aws_types::request_id::RequestId;
+use bytes::Bytes;
 use colored::*;
 use rand::Rng;
 use scopeguard::defer;
+use serde::Deserialize;
 use std::env;
 use std::error::Error;
-use std::time::Instant;
+use std::fmt;
+use std::future::Future;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tokio::task;
 use uuid::Uuid;
 
@@ -38,73 +61,388 @@ pub fn check_aws_profile() -> Result<String, String> {
     }
 }
 
-/// Function to create an AWS SDK configuration
-async fn create_aws_config() -> Result<SdkConfig, Box<dyn Error>> {
-    // Retrieve the AWS profile name from environment variables or default to "default"
-    let profile_name = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+/// Where and how to reach S3. Read from `~/.config/lernspark/s3.json`, or the
+/// file named by `LERNSPARK_S3_CONFIG`, then overridden by `AWS_ENDPOINT_URL`,
+/// `LERNSPARK_S3_PATH_STYLE`, `AWS_REGION`, `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`, so a MinIO or LocalStack
+/// container can stand in for AWS
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct S3Settings {
+    pub endpoint_url: Option<String>,
+    pub force_path_style: bool,
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    /// Bucket `preflight` and the default run check permissions on
+    pub bucket: Option<String>,
+}
 
-    let credentials_provider = ProfileFileCredentialsProvider::builder()
-        .profile_name(&profile_name)
-        .build();
+impl S3Settings {
+    /// Settings from the config file, if there is one, and the environment
+    pub fn load() -> Result<S3Settings, String> {
+        let (path, required) = match env::var("LERNSPARK_S3_CONFIG") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => match dirs::config_dir() {
+                Some(dir) => (dir.join("lernspark").join("s3.json"), false),
+                None => return S3Settings::default().with_env(|name| env::var(name).ok()),
+            },
+        };
+        let file = match std::fs::read_to_string(&path) {
+            Ok(content) => Some(content),
+            Err(_) if !required => None,
+            Err(e) => return Err(format!("Unable to read '{}': {}", path.display(), e)),
+        };
+        let settings = match file {
+            Some(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid S3 settings in '{}': {}", path.display(), e))?,
+            None => S3Settings::default(),
+        };
+        settings.with_env(|name| env::var(name).ok())
+    }
 
-    // Since `default_region` is now an owned String, it can safely be used here
-    let region_provider = RegionProviderChain::default_provider().or_else(Region::new("us-east-1")); // No lifetime issues now
+    /// Override settings with the environment variables `var` returns
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Result<S3Settings, String> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        if let Some(url) = var("AWS_ENDPOINT_URL") {
+            self.endpoint_url = Some(url);
+        }
+        if let Some(path_style) = var("LERNSPARK_S3_PATH_STYLE") {
+            self.force_path_style = match path_style.to_lowercase().as_str() {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" => false,
+                other => return Err(format!("Invalid LERNSPARK_S3_PATH_STYLE '{}'", other)),
+            };
+        }
+        if let Some(region) = var("AWS_REGION") {
+            self.region = Some(region);
+        }
+        if let Some(id) = var("AWS_ACCESS_KEY_ID") {
+            self.access_key_id = Some(id);
+            self.session_token = None;
+        }
+        if let Some(secret) = var("AWS_SECRET_ACCESS_KEY") {
+            self.secret_access_key = Some(secret);
+        }
+        if let Some(token) = var("AWS_SESSION_TOKEN") {
+            self.session_token = Some(token);
+        }
+        if let Some(bucket) = var("LERNSPARK_S3_BUCKET") {
+            self.bucket = Some(bucket);
+        }
+        match (&self.access_key_id, &self.secret_access_key) {
+            (Some(_), None) | (None, Some(_)) => Err(
+                "Static S3 credentials need both an access key id and a secret access key"
+                    .to_string(),
+            ),
+            _ => Ok(self),
+        }
+    }
 
-    let config = aws_config::defaults(BehaviorVersion::latest())
-        .credentials_provider(credentials_provider)
-        .region(region_provider)
-        .load()
-        .await;
+    /// True when a key pair is configured instead of an AWS profile
+    pub fn has_static_credentials(&self) -> bool {
+        self.access_key_id.is_some()
+    }
+}
 
-    Ok(config)
+/// What went wrong talking to AWS, classified from the SDK error metadata
+#[derive(Debug, Clone, PartialEq)]
+pub enum AwsError {
+    /// Missing, invalid or expired credentials
+    Auth(String),
+    /// Valid credentials that are not allowed to make the call
+    Permission(String),
+    /// S3 asked for fewer requests or is briefly unavailable
+    Throttling(String),
+    NotFound(String),
+    /// No response arrived, from DNS, connection or timeout problems
+    Network(String),
+    /// The bucket name is taken or the bucket is busy with another operation
+    BucketConflict(String),
+    Other(String),
 }
 
-/// Check the permissions of the aws user
-pub async fn check_permissions() -> Result<(), String> {
-    // Create AWS configuration
-    let config = create_aws_config().await.map_err(|e| e.to_string())?;
+impl AwsError {
+    /// Classify an SDK error, `context` says what was being done
+    pub fn from_sdk<E>(context: &str, err: SdkError<E>) -> AwsError
+    where
+        E: ProvideErrorMetadata + Error + 'static,
+    {
+        // The SDK's own message is only the variant name, the cause is in the sources
+        let mut message = context.to_string();
+        let mut source: Option<&dyn Error> = Some(&err);
+        while let Some(cause) = source {
+            let cause_text = cause.to_string();
+            if !message.ends_with(&cause_text) {
+                message = format!("{}: {}", message, cause_text);
+            }
+            source = cause.source();
+        }
+        match &err {
+            SdkError::TimeoutError(_) => AwsError::Network(message),
+            SdkError::DispatchFailure(failure) if failure.is_io() || failure.is_timeout() => {
+                AwsError::Network(message)
+            }
+            // Credential providers fail before the request is sent
+            SdkError::DispatchFailure(_) | SdkError::ConstructionFailure(_)
+                if message.to_lowercase().contains("credentials") =>
+            {
+                AwsError::Auth(message)
+            }
+            SdkError::ServiceError(_) | SdkError::ResponseError(_) => {
+                let status = err.raw_response().map(|r| r.status().as_u16());
+                AwsError::classify(err.code(), status, message)
+            }
+            _ => AwsError::Other(message),
+        }
+    }
 
-    let s3_client = S3Client::new(&config);
+    /// Classify a service response by its error code, or else its HTTP status
+    pub fn classify(code: Option<&str>, status: Option<u16>, message: String) -> AwsError {
+        match (code.unwrap_or_default(), status) {
+            (
+                "InvalidAccessKeyId"
+                | "SignatureDoesNotMatch"
+                | "ExpiredToken"
+                | "InvalidToken"
+                | "TokenRefreshRequired",
+                _,
+            )
+            | (_, Some(401)) => AwsError::Auth(message),
+            ("AccessDenied" | "AllAccessDisabled" | "AccountProblem", _) | (_, Some(403)) => {
+                AwsError::Permission(message)
+            }
+            (
+                "SlowDown"
+                | "Throttling"
+                | "ThrottlingException"
+                | "RequestLimitExceeded"
+                | "ServiceUnavailable"
+                | "InternalError"
+                | "RequestTimeout",
+                _,
+            )
+            | (_, Some(429 | 500 | 503)) => AwsError::Throttling(message),
+            (
+                "NoSuchBucket"
+                | "NoSuchKey"
+                | "NoSuchUpload"
+                | "NotFound"
+                | "NoSuchLifecycleConfiguration"
+                | "NoSuchTagSet"
+                | "NoSuchPublicAccessBlockConfiguration"
+                | "ServerSideEncryptionConfigurationNotFoundError",
+                _,
+            )
+            | (_, Some(404)) => AwsError::NotFound(message),
+            (
+                "BucketAlreadyExists"
+                | "BucketAlreadyOwnedByYou"
+                | "OperationAborted"
+                | "BucketNotEmpty",
+                _,
+            )
+            | (_, Some(409)) => AwsError::BucketConflict(message),
+            _ => AwsError::Other(message),
+        }
+    }
 
-    // Attempt to list buckets to check permissions
-    match s3_client.list_buckets().send().await {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            // Interpret error message directly
-            if e.to_string().contains("AccessDenied") {
-                Err("Access denied: Insufficient permissions to list buckets.".to_string())
-            } else {
-                Err(format!("Failed to list buckets: {}", e))
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AwsError::Auth(_) => "authentication",
+            AwsError::Permission(_) => "permission",
+            AwsError::Throttling(_) => "throttling",
+            AwsError::NotFound(_) => "not found",
+            AwsError::Network(_) => "network",
+            AwsError::BucketConflict(_) => "bucket conflict",
+            AwsError::Other(_) => "error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AwsError::Auth(m)
+            | AwsError::Permission(m)
+            | AwsError::Throttling(m)
+            | AwsError::NotFound(m)
+            | AwsError::Network(m)
+            | AwsError::BucketConflict(m)
+            | AwsError::Other(m) => m,
+        }
+    }
+
+    /// Throttling and network errors are worth another attempt
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, AwsError::Throttling(_) | AwsError::Network(_))
+    }
+
+    /// What to do about it
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            AwsError::Auth(_) => Some(
+                "Refresh your credentials with `aws sso login` or `aws configure --profile \
+                 <profile-name>` and set AWS_PROFILE, or set AWS_ACCESS_KEY_ID and \
+                 AWS_SECRET_ACCESS_KEY for a custom endpoint",
+            ),
+            AwsError::Permission(_) => Some(
+                "Your identity is not allowed to make this call, ask for s3:ListBucket, \
+                 s3:GetObject, s3:PutObject and s3:DeleteObject on the bucket",
+            ),
+            AwsError::Throttling(_) => {
+                Some("S3 is throttling requests, lower --concurrency or try again later")
             }
+            AwsError::NotFound(_) => Some(
+                "Check the bucket name, its region (AWS_REGION) and that the key or prefix exists",
+            ),
+            AwsError::Network(_) => Some(
+                "Check your network connection, proxy settings and AWS_ENDPOINT_URL if you set it",
+            ),
+            AwsError::BucketConflict(_) => Some(
+                "Bucket names are global, pick another name or wait for the pending \
+                 create or delete of this bucket to finish",
+            ),
+            AwsError::Other(_) => None,
         }
     }
 }
 
-/// Check the users aws config
-pub async fn check_aws_config() -> Result<(), String> {
-    // Create AWS configuration
-    let config = create_aws_config().await.map_err(|e| e.to_string())?;
+impl fmt::Display for AwsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())?;
+        if let Some(hint) = self.hint() {
+            write!(f, "\n💡 {}", hint)?;
+        }
+        Ok(())
+    }
+}
 
-    // Create an S3 client with the final configuration
-    let s3_client = S3Client::new(&config);
+impl From<AwsError> for String {
+    fn from(err: AwsError) -> String {
+        err.to_string()
+    }
+}
 
-    // Attempt to list buckets as a simple connection test
-    s3_client
-        .list_buckets()
-        .send()
+/// Attempts made at a retryable call before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Exponential backoff from 200ms up to 10s, with jitter so concurrent
+/// uploads do not retry in lockstep
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = (200u64 << (attempt - 1).min(6)).min(10_000);
+    Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2..=ceiling))
+}
+
+/// Run `call` again after throttling and network errors, up to `MAX_ATTEMPTS` times
+pub async fn with_retry<T, F, Fut>(what: &str, mut call: F) -> Result<T, AwsError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AwsError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if e.is_retryable() && attempt < MAX_ATTEMPTS => {
+                let delay = backoff(attempt);
+                println!(
+                    "{}",
+                    format!(
+                        "⏳ {} ({} error), retrying in {} ms ({}/{})",
+                        what,
+                        e.kind(),
+                        delay.as_millis(),
+                        attempt,
+                        MAX_ATTEMPTS - 1
+                    )
+                    .yellow()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Function to create an AWS SDK configuration
+async fn create_aws_config(settings: &S3Settings) -> Result<SdkConfig, Box<dyn Error>> {
+    let mut loader = aws_config::defaults(BehaviorVersion::latest());
+
+    loader = match (&settings.access_key_id, &settings.secret_access_key) {
+        (Some(id), Some(secret)) => loader.credentials_provider(Credentials::new(
+            id,
+            secret,
+            settings.session_token.clone(),
+            None,
+            "lernspark-static",
+        )),
+        _ => {
+            // Retrieve the AWS profile name from environment variables or default to "default"
+            let profile_name = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+            loader.credentials_provider(
+                ProfileFileCredentialsProvider::builder()
+                    .profile_name(&profile_name)
+                    .build(),
+            )
+        }
+    };
+
+    let region_provider = RegionProviderChain::first_try(settings.region.clone().map(Region::new))
+        .or_default_provider()
+        .or_else(Region::new("us-east-1"));
+    loader = loader.region(region_provider);
+
+    if let Some(url) = &settings.endpoint_url {
+        loader = loader.endpoint_url(url);
+    }
+
+    // `with_retry` decides what to retry, so the SDK should not retry as well
+    loader = loader.retry_config(RetryConfig::disabled());
+
+    Ok(loader.load().await)
+}
+
+/// An S3 client for the configured endpoint, with path-style addressing if asked for
+async fn create_s3_client() -> Result<S3Client, String> {
+    create_s3_client_with(&S3Settings::load()?).await
+}
+
+async fn create_s3_client_with(settings: &S3Settings) -> Result<S3Client, String> {
+    let config = create_aws_config(settings)
         .await
-        .map_err(|err| format!("Error connecting to AWS: {}", err))?;
+        .map_err(|e| e.to_string())?;
+    let s3_config = aws_sdk_s3::config::Builder::from(&config)
+        .force_path_style(settings.force_path_style)
+        .build();
+    Ok(S3Client::from_conf(s3_config))
+}
 
-    Ok(())
+/// Check the users aws config reaches S3, with one call listing the buckets
+pub async fn check_aws_config() -> Result<(), String> {
+    let s3_client = create_s3_client().await?;
+    let listed = with_retry("Connecting to AWS", || async {
+        s3_client
+            .list_buckets()
+            .send()
+            .await
+            .map_err(|e| AwsError::from_sdk("Error connecting to AWS", e))
+    })
+    .await;
+    match listed {
+        Ok(_) => Ok(()),
+        Err(AwsError::Permission(_)) => Err(
+            "Access denied: Insufficient permissions to list buckets, set a bucket to \
+             check with `data preflight s3://<bucket>` instead"
+                .to_string(),
+        ),
+        Err(e) => Err(e.to_string()),
+    }
 }
 
 /// A connection and upload test function ensuring you can upload data.
 pub async fn check_s3_deep_glacier() -> Result<bool, String> {
-    // Create AWS configuration
-    let config = create_aws_config().await.map_err(|e| e.to_string())?;
-
     // Create an S3 client
-    let s3_client = S3Client::new(&config);
+    let s3_client = create_s3_client().await?;
 
     // Create a test bucket name (you can use a different naming scheme if needed)
     let test_bucket = format!("lernspark-{}", Uuid::new_v4());
@@ -115,7 +453,7 @@ pub async fn check_s3_deep_glacier() -> Result<bool, String> {
         .bucket(&test_bucket)
         .send()
         .await
-        .map_err(|err| format!("Error creating test bucket: {}", err))?;
+        .map_err(|err| AwsError::from_sdk("Error creating test bucket", err))?;
 
     // Extract relevant information from the create_bucket_resp
     let bucket_location = create_bucket_resp
@@ -141,7 +479,7 @@ pub async fn check_s3_deep_glacier() -> Result<bool, String> {
         .bucket(&test_bucket)
         .send()
         .await
-        .map_err(|err| format!("Error retrieving bucket location: {}", err))?;
+        .map_err(|err| AwsError::from_sdk("Error retrieving bucket location", err))?;
 
     // Check if the bucket's location supports the "DEEP_ARCHIVE" storage class
     let deep_glacier_available = match &get_bucket_location_resp.location_constraint {
@@ -275,10 +613,11 @@ pub async fn upload_random_data(
             );
             Ok(())
         }
-        Err(err) => Err(format!(
-            "Error storing random byte data in bucket '{}': {}",
-            bucket_name, err
-        )),
+        Err(err) => Err(AwsError::from_sdk(
+            &format!("Error storing random byte data in bucket '{}'", bucket_name),
+            err,
+        )
+        .into()),
     }
 }
 
@@ -308,10 +647,14 @@ pub async fn delete_objects_and_bucket(
                 );
             }
             Err(err) => {
-                return Err(format!(
-                    "Error deleting object '{}' from bucket '{}': {}",
-                    object_key, bucket_name, err
-                ));
+                return Err(AwsError::from_sdk(
+                    &format!(
+                        "Error deleting object '{}' from bucket '{}'",
+                        object_key, bucket_name
+                    ),
+                    err,
+                )
+                .into());
             }
         }
     }
@@ -327,7 +670,878 @@ pub async fn delete_objects_and_bucket(
             );
             Ok(())
         }
-        Err(err) => Err(format!("Error deleting bucket '{}': {}", bucket_name, err)),
+        Err(err) => {
+            Err(AwsError::from_sdk(&format!("Error deleting bucket '{}'", bucket_name), err).into())
+        }
+    }
+}
+
+/// An `ObjectStore` backed by one S3 bucket
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+}
+
+impl S3Store {
+    /// A store for `bucket` using the AWS profile or the configured
+    /// endpoint and static credentials
+    pub async fn connect(bucket: &str) -> Result<S3Store, String> {
+        if !S3Settings::load()?.has_static_credentials() {
+            check_aws_profile()?;
+        }
+        Ok(S3Store {
+            client: create_s3_client().await?,
+            bucket: bucket.to_string(),
+        })
+    }
+
+    /// Like `connect`, with a client for `region`, where the bucket lives
+    /// or is to be created
+    pub async fn connect_in(bucket: &str, region: &str) -> Result<S3Store, String> {
+        let mut settings = S3Settings::load()?;
+        if !settings.has_static_credentials() {
+            check_aws_profile()?;
+        }
+        settings.region = Some(region.to_string());
+        Ok(S3Store {
+            client: create_s3_client_with(&settings).await?,
+            bucket: bucket.to_string(),
+        })
+    }
+
+    /// The object, or the bytes of it an HTTP `Range` header selects
+    async fn download(&self, key: &str, range: Option<String>) -> Result<Bytes, String> {
+        let context = format!("Error downloading '{}'", key);
+        let body = with_retry(&context, || async {
+            let object = self
+                .client
+                .get_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .set_range(range.clone())
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))?;
+            object
+                .body
+                .collect()
+                .await
+                .map_err(|e| AwsError::Network(format!("{}: {}", context, e)))
+        })
+        .await?;
+        Ok(body.into_bytes())
+    }
+}
+
+/// The region requests go to when no bucket says otherwise
+pub async fn default_region() -> Result<String, String> {
+    let client = create_s3_client().await?;
+    Ok(client
+        .config()
+        .region()
+        .map(|r| r.to_string())
+        .unwrap_or_else(|| "us-east-1".to_string()))
+}
+
+impl ObjectStore for S3Store {
+    fn location(&self) -> String {
+        format!("s3://{}", self.bucket)
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        body: Bytes,
+        storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let context = format!("Error uploading '{}'", key);
+            with_retry(&context, || async {
+                self.client
+                    .put_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .set_storage_class(storage_class.map(|c| S3StorageClass::from(c.as_str())))
+                    .body(ByteStream::from(body.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Bytes> {
+        Box::pin(self.download(key, None))
+    }
+
+    fn get_range<'a>(&'a self, key: &'a str, range: Range<u64>) -> StoreFuture<'a, Bytes> {
+        Box::pin(async move {
+            if range.is_empty() {
+                return Ok(Bytes::new());
+            }
+            // HTTP ranges include their last byte
+            let bytes = format!("bytes={}-{}", range.start, range.end - 1);
+            self.download(key, Some(bytes)).await
+        })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, Vec<ObjectMeta>> {
+        Box::pin(async move {
+            let context = format!("Error listing '{}'", prefix);
+            let mut objects = Vec::new();
+            let mut continuation_token: Option<String> = None;
+            loop {
+                let page = with_retry(&context, || async {
+                    self.client
+                        .list_objects_v2()
+                        .bucket(&self.bucket)
+                        .prefix(prefix)
+                        .set_continuation_token(continuation_token.clone())
+                        .send()
+                        .await
+                        .map_err(|e| AwsError::from_sdk(&context, e))
+                })
+                .await?;
+                objects.extend(page.contents().iter().map(|object| {
+                    ObjectMeta {
+                        key: object.key().unwrap_or_default().to_string(),
+                        size: object.size().unwrap_or(0) as u64,
+                        etag: object
+                            .e_tag()
+                            .map(|etag| etag.trim_matches('"').to_string()),
+                        storage_class: object.storage_class().map(|c| c.as_str().to_string()),
+                    }
+                }));
+                continuation_token = page.next_continuation_token().map(|t| t.to_string());
+                if continuation_token.is_none() {
+                    return Ok(objects);
+                }
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let context = format!("Error deleting '{}'", key);
+            with_retry(&context, || async {
+                self.client
+                    .delete_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn create_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, String> {
+        Box::pin(async move {
+            let context = format!("Error starting upload of '{}'", key);
+            let upload = with_retry(&context, || async {
+                self.client
+                    .create_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .set_storage_class(storage_class.map(|c| S3StorageClass::from(c.as_str())))
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await?;
+            upload
+                .upload_id()
+                .map(|id| id.to_string())
+                .ok_or_else(|| format!("No upload id returned for '{}'", key))
+        })
+    }
+
+    fn put_part<'a>(
+        &'a self,
+        key: &'a str,
+        upload_id: &'a str,
+        number: i32,
+        body: Bytes,
+    ) -> StoreFuture<'a, Part> {
+        Box::pin(async move {
+            let context = format!("Error uploading part {} of '{}'", number, key);
+            let part = with_retry(&context, || async {
+                self.client
+                    .upload_part()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(number)
+                    .body(ByteStream::from(body.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await?;
+            Ok(Part {
+                number,
+                etag: part.e_tag().unwrap_or_default().to_string(),
+            })
+        })
+    }
+
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        upload_id: &'a str,
+        mut parts: Vec<Part>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            parts.sort_by_key(|p| p.number);
+            let completed = CompletedMultipartUpload::builder()
+                .set_parts(Some(
+                    parts
+                        .into_iter()
+                        .map(|part| {
+                            CompletedPart::builder()
+                                .part_number(part.number)
+                                .e_tag(part.etag)
+                                .build()
+                        })
+                        .collect(),
+                ))
+                .build();
+            let context = format!("Error completing upload of '{}'", key);
+            with_retry(&context, || async {
+                self.client
+                    .complete_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .multipart_upload(completed.clone())
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn abort_multipart<'a>(&'a self, key: &'a str, upload_id: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let context = format!("Error aborting upload of '{}'", key);
+            with_retry(&context, || async {
+                self.client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await?;
+            Ok(())
+        })
+    }
+}
+
+/// A missing bucket setting reads as `None` rather than an error
+fn optional<T>(result: Result<T, AwsError>) -> Result<Option<T>, String> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(AwsError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Bucket settings, for `provision`
+impl S3Store {
+    /// The settings of the bucket, `None` when it does not exist
+    pub async fn bucket_state(&self) -> Result<Option<BucketState>, String> {
+        let bucket = &self.bucket;
+        let context = format!("Error reading the settings of bucket '{}'", bucket);
+        let exists = with_retry(&context, || async {
+            self.client
+                .head_bucket()
+                .bucket(bucket)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))
+        })
+        .await;
+        if optional(exists)?.is_none() {
+            return Ok(None);
+        }
+
+        let location = with_retry(&context, || async {
+            self.client
+                .get_bucket_location()
+                .bucket(bucket)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))
+        })
+        .await?;
+        // us-east-1 has no location constraint and EU is the legacy name of eu-west-1
+        let region = match location.location_constraint().map(|l| l.as_str()) {
+            None | Some("") => "us-east-1",
+            Some("EU") => "eu-west-1",
+            Some(region) => region,
+        };
+
+        let versioning = with_retry(&context, || async {
+            self.client
+                .get_bucket_versioning()
+                .bucket(bucket)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))
+        })
+        .await?;
+
+        let encryption = optional(
+            with_retry(&context, || async {
+                self.client
+                    .get_bucket_encryption()
+                    .bucket(bucket)
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await,
+        )?;
+        let encryption = encryption
+            .as_ref()
+            .and_then(|e| e.server_side_encryption_configuration())
+            .and_then(|c| c.rules().first())
+            .and_then(|r| r.apply_server_side_encryption_by_default())
+            .map(|default| match default.sse_algorithm() {
+                ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse => {
+                    Encryption::Kms(default.kms_master_key_id().map(str::to_string))
+                }
+                _ => Encryption::SseS3,
+            });
+
+        let public_access = optional(
+            with_retry(&context, || async {
+                self.client
+                    .get_public_access_block()
+                    .bucket(bucket)
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await,
+        )?;
+        let block_public_access = public_access
+            .as_ref()
+            .and_then(|p| p.public_access_block_configuration())
+            .is_some_and(|c| {
+                c.block_public_acls() == Some(true)
+                    && c.ignore_public_acls() == Some(true)
+                    && c.block_public_policy() == Some(true)
+                    && c.restrict_public_buckets() == Some(true)
+            });
+
+        let lifecycle = optional(
+            with_retry(&context, || async {
+                self.client
+                    .get_bucket_lifecycle_configuration()
+                    .bucket(bucket)
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await,
+        )?;
+        let lifecycle = lifecycle
+            .as_ref()
+            .map(|l| l.rules())
+            .unwrap_or_default()
+            .iter()
+            .map(|rule| {
+                let transition = rule.transitions().first();
+                LifecycleRule {
+                    id: rule.id().unwrap_or_default().to_string(),
+                    prefix: match rule.filter() {
+                        Some(LifecycleRuleFilter::Prefix(prefix)) => prefix.clone(),
+                        _ => String::new(),
+                    },
+                    transition_days: transition.and_then(|t| t.days()),
+                    storage_class: transition
+                        .and_then(|t| t.storage_class())
+                        .map_or("DEEP_ARCHIVE", |c| c.as_str())
+                        .to_string(),
+                    expiration_days: rule.expiration().and_then(|e| e.days()),
+                }
+            })
+            .collect();
+
+        let tagging = optional(
+            with_retry(&context, || async {
+                self.client
+                    .get_bucket_tagging()
+                    .bucket(bucket)
+                    .send()
+                    .await
+                    .map_err(|e| AwsError::from_sdk(&context, e))
+            })
+            .await,
+        )?;
+        let tags = tagging
+            .as_ref()
+            .map(|t| t.tag_set())
+            .unwrap_or_default()
+            .iter()
+            .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+            .collect();
+
+        Ok(Some(BucketState {
+            region: region.to_string(),
+            versioning: versioning.status() == Some(&BucketVersioningStatus::Enabled),
+            encryption,
+            block_public_access,
+            lifecycle,
+            tags,
+        }))
+    }
+
+    /// Create the bucket in `region`
+    pub async fn create_bucket(&self, region: &str) -> Result<(), String> {
+        let context = format!("Error creating bucket '{}'", self.bucket);
+        with_retry(&context, || async {
+            let mut request = self.client.create_bucket().bucket(&self.bucket);
+            // us-east-1 is the default and rejects being named as a constraint
+            if region != "us-east-1" {
+                request = request.create_bucket_configuration(
+                    CreateBucketConfiguration::builder()
+                        .location_constraint(BucketLocationConstraint::from(region))
+                        .build(),
+                );
+            }
+            request
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Bring the `drifts` settings of the bucket, currently `actual`, to `desired`.
+    /// Tags already on the bucket are kept.
+    pub async fn apply_bucket_state(
+        &self,
+        desired: &BucketState,
+        actual: &BucketState,
+        drifts: &[Drift],
+    ) -> Result<(), String> {
+        let bucket = &self.bucket;
+        let build_error =
+            |e: BuildError| format!("Invalid settings for bucket '{}': {}", bucket, e);
+        for drift in drifts {
+            let context = format!(
+                "Error setting the {} of bucket '{}'",
+                drift.setting.as_str(),
+                bucket
+            );
+            match drift.setting {
+                // Buckets cannot be moved, `provision` reports it instead
+                Setting::Region => {}
+                Setting::Versioning => {
+                    let status = if desired.versioning {
+                        BucketVersioningStatus::Enabled
+                    } else {
+                        BucketVersioningStatus::Suspended
+                    };
+                    let config = VersioningConfiguration::builder().status(status).build();
+                    with_retry(&context, || async {
+                        self.client
+                            .put_bucket_versioning()
+                            .bucket(bucket)
+                            .versioning_configuration(config.clone())
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+                Setting::Encryption => {
+                    let default = match &desired.encryption {
+                        Some(Encryption::Kms(key)) => ServerSideEncryptionByDefault::builder()
+                            .sse_algorithm(ServerSideEncryption::AwsKms)
+                            .set_kms_master_key_id(key.clone()),
+                        _ => ServerSideEncryptionByDefault::builder()
+                            .sse_algorithm(ServerSideEncryption::Aes256),
+                    };
+                    let config = ServerSideEncryptionConfiguration::builder()
+                        .rules(
+                            ServerSideEncryptionRule::builder()
+                                .apply_server_side_encryption_by_default(
+                                    default.build().map_err(build_error)?,
+                                )
+                                .bucket_key_enabled(true)
+                                .build(),
+                        )
+                        .build()
+                        .map_err(build_error)?;
+                    with_retry(&context, || async {
+                        self.client
+                            .put_bucket_encryption()
+                            .bucket(bucket)
+                            .server_side_encryption_configuration(config.clone())
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+                Setting::PublicAccessBlock if desired.block_public_access => {
+                    let config = PublicAccessBlockConfiguration::builder()
+                        .block_public_acls(true)
+                        .ignore_public_acls(true)
+                        .block_public_policy(true)
+                        .restrict_public_buckets(true)
+                        .build();
+                    with_retry(&context, || async {
+                        self.client
+                            .put_public_access_block()
+                            .bucket(bucket)
+                            .public_access_block_configuration(config.clone())
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+                Setting::PublicAccessBlock => {
+                    with_retry(&context, || async {
+                        self.client
+                            .delete_public_access_block()
+                            .bucket(bucket)
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+                Setting::Lifecycle if desired.lifecycle.is_empty() => {
+                    with_retry(&context, || async {
+                        self.client
+                            .delete_bucket_lifecycle()
+                            .bucket(bucket)
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+                Setting::Lifecycle => {
+                    let mut config = BucketLifecycleConfiguration::builder();
+                    for rule in &desired.lifecycle {
+                        let mut builder = LifecycleRuleBuilder::default()
+                            .id(&rule.id)
+                            .filter(LifecycleRuleFilter::Prefix(rule.prefix.clone()))
+                            .status(ExpirationStatus::Enabled);
+                        if let Some(days) = rule.transition_days {
+                            builder = builder.transitions(
+                                Transition::builder()
+                                    .days(days)
+                                    .storage_class(TransitionStorageClass::from(
+                                        rule.storage_class.as_str(),
+                                    ))
+                                    .build(),
+                            );
+                        }
+                        if let Some(days) = rule.expiration_days {
+                            builder = builder
+                                .expiration(LifecycleExpiration::builder().days(days).build());
+                        }
+                        config = config.rules(builder.build().map_err(build_error)?);
+                    }
+                    let config = config.build().map_err(build_error)?;
+                    with_retry(&context, || async {
+                        self.client
+                            .put_bucket_lifecycle_configuration()
+                            .bucket(bucket)
+                            .lifecycle_configuration(config.clone())
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+                Setting::Tags => {
+                    let mut tags = actual.tags.clone();
+                    tags.extend(desired.tags.clone());
+                    let mut tagging = Tagging::builder();
+                    for (key, value) in tags {
+                        tagging = tagging.tag_set(
+                            Tag::builder()
+                                .key(key)
+                                .value(value)
+                                .build()
+                                .map_err(build_error)?,
+                        );
+                    }
+                    let tagging = tagging.build().map_err(build_error)?;
+                    with_retry(&context, || async {
+                        self.client
+                            .put_bucket_tagging()
+                            .bucket(bucket)
+                            .tagging(tagging.clone())
+                            .send()
+                            .await
+                            .map_err(|e| AwsError::from_sdk(&context, e))
+                    })
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Glacier restores, for `restore`
+impl S3Store {
+    /// Whether the object can be read or first needs a restore
+    pub async fn restore_state(&self, key: &str) -> Result<RestoreState, String> {
+        let context = format!("Error checking '{}'", key);
+        let head = with_retry(&context, || async {
+            self.client
+                .head_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))
+        })
+        .await?;
+        Ok(restore_state(
+            head.storage_class().map(|c| c.as_str()),
+            head.archive_status().map(|s| s.as_str()),
+            head.restore(),
+        ))
+    }
+
+    /// Ask for a restore of an archived object, readable for `days` once done.
+    /// False when a restore is already in progress.
+    pub async fn request_restore(
+        &self,
+        key: &str,
+        days: Option<i32>,
+        tier: &str,
+    ) -> Result<bool, String> {
+        let context = format!("Error requesting a restore of '{}'", key);
+        let parameters = GlacierJobParameters::builder()
+            .tier(Tier::from(tier))
+            .build()
+            .map_err(|e| format!("{}: {}", context, e))?;
+        let request = match days {
+            Some(days) => RestoreRequest::builder()
+                .days(days)
+                .glacier_job_parameters(parameters)
+                .build(),
+            None => RestoreRequest::builder().tier(Tier::from(tier)).build(),
+        };
+        let requested = with_retry(&context, || async {
+            match self
+                .client
+                .restore_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .restore_request(request.clone())
+                .send()
+                .await
+            {
+                Ok(_) => Ok(true),
+                Err(e) if e.code() == Some("RestoreAlreadyInProgress") => Ok(false),
+                Err(e) => Err(AwsError::from_sdk(&context, e)),
+            }
+        })
+        .await?;
+        Ok(requested)
+    }
+}
+
+/// Probes for `preflight`
+impl S3Store {
+    /// List at most one key, the cheapest call showing the bucket is readable
+    pub async fn check_list(&self) -> Result<(), String> {
+        let context = format!("Error listing bucket '{}'", self.bucket);
+        with_retry(&context, || async {
+            self.client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .max_keys(1)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk(&context, e))
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Try every action lernspark needs with a real, cheap call: list one key,
+    /// write, read and delete a small object under `prefix`, start and abort a
+    /// multipart upload and create the (existing) bucket again
+    pub async fn preflight(&self, prefix: &str) -> Vec<Check> {
+        let bucket = &self.bucket;
+        let list_prefix = crate::upload::list_prefix(prefix);
+        let key = format!("{}.lernspark-preflight-{}", list_prefix, Uuid::new_v4());
+        let mut checks = Vec::new();
+
+        let listed = with_retry("Probing s3:ListBucket", || async {
+            self.client
+                .list_objects_v2()
+                .bucket(bucket)
+                .prefix(&list_prefix)
+                .max_keys(1)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk("ListObjectsV2", e))
+        })
+        .await;
+        if let Err(AwsError::NotFound(_)) = listed {
+            let missing = format!("bucket '{}' does not exist, provision creates it", bucket);
+            checks.push(Check::new(
+                Action::ListBucket,
+                "ListObjectsV2",
+                Outcome::Unknown(missing),
+            ));
+            return checks;
+        }
+        let can_list = listed.is_ok();
+        checks.push(Check::new(
+            Action::ListBucket,
+            "ListObjectsV2",
+            Outcome::of(listed),
+        ));
+
+        let put = with_retry("Probing s3:PutObject", || async {
+            self.client
+                .put_object()
+                .bucket(bucket)
+                .key(&key)
+                .body(ByteStream::from_static(b"lernspark preflight"))
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk("PutObject", e))
+        })
+        .await;
+        let wrote = put.is_ok();
+        checks.push(Check::new(Action::PutObject, "PutObject", Outcome::of(put)));
+
+        // Without the probe object the read targets a missing key, which S3
+        // reports as not found when the caller may list, and as denied otherwise
+        let got = with_retry("Probing s3:GetObject", || async {
+            self.client
+                .get_object()
+                .bucket(bucket)
+                .key(&key)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk("GetObject", e))
+        })
+        .await;
+        let outcome = match got {
+            Err(AwsError::NotFound(_)) if !wrote => Outcome::Allowed,
+            Err(AwsError::Permission(_)) if !wrote && !can_list => {
+                Outcome::Unknown("needs s3:PutObject or s3:ListBucket to tell".to_string())
+            }
+            got => Outcome::of(got),
+        };
+        checks.push(Check::new(Action::GetObject, "GetObject", outcome));
+
+        let created = with_retry("Probing multipart uploads", || async {
+            self.client
+                .create_multipart_upload()
+                .bucket(bucket)
+                .key(&key)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk("CreateMultipartUpload", e))
+        })
+        .await;
+        let upload_id = created
+            .as_ref()
+            .ok()
+            .and_then(|upload| upload.upload_id())
+            .map(|id| id.to_string());
+        checks.push(Check::new(
+            Action::PutObject,
+            "CreateMultipartUpload",
+            Outcome::of(created),
+        ));
+        // Aborting an unknown upload is not found when allowed
+        let aborted = with_retry("Probing s3:AbortMultipartUpload", || async {
+            self.client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(&key)
+                .upload_id(upload_id.as_deref().unwrap_or("lernspark-preflight"))
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk("AbortMultipartUpload", e))
+        })
+        .await;
+        let outcome = match aborted {
+            Err(AwsError::NotFound(_)) if upload_id.is_none() => Outcome::Allowed,
+            aborted => Outcome::of(aborted),
+        };
+        checks.push(Check::new(
+            Action::AbortMultipartUpload,
+            "AbortMultipartUpload",
+            outcome,
+        ));
+
+        let deleted = with_retry("Probing s3:DeleteObject", || async {
+            self.client
+                .delete_object()
+                .bucket(bucket)
+                .key(&key)
+                .send()
+                .await
+                .map_err(|e| AwsError::from_sdk("DeleteObject", e))
+        })
+        .await;
+        checks.push(Check::new(
+            Action::DeleteObject,
+            "DeleteObject",
+            Outcome::of(deleted),
+        ));
+
+        // Creating a bucket you already own changes nothing. Only that answer
+        // shows the permission, another owner's bucket or a pending operation
+        // tell nothing about it
+        let region = self.client.config().region().map(|r| r.to_string());
+        let recreated = with_retry("Probing s3:CreateBucket", || async {
+            let mut request = self.client.create_bucket().bucket(bucket);
+            if let Some(region) = region.as_deref().filter(|r| *r != "us-east-1") {
+                request = request.create_bucket_configuration(
+                    CreateBucketConfiguration::builder()
+                        .location_constraint(BucketLocationConstraint::from(region))
+                        .build(),
+                );
+            }
+            match request.send().await {
+                Ok(_) => Ok(()),
+                Err(e) if e.code() == Some("BucketAlreadyOwnedByYou") => Ok(()),
+                Err(e) => Err(AwsError::from_sdk("CreateBucket", e)),
+            }
+        })
+        .await;
+        checks.push(Check::new(
+            Action::CreateBucket,
+            "CreateBucket",
+            Outcome::of(recreated),
+        ));
+        checks
     }
 }
 
@@ -335,6 +1549,72 @@ pub async fn delete_objects_and_bucket(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_s3_settings_from_file_and_env() {
+        let file: S3Settings = serde_json::from_str(
+            r#"{"endpoint_url": "http://localhost:9000", "force_path_style": true,
+                "access_key_id": "minio", "secret_access_key": "minio123"}"#,
+        )
+        .unwrap();
+        let env = |name: &str| match name {
+            "AWS_ENDPOINT_URL" => Some("http://localstack:4566".to_string()),
+            "AWS_REGION" => Some("eu-west-1".to_string()),
+            "LERNSPARK_S3_BUCKET" => Some("lernspark-raw".to_string()),
+            _ => None,
+        };
+        let settings = file.with_env(env).unwrap();
+        assert_eq!(
+            settings.endpoint_url.as_deref(),
+            Some("http://localstack:4566")
+        );
+        assert_eq!(settings.region.as_deref(), Some("eu-west-1"));
+        assert_eq!(settings.bucket.as_deref(), Some("lernspark-raw"));
+        assert!(settings.force_path_style);
+        assert!(settings.has_static_credentials());
+
+        let half = |name: &str| (name == "AWS_ACCESS_KEY_ID").then(|| "id".to_string());
+        assert!(S3Settings::default().with_env(half).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_errors_are_classified_and_retried() {
+        let throttled = AwsError::classify(Some("SlowDown"), Some(503), "slow".to_string());
+        assert_eq!(throttled, AwsError::Throttling("slow".to_string()));
+        assert!(matches!(
+            AwsError::classify(Some("AccessDenied"), Some(403), String::new()),
+            AwsError::Permission(_)
+        ));
+        assert!(matches!(
+            AwsError::classify(None, Some(404), String::new()),
+            AwsError::NotFound(_)
+        ));
+        assert!(matches!(
+            AwsError::classify(Some("BucketAlreadyExists"), Some(409), String::new()),
+            AwsError::BucketConflict(_)
+        ));
+        assert!(throttled.to_string().contains("--concurrency"));
+
+        let calls = std::sync::atomic::AtomicU32::new(0);
+        let result = with_retry("test", || async {
+            match calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 | 1 => Err(AwsError::Network("reset".to_string())),
+                _ => Ok("done"),
+            }
+        })
+        .await;
+        assert_eq!(result, Ok("done"));
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        calls.store(0, std::sync::atomic::Ordering::SeqCst);
+        let result: Result<(), AwsError> = with_retry("test", || async {
+            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(AwsError::Permission("denied".to_string()))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_s3_upload() {
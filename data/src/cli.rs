@@ -0,0 +1,98 @@
+//! Tiny command line parser for the `data` subcommands.
+//! Arguments are either positional or `--flag value` pairs,
+//! a `--flag` with no value is treated as a switch.
+//! Known switches never take a value, so they can precede positionals.
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub positional: Vec<String>,
+    flags: HashMap<String, Option<String>>,
+}
+
+impl Args {
+    /// Parse everything after the subcommand name, `switches` are the flags
+    /// that never take a value
+    pub fn parse<I: IntoIterator<Item = String>>(args: I, switches: &[&str]) -> Args {
+        let mut parsed = Args::default();
+        let mut iter = args.into_iter().peekable();
+
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(flag) => {
+                    let value = match iter.peek() {
+                        _ if switches.contains(&flag) => None,
+                        Some(next) if !next.starts_with("--") => iter.next(),
+                        _ => None,
+                    };
+                    parsed.flags.insert(flag.to_string(), value);
+                }
+                None => parsed.positional.push(arg),
+            }
+        }
+
+        parsed
+    }
+
+    /// True when `--name` was passed, with or without a value
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    /// The raw value of `--name value`
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).and_then(|v| v.as_deref())
+    }
+
+    /// Parse the value of `--name`, falling back to `default` when absent
+    pub fn value_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.value(name) {
+            Some(raw) => raw
+                .parse::<T>()
+                .map_err(|_| format!("Invalid value '{}' for --{}", raw, name)),
+            None => Ok(default),
+        }
+    }
+
+    /// The positional argument at `index`, or an error naming what is missing
+    pub fn required(&self, index: usize, what: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(|s| s.as_str())
+            .ok_or_else(|| format!("Missing required argument <{}>", what))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = Args::parse(
+            ["Users", "--events", "500", "--dry-run", "--out", "tmp"]
+                .iter()
+                .map(|s| s.to_string()),
+            &[],
+        );
+
+        assert_eq!(args.required(0, "table").unwrap(), "Users");
+        assert_eq!(args.value_or("events", 10usize).unwrap(), 500);
+        assert_eq!(args.value("out"), Some("tmp"));
+        assert!(args.has("dry-run"));
+        assert!(args.value("dry-run").is_none());
+        assert!(args.required(1, "other").is_err());
+
+        // Switches before positionals leave them alone
+        let args = Args::parse(
+            ["--dry-run", "dir", "--json", "s3://b/p", "--rows", "5"]
+                .iter()
+                .map(|s| s.to_string()),
+            &["dry-run", "json"],
+        );
+        assert_eq!(args.positional, vec!["dir", "s3://b/p"]);
+        assert!(args.has("dry-run") && args.has("json"));
+        assert_eq!(args.value("rows"), Some("5"));
+    }
+}
@@ -0,0 +1,477 @@
+//! Rust source for typed structs mirroring data.sql, each with serde derives
+//! and conversions to and from Arrow `RecordBatch`es. `build.rs` writes the
+//! module for data.sql into `OUT_DIR`, so changing data.sql turns stale
+//! field accesses into compile errors. `data export --format rust` prints
+//! the same code for other crates.
+use crate::sql::{Column, DataType, Table};
+
+/// Schema compiled into the tests so the generated code is always built
+#[allow(dead_code)]
+pub const EXAMPLE_SQL: &str = "CREATE TABLE Orders (
+    ID INT AUTO_INCREMENT PRIMARY KEY,
+    Status ENUM('new', 'in-transit', 'done') NOT NULL,
+    CustomerRef UUID,
+    Total DOUBLE,
+    Weight FLOAT,
+    Items BIGINT NOT NULL,
+    Placed DATE,
+    Note VARCHAR(40),
+    type TEXT,
+    Paid BOOLEAN NOT NULL
+);";
+
+const RUST_KEYWORDS: &str = "as async await break const continue dyn else enum extern \
+    false fn for if impl in let loop match mod move mut pub ref return static struct \
+    trait true type unsafe use where while abstract become box do final macro override priv \
+    try typeof unsized virtual yield";
+
+/// Keywords that cannot be raw identifiers either, these get a `_` suffix
+const PATH_KEYWORDS: &str = "crate self Self super";
+
+/// Split an identifier into lowercase words at `_`, `-`, spaces and case changes
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let boundary = i > 0
+            && c.is_uppercase()
+            && (chars[i - 1].is_lowercase()
+                || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+        if boundary && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn escape_keyword(ident: String) -> String {
+    if PATH_KEYWORDS.split_whitespace().any(|k| k == ident) {
+        format!("{}_", ident)
+    } else if RUST_KEYWORDS.split_whitespace().any(|k| k == ident) {
+        format!("r#{}", ident)
+    } else if ident.starts_with(|c: char| c.is_ascii_digit()) || ident.is_empty() {
+        format!("_{}", ident)
+    } else {
+        ident
+    }
+}
+
+/// `snake_case` field name for a column
+pub fn field_name(column: &str) -> String {
+    escape_keyword(words(column).join("_"))
+}
+
+/// `PascalCase` type name for a table or enum value
+pub fn type_name(name: &str) -> String {
+    let pascal: String = words(name)
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    if pascal.starts_with(|c: char| c.is_ascii_digit()) || pascal.is_empty() {
+        format!("V{}", pascal)
+    } else if pascal == "Self" {
+        "Self_".to_string()
+    } else {
+        pascal
+    }
+}
+
+/// The first two originals that map to the same generated name
+fn collision<'a>(
+    names: impl Iterator<Item = (&'a str, String)>,
+) -> Option<(&'a str, &'a str, String)> {
+    let mut seen: Vec<(&str, String)> = Vec::new();
+    for (original, generated) in names {
+        if let Some((first, _)) = seen.iter().find(|(_, g)| *g == generated) {
+            return Some((first, original, generated));
+        }
+        seen.push((original, generated));
+    }
+    None
+}
+
+/// Columns and ENUM values must keep distinct names once sanitized, or the
+/// generated code does not compile
+pub fn check_names(table: &Table) -> Result<(), String> {
+    let fields = table
+        .columns
+        .iter()
+        .map(|col| (col.name.as_str(), field_name(&col.name)));
+    if let Some((a, b, field)) = collision(fields) {
+        return Err(format!(
+            "Columns '{}' and '{}' of {} both become the field `{}`",
+            a, b, table.name, field
+        ));
+    }
+    for col in &table.columns {
+        if let DataType::Enum(values) = &col.data_type {
+            let variants = values.iter().map(|v| (v.as_str(), type_name(v)));
+            if let Some((a, b, variant)) = collision(variants) {
+                return Err(format!(
+                    "ENUM values '{}' and '{}' of {}.{} both become the variant `{}`",
+                    a, b, table.name, col.name, variant
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn enum_type_name(table: &Table, col: &Column) -> String {
+    format!("{}{}", type_name(&table.flat_name()), type_name(&col.name))
+}
+
+/// Rust type, Arrow array and Arrow data type for a column
+fn rust_types(table: &Table, col: &Column) -> (String, &'static str, &'static str) {
+    match &col.data_type {
+        DataType::Int(_) => ("i32".to_string(), "Int32Array", "Int32"),
+        DataType::BigInt(_) => ("i64".to_string(), "Int64Array", "Int64"),
+        DataType::Float(_) => ("f32".to_string(), "Float32Array", "Float32"),
+        DataType::Double(_) => ("f64".to_string(), "Float64Array", "Float64"),
+        DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_) => {
+            ("String".to_string(), "StringArray", "Utf8")
+        }
+        DataType::Enum(_) => (enum_type_name(table, col), "StringArray", "Utf8"),
+        DataType::DateTime(_) => ("chrono::NaiveDate".to_string(), "Date32Array", "Date32"),
+        DataType::Boolean(_) => ("bool".to_string(), "BooleanArray", "Boolean"),
+    }
+}
+
+fn is_nullable(col: &Column) -> bool {
+    !(col.is_not_null() || col.is_primary_key())
+}
+
+fn enum_definition(table: &Table, col: &Column, values: &[String]) -> String {
+    let name = enum_type_name(table, col);
+    let variants: Vec<(String, &String)> = values.iter().map(|v| (type_name(v), v)).collect();
+    let mut out = format!(
+        "/// Values of `{}.{}`\n#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]\npub enum {} {{\n",
+        table.name, col.name, name
+    );
+    for (variant, value) in &variants {
+        out.push_str(&format!(
+            "    #[serde(rename = {:?})]\n    {},\n",
+            value, variant
+        ));
+    }
+    out.push_str(&format!(
+        "}}\n\nimpl {} {{\n    pub fn as_str(&self) -> &'static str {{\n        match self {{\n",
+        name
+    ));
+    for (variant, value) in &variants {
+        out.push_str(&format!(
+            "            {}::{} => {:?},\n",
+            name, variant, value
+        ));
+    }
+    out.push_str(&format!(
+        "        }}\n    }}\n}}\n\nimpl std::str::FromStr for {} {{\n    type Err = String;\n\n    fn from_str(s: &str) -> Result<Self, Self::Err> {{\n        match s {{\n",
+        name
+    ));
+    for (variant, value) in &variants {
+        out.push_str(&format!(
+            "            {:?} => Ok({}::{}),\n",
+            value, name, variant
+        ));
+    }
+    out.push_str(&format!(
+        "            other => Err(format!(\"Unknown {}.{} value '{{}}'\", other)),\n        }}\n    }}\n}}\n\n",
+        table.name, col.name
+    ));
+    out
+}
+
+/// Expression turning `row.<field>` into the Arrow builder's `Option` item
+fn to_arrow_item(col: &Column, field: &str) -> String {
+    let nullable = is_nullable(col);
+    match (&col.data_type, nullable) {
+        (DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_), true) => {
+            format!("row.{}.as_deref()", field)
+        }
+        (DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_), false) => {
+            format!("Some(row.{}.as_str())", field)
+        }
+        (DataType::Enum(_), true) => format!("row.{}.map(|v| v.as_str())", field),
+        (DataType::Enum(_), false) => format!("Some(row.{}.as_str())", field),
+        (DataType::DateTime(_), true) => format!("row.{}.map(days_from_date)", field),
+        (DataType::DateTime(_), false) => format!("Some(days_from_date(row.{}))", field),
+        (_, true) => format!("row.{}", field),
+        (_, false) => format!("Some(row.{})", field),
+    }
+}
+
+/// Expression reading row `i` of the array bound to `var` as the field type
+fn from_arrow_value(table: &Table, col: &Column, var: &str) -> String {
+    match &col.data_type {
+        DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_) => {
+            format!("{}.value(i).to_string()", var)
+        }
+        DataType::Enum(_) => format!(
+            "{}.value(i).parse::<{}>()?",
+            var,
+            enum_type_name(table, col)
+        ),
+        DataType::DateTime(_) => format!("date_from_days({}.value(i))", var),
+        _ => format!("{}.value(i)", var),
+    }
+}
+
+fn struct_definition(table: &Table) -> String {
+    let name = type_name(&table.flat_name());
+    let fields: Vec<(String, &Column)> = table
+        .columns
+        .iter()
+        .map(|col| (field_name(&col.name), col))
+        .collect();
+
+    let mut out = format!(
+        "/// A row of `{}`\n#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]\npub struct {} {{\n",
+        table.qualified_name(),
+        name
+    );
+    for (field, col) in &fields {
+        let (rust_type, _, _) = rust_types(table, col);
+        let rust_type = if is_nullable(col) {
+            format!("Option<{}>", rust_type)
+        } else {
+            rust_type
+        };
+        out.push_str(&format!(
+            "    #[serde(rename = {:?})]\n    pub {}: {},\n",
+            col.name, field, rust_type
+        ));
+    }
+    out.push_str("}\n\n");
+
+    out.push_str(&format!(
+        "impl {} {{\n    pub const TABLE: &'static str = {:?};\n\n",
+        name,
+        table.qualified_name()
+    ));
+    out.push_str("    /// The Arrow schema `data` writes for this table\n    pub fn schema() -> Schema {\n        Schema::new(vec![\n");
+    for (_, col) in &fields {
+        let (_, _, arrow_type) = rust_types(table, col);
+        out.push_str(&format!(
+            "            Field::new({:?}, ArrowType::{}, true),\n",
+            col.name, arrow_type
+        ));
+    }
+    out.push_str("        ])\n    }\n\n");
+
+    out.push_str(
+        "    pub fn to_record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {\n        let columns: Vec<ArrayRef> = vec![\n",
+    );
+    for (field, col) in &fields {
+        let (_, array, _) = rust_types(table, col);
+        out.push_str(&format!(
+            "            Arc::new({}::from(\n                rows.iter().map(|row| {}).collect::<Vec<_>>(),\n            )),\n",
+            array,
+            to_arrow_item(col, field)
+        ));
+    }
+    out.push_str(
+        "        ];\n        RecordBatch::try_new(Arc::new(Self::schema()), columns)\n    }\n\n",
+    );
+
+    out.push_str(
+        "    /// Columns are matched by name, so extra columns and a different order are fine\n    pub fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Self>, String> {\n",
+    );
+    for (i, (_, col)) in fields.iter().enumerate() {
+        let (_, array, _) = rust_types(table, col);
+        out.push_str(&format!(
+            "        let c{} = column::<{}>(batch, {:?})?;\n",
+            i, array, col.name
+        ));
+    }
+    out.push_str("        let mut rows = Vec::with_capacity(batch.num_rows());\n        for i in 0..batch.num_rows() {\n            rows.push(Self {\n");
+    for (i, (field, col)) in fields.iter().enumerate() {
+        let var = format!("c{}", i);
+        let value = from_arrow_value(table, col, &var);
+        if is_nullable(col) {
+            out.push_str(&format!(
+                "                {}: if {}.is_null(i) {{ None }} else {{ Some({}) }},\n",
+                field, var, value
+            ));
+        } else {
+            out.push_str(&format!(
+                "                {}: if {}.is_null(i) {{\n                    return Err(null_error({:?}, i));\n                }} else {{\n                    {}\n                }},\n",
+                field, var, col.name, value
+            ));
+        }
+    }
+    out.push_str("            });\n        }\n        Ok(rows)\n    }\n}\n\n");
+    out
+}
+
+const RUNTIME: &str = "use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, BooleanArray, Date32Array, Float32Array, Float64Array, Int32Array,
+    Int64Array, StringArray,
+};
+use arrow::datatypes::{DataType as ArrowType, Field, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+fn column<'a, A: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a A, String> {
+    let index = batch
+        .schema()
+        .index_of(name)
+        .map_err(|_| format!(\"Missing column '{}'\", name))?;
+    let array = batch.column(index);
+    array
+        .as_any()
+        .downcast_ref::<A>()
+        .ok_or_else(|| format!(\"Column '{}' has unexpected type {:?}\", name, array.data_type()))
+}
+
+fn null_error(column: &str, row: usize) -> String {
+    format!(\"Null in NOT NULL column '{}' at row {}\", column, row)
+}
+
+fn epoch() -> chrono::NaiveDate {
+    chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+fn date_from_days(days: i32) -> chrono::NaiveDate {
+    epoch() + chrono::Duration::days(days as i64)
+}
+
+fn days_from_date(date: chrono::NaiveDate) -> i32 {
+    (date - epoch()).num_days() as i32
+}
+";
+
+/// A self-contained Rust module with a struct per table, an error when
+/// sanitized names collide
+pub fn rust_module(tables: &[Table]) -> Result<String, String> {
+    let mut out = String::from("// Generated from data.sql, do not edit\n");
+    if tables.is_empty() {
+        return Ok(out);
+    }
+    out.push_str(RUNTIME);
+    out.push('\n');
+    for table in tables {
+        check_names(table)?;
+        for col in &table.columns {
+            if let DataType::Enum(values) = &col.data_type {
+                out.push_str(&enum_definition(table, col, values));
+            }
+        }
+        out.push_str(&struct_definition(table));
+    }
+    Ok(out.trim_end().to_string() + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    mod example {
+        include!(concat!(env!("OUT_DIR"), "/example_tables.rs"));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(field_name("CustomerRef"), "customer_ref");
+        assert_eq!(field_name("type"), "r#type");
+        assert_eq!(field_name("crate"), "crate_");
+        assert_eq!(field_name("super"), "super_");
+        assert_eq!(field_name("Self"), "self_");
+        assert_eq!(type_name("self"), "Self_");
+
+        let items =
+            crate::sql::parse_create_table("CREATE TABLE Items (\"Item Id\" INT, \"Item-Id\" INT)");
+        let error = rust_module(&[items]).unwrap_err();
+        assert!(error.contains("`item_id`"), "{}", error);
+        let states =
+            crate::sql::parse_create_table("CREATE TABLE T (S ENUM('in-transit', 'in_transit'))");
+        assert!(check_names(&states).unwrap_err().contains("`InTransit`"));
+
+        // Same table names in two namespaces stay apart
+        let users = crate::sql::parse_schema(
+            "CREATE TABLE a.users (ID INT); CREATE TABLE b.users (ID INT);",
+        )
+        .unwrap()
+        .tables;
+        let module = rust_module(&users).unwrap();
+        assert!(module.contains("pub struct AUsers {") && module.contains("pub struct BUsers {"));
+        assert!(module.contains("pub const TABLE: &'static str = \"b.users\";"));
+        assert_eq!(type_name("in-transit"), "InTransit");
+        assert_eq!(type_name("player_stats"), "PlayerStats");
+    }
+
+    #[test]
+    fn test_generated_structs_round_trip() {
+        use example::{Orders, OrdersStatus};
+
+        let rows = vec![
+            Orders {
+                id: 1,
+                status: OrdersStatus::InTransit,
+                customer_ref: Some("0b7c6e42-8d0c-4a5e-9f43-3f1d9a3c2b10".to_string()),
+                total: Some(12.5),
+                weight: None,
+                items: 3,
+                placed: chrono::NaiveDate::from_ymd_opt(2024, 2, 29),
+                note: None,
+                r#type: Some("gift".to_string()),
+                paid: true,
+            },
+            Orders {
+                id: 2,
+                status: OrdersStatus::New,
+                customer_ref: None,
+                total: None,
+                weight: Some(0.25),
+                items: 1,
+                placed: None,
+                note: Some("leave at door".to_string()),
+                r#type: None,
+                paid: false,
+            },
+        ];
+
+        let batch = Orders::to_record_batch(&rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(Orders::from_record_batch(&batch).unwrap(), rows);
+
+        let json = serde_json::to_value(&rows[0]).unwrap();
+        assert_eq!(json["Status"], "in-transit");
+        assert_eq!(json["type"], "gift");
+        let parsed: Orders = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, rows[0]);
+
+        // Files written by the generator read back into the structs
+        let table = crate::sql::parse_create_table(EXAMPLE_SQL);
+        let generated = crate::model::generate_table_rows(&table, 20).unwrap();
+        let dir = tempdir::TempDir::new("codegen").unwrap();
+        let path = dir.path().join("Orders.parquet");
+        crate::model::write_rows(&path, &table, &generated, crate::model::FileFormat::Parquet)
+            .unwrap();
+        let mut read = 0;
+        crate::infer::for_each_batch(&path, &crate::infer::InferOptions::default(), |batch| {
+            read += Orders::from_record_batch(batch).unwrap().len();
+        })
+        .unwrap();
+        assert_eq!(read, 20);
+    }
+}
@@ -0,0 +1,435 @@
+//! Write the tables from data.sql back out as DDL for the engines our
+//! pipeline reads from: Spark SQL, PostgreSQL, DuckDB and Hive.
+use std::str::FromStr;
+
+use crate::sql::{quote_identifier, quote_qualified, Column, DataType, Index, Schema, Table, View};
+
+/// Target SQL dialect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Spark,
+    Postgres,
+    DuckDb,
+    Hive,
+}
+
+impl Dialect {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dialect::Spark => "spark",
+            Dialect::Postgres => "postgres",
+            Dialect::DuckDb => "duckdb",
+            Dialect::Hive => "hive",
+        }
+    }
+
+    /// The column type in this dialect
+    pub fn column_type(&self, data_type: &DataType) -> String {
+        let name = match (self, data_type) {
+            (Dialect::Postgres, DataType::Int(_)) => "INTEGER",
+            (Dialect::DuckDb, DataType::Int(_)) => "INTEGER",
+            (_, DataType::Int(_)) => "INT",
+            (_, DataType::BigInt(_)) => "BIGINT",
+            (Dialect::Postgres, DataType::Float(_)) => "REAL",
+            (_, DataType::Float(_)) => "FLOAT",
+            (Dialect::Postgres, DataType::Double(_)) => "DOUBLE PRECISION",
+            (_, DataType::Double(_)) => "DOUBLE",
+            (Dialect::Postgres, DataType::String(_)) => "TEXT",
+            (Dialect::DuckDb, DataType::String(_) | DataType::VarChar(_)) => "VARCHAR",
+            (Dialect::Spark, DataType::VarChar(_)) => "STRING",
+            (_, DataType::VarChar(n)) => return format!("VARCHAR({})", n),
+            (Dialect::Postgres | Dialect::DuckDb, DataType::UUID(_)) => "UUID",
+            (Dialect::DuckDb, DataType::Enum(_)) => return data_type.to_string(),
+            (Dialect::Postgres, DataType::Enum(_)) => "TEXT",
+            (_, DataType::String(_) | DataType::UUID(_) | DataType::Enum(_)) => "STRING",
+            (_, DataType::DateTime(_)) => "DATE",
+            (_, DataType::Boolean(_)) => "BOOLEAN",
+        };
+        name.to_string()
+    }
+
+    /// Spark and Hive read Parquet files in place, the others own their data
+    fn is_lake(&self) -> bool {
+        matches!(self, Dialect::Spark | Dialect::Hive)
+    }
+
+    /// Spark and Hive quote names with backticks, the others like ANSI SQL
+    fn quote(&self) -> char {
+        if self.is_lake() {
+            '`'
+        } else {
+            '"'
+        }
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spark" | "sparksql" | "spark-sql" => Ok(Dialect::Spark),
+            "postgres" | "postgresql" | "pg" => Ok(Dialect::Postgres),
+            "duckdb" => Ok(Dialect::DuckDb),
+            "hive" => Ok(Dialect::Hive),
+            other => Err(format!("Unknown SQL dialect '{}'", other)),
+        }
+    }
+}
+
+/// Where the data lives and how it is partitioned
+#[derive(Debug, Clone, Default)]
+pub struct DdlOptions {
+    /// Base directory or URI, each table lives in `<location>/<table>`, or
+    /// `<location>/<namespace>/<table>` like the files `data` writes
+    pub location: Option<String>,
+    /// Partition columns, added as strings when the table does not have them
+    /// (such as the `dt` partitions written by `evolve`)
+    pub partitioned_by: Vec<String>,
+}
+
+impl DdlOptions {
+    fn table_location(&self, table: &Table) -> Option<String> {
+        self.location.as_ref().map(|base| {
+            let mut parts = vec![base.trim_end_matches('/').to_string()];
+            parts.extend(table.namespace.iter().cloned());
+            parts.push(table.name.clone());
+            parts.join("/")
+        })
+    }
+}
+
+/// Parents before the tables that reference them, so foreign keys resolve.
+/// An ambiguous parent name is an error.
+pub fn creation_order(tables: &[Table]) -> Result<Vec<&Table>, String> {
+    let mut parents: Vec<Vec<usize>> = Vec::new();
+    for table in tables {
+        let mut of_table = Vec::new();
+        for (parent, _) in table.columns.iter().filter_map(|col| col.references()) {
+            of_table.extend(Table::position(tables, &parent)?);
+        }
+        parents.push(of_table);
+    }
+    let mut ordered: Vec<usize> = Vec::new();
+    let mut remaining: Vec<usize> = (0..tables.len()).collect();
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|&i| {
+            parents[i]
+                .iter()
+                .all(|parent| *parent == i || ordered.contains(parent))
+        });
+        // A reference cycle cannot be ordered, keep the declared order
+        ordered.push(remaining.remove(ready.unwrap_or(0)));
+    }
+    Ok(ordered.into_iter().map(|i| &tables[i]).collect())
+}
+
+fn column_definition(col: &Column, dialect: Dialect) -> String {
+    let name = quote_identifier(&col.name, dialect.quote());
+    let mut definition = format!("{} {}", name, dialect.column_type(&col.data_type));
+    let auto_increment = col
+        .constraints
+        .iter()
+        .any(|c| c.eq_ignore_ascii_case("AUTO_INCREMENT"));
+    match dialect {
+        Dialect::Postgres | Dialect::DuckDb => {
+            if auto_increment && dialect == Dialect::Postgres {
+                definition.push_str(" GENERATED BY DEFAULT AS IDENTITY");
+            }
+            if col.is_primary_key() {
+                definition.push_str(" PRIMARY KEY");
+            } else {
+                if col.is_not_null() {
+                    definition.push_str(" NOT NULL");
+                }
+                if col.is_unique() {
+                    definition.push_str(" UNIQUE");
+                }
+            }
+            if let (Dialect::Postgres, DataType::Enum(values)) = (dialect, &col.data_type) {
+                let quoted: Vec<String> = values
+                    .iter()
+                    .map(|v| format!("'{}'", v.replace('\'', "''")))
+                    .collect();
+                definition.push_str(&format!(" CHECK ({} IN ({}))", name, quoted.join(", ")));
+            }
+            if let Some((table, column)) = col.references() {
+                definition.push_str(&format!(
+                    " REFERENCES {}({})",
+                    quote_qualified(&table, '"'),
+                    quote_identifier(&column, '"')
+                ));
+            }
+        }
+        Dialect::Spark => {
+            if col.is_not_null() || col.is_primary_key() {
+                definition.push_str(" NOT NULL");
+            }
+        }
+        // Hive only knows informational constraints, leave them out
+        Dialect::Hive => {}
+    }
+    definition
+}
+
+/// `CREATE TABLE` (or `CREATE VIEW` for DuckDB over existing files) for one table
+pub fn table_ddl(table: &Table, dialect: Dialect, options: &DdlOptions) -> String {
+    let location = options.table_location(table);
+    let table_name = table.sql_name(dialect.quote());
+
+    if dialect == Dialect::DuckDb {
+        if let Some(location) = &location {
+            return format!(
+                "CREATE VIEW {} AS SELECT * FROM read_parquet('{}/**/*.parquet', hive_partitioning = true);",
+                table_name, location
+            );
+        }
+    }
+
+    let is_partition = |col: &Column| {
+        options
+            .partitioned_by
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(&col.name))
+    };
+    let mut definitions: Vec<String> = table
+        .columns
+        .iter()
+        // Hive declares partition columns only in PARTITIONED BY
+        .filter(|col| !(dialect == Dialect::Hive && is_partition(col)))
+        .map(|col| column_definition(col, dialect))
+        .collect();
+    if dialect == Dialect::Spark {
+        for partition in &options.partitioned_by {
+            if table.column_index(partition).is_none() {
+                definitions.push(format!(
+                    "{} STRING",
+                    quote_identifier(partition, dialect.quote())
+                ));
+            }
+        }
+    }
+
+    let create = match dialect {
+        Dialect::Hive => "CREATE EXTERNAL TABLE IF NOT EXISTS",
+        Dialect::Spark => "CREATE TABLE IF NOT EXISTS",
+        _ => "CREATE TABLE",
+    };
+    let mut ddl = format!(
+        "{} {} (\n    {}\n)",
+        create,
+        table_name,
+        definitions.join(",\n    ")
+    );
+
+    match dialect {
+        Dialect::Spark => {
+            ddl.push_str("\nUSING parquet");
+            if !options.partitioned_by.is_empty() {
+                ddl.push_str(&format!(
+                    "\nPARTITIONED BY ({})",
+                    options
+                        .partitioned_by
+                        .iter()
+                        .map(|p| quote_identifier(p, dialect.quote()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        Dialect::Hive => {
+            if !options.partitioned_by.is_empty() {
+                let partitions: Vec<String> = options
+                    .partitioned_by
+                    .iter()
+                    .map(|p| match table.column_index(p) {
+                        Some(i) => format!(
+                            "{} {}",
+                            quote_identifier(&table.columns[i].name, dialect.quote()),
+                            dialect.column_type(&table.columns[i].data_type)
+                        ),
+                        None => format!("{} STRING", quote_identifier(p, dialect.quote())),
+                    })
+                    .collect();
+                ddl.push_str(&format!("\nPARTITIONED BY ({})", partitions.join(", ")));
+            }
+            ddl.push_str("\nSTORED AS PARQUET");
+        }
+        _ => {}
+    }
+    if dialect.is_lake() {
+        if let Some(location) = &location {
+            ddl.push_str(&format!("\nLOCATION '{}'", location));
+        }
+    }
+    ddl.push(';');
+
+    if dialect.is_lake() && !options.partitioned_by.is_empty() && location.is_some() {
+        ddl.push_str(&format!("\nMSCK REPAIR TABLE {};", table_name));
+    }
+    ddl
+}
+
+/// `CREATE INDEX`, for the dialects whose tables hold their own data. Spark
+/// and Hive have no indexes, and DuckDB cannot index a view over files.
+pub fn index_ddl(index: &Index, dialect: Dialect, options: &DdlOptions) -> Option<String> {
+    match dialect {
+        Dialect::Spark | Dialect::Hive => return None,
+        Dialect::DuckDb if options.location.is_some() => return None,
+        _ => {}
+    }
+    let columns: Vec<String> = index
+        .columns
+        .iter()
+        .map(|c| {
+            let name = quote_identifier(&c.name, '"');
+            if c.descending {
+                format!("{} DESC", name)
+            } else {
+                name
+            }
+        })
+        .collect();
+    // DuckDB only has its default ART index
+    let using = match (&index.method, dialect) {
+        (Some(method), Dialect::Postgres) => format!(" USING {}", method),
+        _ => String::new(),
+    };
+    Some(format!(
+        "CREATE {}INDEX {} ON {}{} ({});",
+        if index.unique { "UNIQUE " } else { "" },
+        quote_identifier(&index.name, '"'),
+        quote_qualified(&index.table, '"'),
+        using,
+        columns.join(", ")
+    ))
+}
+
+/// `CREATE VIEW` with the query as written in data.sql
+pub fn view_ddl(view: &View, dialect: Dialect) -> String {
+    let create = match dialect {
+        Dialect::Spark | Dialect::Hive | Dialect::DuckDb => "CREATE OR REPLACE VIEW",
+        Dialect::Postgres => "CREATE VIEW",
+    };
+    format!(
+        "{} {} AS\n{};",
+        create,
+        quote_qualified(&view.name, dialect.quote()),
+        view.query
+    )
+}
+
+/// DDL for every table, parents first, then indexes and views
+pub fn emit_ddl(schema: &Schema, dialect: Dialect, options: &DdlOptions) -> Result<String, String> {
+    let mut out = format!("-- Generated from data.sql for {}\n", dialect.as_str());
+    if dialect == Dialect::Postgres && !options.partitioned_by.is_empty() {
+        out.push_str("-- Partitioning is not applied, PostgreSQL tables own their data\n");
+    }
+    for table in creation_order(&schema.tables)? {
+        out.push('\n');
+        out.push_str(&table_ddl(table, dialect, options));
+        out.push('\n');
+    }
+    let indexes: Vec<String> = schema
+        .indexes
+        .iter()
+        .filter_map(|index| index_ddl(index, dialect, options))
+        .collect();
+    if !indexes.is_empty() {
+        out.push('\n');
+        out.push_str(&indexes.join("\n"));
+        out.push('\n');
+    }
+    for view in &schema.views {
+        out.push('\n');
+        out.push_str(&view_ddl(view, dialect));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql;
+
+    fn schema() -> Schema {
+        sql::parse_schema(
+            "CREATE TABLE Players (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                TeamID INT NOT NULL REFERENCES Teams(ID),
+                Nick VARCHAR(20) UNIQUE
+            );
+            CREATE TABLE Teams (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                Founded DATE
+            );
+            CREATE INDEX players_team ON Players USING hash (TeamID);
+            CREATE VIEW TeamSizes AS SELECT TeamID, COUNT(*) AS Players FROM Players GROUP BY TeamID;",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_spark_and_hive_ddl_point_at_partitioned_data() {
+        let options = DdlOptions {
+            location: Some("s3://lake/raw/".to_string()),
+            partitioned_by: vec!["dt".to_string()],
+        };
+        let tables = schema().tables;
+        assert_eq!(
+            table_ddl(&tables[1], Dialect::Spark, &options),
+            "CREATE TABLE IF NOT EXISTS Teams (
+    ID INT NOT NULL,
+    Founded DATE,
+    dt STRING
+)
+USING parquet
+PARTITIONED BY (dt)
+LOCATION 's3://lake/raw/Teams';
+MSCK REPAIR TABLE Teams;"
+        );
+        let hive = table_ddl(&tables[0], Dialect::Hive, &options);
+        assert!(hive.starts_with("CREATE EXTERNAL TABLE IF NOT EXISTS Players (\n    ID INT,"));
+        assert!(hive.contains("Nick VARCHAR(20)\n)\nPARTITIONED BY (dt STRING)\nSTORED AS PARQUET"));
+    }
+
+    #[test]
+    fn test_postgres_creates_parents_first() {
+        let ddl = emit_ddl(&schema(), Dialect::Postgres, &DdlOptions::default()).unwrap();
+        let teams = ddl.find("CREATE TABLE Teams").unwrap();
+        let players = ddl.find("CREATE TABLE Players").unwrap();
+        assert!(teams < players);
+        assert!(ddl.contains("ID INTEGER GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY"));
+        assert!(ddl.contains("TeamID INTEGER NOT NULL REFERENCES Teams(ID)"));
+        assert!(ddl.contains("Nick VARCHAR(20) UNIQUE"));
+        assert!(ddl.contains("CREATE INDEX players_team ON Players USING hash (TeamID);"));
+        assert!(ddl.contains(
+            "CREATE VIEW TeamSizes AS\nSELECT TeamID, COUNT(*) AS Players FROM Players GROUP BY TeamID;"
+        ));
+
+        let duckdb = emit_ddl(
+            &schema(),
+            Dialect::DuckDb,
+            &DdlOptions {
+                location: Some("data".to_string()),
+                ..DdlOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(duckdb.contains(
+            "CREATE VIEW Teams AS SELECT * FROM read_parquet('data/Teams/**/*.parquet', hive_partitioning = true);"
+        ));
+        assert!(!duckdb.contains("CREATE INDEX"));
+
+        // A parent named bare must be unique across namespaces
+        let ambiguous = sql::parse_schema(
+            "CREATE TABLE a.Teams (ID INT PRIMARY KEY);
+            CREATE TABLE b.Teams (ID INT PRIMARY KEY);
+            CREATE TABLE Players (TeamID INT REFERENCES Teams(ID));",
+        )
+        .unwrap();
+        let error = emit_ddl(&ambiguous, Dialect::Postgres, &DdlOptions::default()).unwrap_err();
+        assert!(error.contains("Ambiguous table name 'Teams'"), "{}", error);
+    }
+}
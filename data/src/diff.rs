@@ -0,0 +1,398 @@
+//! Compare two versions of data.sql, such as the backup the wizard keeps and
+//! the file it wrote. Every difference becomes an `ALTER TABLE` (or `CREATE`
+//! / `DROP TABLE`) statement classified like the `evolve` command does, and
+//! the worst classification is the verdict for the Parquet files already
+//! written under the old schema.
+use colored::Colorize;
+use serde_json::json;
+
+use crate::ddl::creation_order;
+use crate::evolution::{classify_change, diff_table, Compatibility};
+use crate::sql::{quote_identifier, quote_qualified, Column, SchemaChange, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    AddTable,
+    DropTable,
+    AddColumn,
+    DropColumn,
+    RenameColumn,
+    ChangeType,
+    AddConstraint,
+    DropConstraint,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::AddTable => "add_table",
+            ChangeKind::DropTable => "drop_table",
+            ChangeKind::AddColumn => "add_column",
+            ChangeKind::DropColumn => "drop_column",
+            ChangeKind::RenameColumn => "rename_column",
+            ChangeKind::ChangeType => "change_type",
+            ChangeKind::AddConstraint => "add_constraint",
+            ChangeKind::DropConstraint => "drop_constraint",
+        }
+    }
+}
+
+/// One difference between the schemas and the statement that migrates it
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub table: String,
+    pub kind: ChangeKind,
+    pub statement: String,
+    pub compatibility: Compatibility,
+    pub reason: String,
+}
+
+/// Column constraints the diff tracks
+#[derive(Debug, Clone, PartialEq)]
+enum Constraint {
+    NotNull,
+    Unique,
+    PrimaryKey,
+    References(String, String),
+}
+
+fn constraints_of(col: &Column) -> Vec<Constraint> {
+    let mut constraints = Vec::new();
+    if col.is_primary_key() {
+        constraints.push(Constraint::PrimaryKey);
+    } else if col.is_unique() {
+        constraints.push(Constraint::Unique);
+    }
+    if col.is_not_null() {
+        constraints.push(Constraint::NotNull);
+    }
+    if let Some((table, column)) = col.references() {
+        constraints.push(Constraint::References(table, column));
+    }
+    constraints
+}
+
+fn add_constraint(table: &str, column: &str, constraint: &Constraint) -> Change {
+    let (name, col) = (quote_qualified(table, '"'), quote_identifier(column, '"'));
+    let (statement, reason) = match constraint {
+        Constraint::NotNull => (
+            format!("ALTER TABLE {} ALTER COLUMN {} SET NOT NULL", name, col),
+            format!("older files may hold nulls in '{}'", column),
+        ),
+        Constraint::Unique => (
+            format!("ALTER TABLE {} ADD UNIQUE ({})", name, col),
+            format!("older files may hold duplicates in '{}'", column),
+        ),
+        Constraint::PrimaryKey => (
+            format!("ALTER TABLE {} ADD PRIMARY KEY ({})", name, col),
+            format!("older files may hold nulls or duplicates in '{}'", column),
+        ),
+        Constraint::References(parent, key) => (
+            format!(
+                "ALTER TABLE {} ADD FOREIGN KEY ({}) REFERENCES {}({})",
+                name,
+                col,
+                quote_qualified(parent, '"'),
+                quote_identifier(key, '"')
+            ),
+            format!("older files may reference rows missing from '{}'", parent),
+        ),
+    };
+    Change {
+        table: table.to_string(),
+        kind: ChangeKind::AddConstraint,
+        statement,
+        compatibility: Compatibility::Breaking,
+        reason,
+    }
+}
+
+/// Named constraints are dropped by PostgreSQL's default constraint names
+fn drop_constraint(table: &str, column: &str, constraint: &Constraint) -> Change {
+    let (name, col) = (quote_qualified(table, '"'), quote_identifier(column, '"'));
+    // Defaults are built from the bare table name, lowercased when unquoted
+    let bare = table.rsplit('.').next().unwrap_or(table);
+    let default_name = |suffix: &str| {
+        quote_identifier(
+            &format!("{}_{}_{}", bare, column, suffix).to_lowercase(),
+            '"',
+        )
+    };
+    let statement = match constraint {
+        Constraint::NotNull => format!("ALTER TABLE {} ALTER COLUMN {} DROP NOT NULL", name, col),
+        Constraint::Unique => format!(
+            "ALTER TABLE {} DROP CONSTRAINT {}",
+            name,
+            default_name("key")
+        ),
+        Constraint::PrimaryKey => format!(
+            "ALTER TABLE {} DROP CONSTRAINT {}",
+            name,
+            quote_identifier(&format!("{}_pkey", bare.to_lowercase()), '"')
+        ),
+        Constraint::References(..) => format!(
+            "ALTER TABLE {} DROP CONSTRAINT {}",
+            name,
+            default_name("fkey")
+        ),
+    };
+    Change {
+        table: table.to_string(),
+        kind: ChangeKind::DropConstraint,
+        statement,
+        compatibility: Compatibility::Compatible,
+        reason: format!("older files satisfy the looser constraint on '{}'", column),
+    }
+}
+
+/// Column, type and constraint changes of a table present in both versions
+fn diff_columns(old: &Table, new: &Table) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut schema = old.clone();
+    let mut added = Vec::new();
+    for change in diff_table(old, new) {
+        let (compatibility, reason) = classify_change(&change, &schema);
+        let kind = match &change {
+            SchemaChange::AddColumn { column, .. } => {
+                added.push(column.name.clone());
+                ChangeKind::AddColumn
+            }
+            SchemaChange::DropColumn { .. } => ChangeKind::DropColumn,
+            SchemaChange::RenameColumn { .. } => ChangeKind::RenameColumn,
+            SchemaChange::ChangeType { .. } => ChangeKind::ChangeType,
+        };
+        // diff_table only produces changes that apply to `old`
+        change.apply(&mut schema).ok();
+        changes.push(Change {
+            table: new.qualified_name(),
+            kind,
+            statement: change.to_string(),
+            compatibility,
+            reason,
+        });
+    }
+
+    // Added columns carry their constraints, the rest are compared here
+    for col in &new.columns {
+        if added.iter().any(|name| name == &col.name) {
+            continue;
+        }
+        let before = match schema.column_index(&col.name) {
+            Some(i) => constraints_of(&schema.columns[i]),
+            None => continue,
+        };
+        let after = constraints_of(col);
+        for constraint in before.iter().filter(|c| !after.contains(c)) {
+            changes.push(drop_constraint(
+                &new.qualified_name(),
+                &col.name,
+                constraint,
+            ));
+        }
+        for constraint in after.iter().filter(|c| !before.contains(c)) {
+            changes.push(add_constraint(&new.qualified_name(), &col.name, constraint));
+        }
+    }
+    changes
+}
+
+/// Every difference between two versions of data.sql
+#[derive(Debug, Clone)]
+pub struct SchemaDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SchemaDiff {
+    /// The worst compatibility of any change, compatible when nothing changed
+    pub fn verdict(&self) -> Compatibility {
+        self.changes
+            .iter()
+            .map(|c| c.compatibility)
+            .max()
+            .unwrap_or(Compatibility::Compatible)
+    }
+
+    /// The statements that migrate the old schema, with each reason as a comment
+    pub fn migration_sql(&self) -> String {
+        let mut out = format!(
+            "-- Migration generated by `data diff`, verdict: {}\n",
+            self.verdict().as_str()
+        );
+        for change in &self.changes {
+            out.push_str(&format!(
+                "\n-- [{}] {}\n{};\n",
+                change.compatibility.as_str(),
+                change.reason,
+                change.statement
+            ));
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "verdict": self.verdict().as_str(),
+            "changes": self.changes.iter().map(|c| json!({
+                "table": c.table,
+                "kind": c.kind.as_str(),
+                "statement": c.statement,
+                "compatibility": c.compatibility.as_str(),
+                "reason": c.reason,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn print(&self, old: &str, new: &str) {
+        println!("🔀 {} -> {}", old.bold(), new.bold());
+        if self.changes.is_empty() {
+            println!("{}", "✅ The schemas are identical".green());
+            return;
+        }
+        let mut table = "";
+        for change in &self.changes {
+            if change.table != table {
+                table = &change.table;
+                println!("\n  {}", table.bold().yellow());
+            }
+            println!(
+                "    {} [{}] {}",
+                change.statement.bold(),
+                match change.compatibility {
+                    Compatibility::Compatible => change.compatibility.as_str().green(),
+                    Compatibility::NeedsCast => change.compatibility.as_str().yellow(),
+                    Compatibility::Breaking => change.compatibility.as_str().red(),
+                },
+                change.reason
+            );
+        }
+        println!();
+        match self.verdict() {
+            Compatibility::Compatible => println!(
+                "{}",
+                "✅ Existing Parquet data stays readable under the new schema".green()
+            ),
+            Compatibility::NeedsCast => println!(
+                "{}",
+                "⚠️  Existing Parquet data is readable after casting or merging schemas".yellow()
+            ),
+            Compatibility::Breaking => println!(
+                "{}",
+                "❌ Existing Parquet data breaks under the new schema".red()
+            ),
+        }
+    }
+}
+
+/// Compare two parsed data.sql files. Tables are matched by name; created
+/// tables come first, parents before children, and dropped tables last.
+pub fn diff_schemas(old: &[Table], new: &[Table]) -> Result<SchemaDiff, String> {
+    let find = |tables: &[Table], table: &Table| Table::position(tables, &table.qualified_name());
+    let mut changes = Vec::new();
+
+    for table in creation_order(new)? {
+        if find(old, table)?.is_none() {
+            changes.push(Change {
+                table: table.qualified_name(),
+                kind: ChangeKind::AddTable,
+                statement: table.to_string().trim_end_matches(';').to_string(),
+                compatibility: Compatibility::Compatible,
+                reason: "new table, no existing files".to_string(),
+            });
+        }
+    }
+    for table in new {
+        if let Some(i) = find(old, table)? {
+            changes.extend(diff_columns(&old[i], table));
+        }
+    }
+    for table in old {
+        if find(new, table)?.is_none() {
+            changes.push(Change {
+                table: table.qualified_name(),
+                kind: ChangeKind::DropTable,
+                statement: format!("DROP TABLE {}", table.sql_name('"')),
+                compatibility: Compatibility::Breaking,
+                reason: format!(
+                    "existing files of '{}' lose their schema",
+                    table.qualified_name()
+                ),
+            });
+        }
+    }
+    Ok(SchemaDiff { changes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql;
+
+    #[test]
+    fn test_diff_schemas() {
+        let old = sql::parse_schema(
+            "CREATE TABLE Players (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                Nick VARCHAR(20) UNIQUE,
+                Email TEXT NOT NULL,
+                Score INT
+            );
+            CREATE TABLE Legacy (
+                ID INT PRIMARY KEY
+            );",
+        )
+        .unwrap()
+        .tables;
+        let new = sql::parse_schema(
+            "CREATE TABLE Players (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                Handle VARCHAR(20) UNIQUE,
+                Email TEXT,
+                Score BIGINT NOT NULL,
+                TeamID INT REFERENCES Teams(ID)
+            );
+            CREATE TABLE Teams (
+                ID INT AUTO_INCREMENT PRIMARY KEY
+            );",
+        )
+        .unwrap()
+        .tables;
+
+        let diff = diff_schemas(&old, &new).unwrap();
+        let statements: Vec<&str> = diff.changes.iter().map(|c| c.statement.as_str()).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "CREATE TABLE Teams (\n    ID INT AUTO_INCREMENT PRIMARY KEY\n)",
+                "ALTER TABLE Players RENAME COLUMN Nick TO Handle",
+                "ALTER TABLE Players ALTER COLUMN Score TYPE BIGINT",
+                "ALTER TABLE Players ADD COLUMN TeamID INT REFERENCES Teams(ID)",
+                "ALTER TABLE Players ALTER COLUMN Email DROP NOT NULL",
+                "ALTER TABLE Players ALTER COLUMN Score SET NOT NULL",
+                "DROP TABLE Legacy",
+            ]
+        );
+        let kinds: Vec<Compatibility> = diff.changes.iter().map(|c| c.compatibility).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Compatibility::Compatible,
+                Compatibility::Breaking,
+                Compatibility::NeedsCast,
+                Compatibility::Compatible,
+                Compatibility::Compatible,
+                Compatibility::Breaking,
+                Compatibility::Breaking,
+            ]
+        );
+        assert_eq!(diff.verdict(), Compatibility::Breaking);
+        assert!(diff
+            .migration_sql()
+            .contains("\n-- [needs_cast] INT -> BIGINT: widening, but the Parquet physical type changes\nALTER TABLE Players ALTER COLUMN Score TYPE BIGINT;\n"));
+
+        assert_eq!(
+            diff_schemas(&new, &new).unwrap().verdict(),
+            Compatibility::Compatible
+        );
+        assert!(diff_schemas(&new, &new).unwrap().changes.is_empty());
+    }
+}
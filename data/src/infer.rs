@@ -0,0 +1,523 @@
+//! Reverse-engineer a data.sql from existing Parquet or CSV files. Column
+//! types come from the Arrow schema (inferred for CSV), constraints and
+//! optional comments describing the values actually observed.
+use colored::Colorize;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::datatypes::DataType as ArrowType;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
+
+use crate::inspect;
+use crate::model::{self, Value};
+use crate::sql::{self, Column, DataType, Table};
+
+/// Stop tracking distinct values past this many, the column is then
+/// assumed not to be unique
+const DISTINCT_LIMIT: usize = 1_000_000;
+
+/// How to read the input files and what to derive from them
+#[derive(Debug, Clone)]
+pub struct InferOptions {
+    /// Field delimiter for CSV files, `.tsv` files always use tabs
+    pub delimiter: u8,
+    /// Columns with at most this many distinct values get a `oneof` hint
+    pub max_enum_values: usize,
+}
+
+impl Default for InferOptions {
+    fn default() -> Self {
+        InferOptions {
+            delimiter: b',',
+            max_enum_values: 10,
+        }
+    }
+}
+
+/// What was observed in one column across every row read
+#[derive(Debug, Clone)]
+struct ColumnObservation {
+    name: String,
+    arrow_type: ArrowType,
+    nulls: usize,
+    distinct: HashSet<String>,
+    distinct_overflow: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    min_len: Option<usize>,
+    max_len: usize,
+    all_uuid: bool,
+    trues: usize,
+}
+
+impl ColumnObservation {
+    fn new(name: &str, arrow_type: ArrowType) -> ColumnObservation {
+        ColumnObservation {
+            name: name.to_string(),
+            arrow_type,
+            nulls: 0,
+            distinct: HashSet::new(),
+            distinct_overflow: false,
+            min: None,
+            max: None,
+            min_len: None,
+            max_len: 0,
+            all_uuid: true,
+            trues: 0,
+        }
+    }
+
+    fn observe(&mut self, value: &Value, empty_is_null: bool) {
+        let number = match value {
+            Value::Null => {
+                self.nulls += 1;
+                return;
+            }
+            Value::Str(s) if empty_is_null && s.is_empty() => {
+                self.nulls += 1;
+                return;
+            }
+            Value::Int(v) => Some(*v as f64),
+            Value::Long(v) => Some(*v as f64),
+            Value::Float(v) => Some(*v as f64),
+            Value::Double(v) => Some(*v),
+            Value::Date(v) => Some(*v as f64),
+            Value::Str(s) => {
+                let len = s.chars().count();
+                self.min_len = Some(self.min_len.map_or(len, |m| m.min(len)));
+                self.max_len = self.max_len.max(len);
+                self.all_uuid &= uuid::Uuid::parse_str(s).is_ok();
+                None
+            }
+            Value::Bool(b) => {
+                self.trues += *b as usize;
+                None
+            }
+        };
+        if let Some(n) = number {
+            self.min = Some(self.min.map_or(n, |m| m.min(n)));
+            self.max = Some(self.max.map_or(n, |m| m.max(n)));
+        }
+        if !self.distinct_overflow {
+            self.distinct.insert(value.to_string());
+            if self.distinct.len() > DISTINCT_LIMIT {
+                self.distinct_overflow = true;
+                self.distinct.clear();
+            }
+        }
+    }
+
+    fn non_null(&self, rows: usize) -> usize {
+        rows - self.nulls
+    }
+
+    fn is_unique(&self, rows: usize) -> bool {
+        !self.distinct_overflow && self.distinct.len() == self.non_null(rows)
+    }
+
+    fn fits_i32(&self) -> bool {
+        self.min.unwrap_or(0.0) >= i32::MIN as f64 && self.max.unwrap_or(0.0) <= i32::MAX as f64
+    }
+
+    /// The narrowest data.sql type holding every observed value. Only CSV
+    /// integers are narrowed, Parquet files keep their physical width.
+    fn sql_type(&self, from_csv: bool) -> DataType {
+        let name = match &self.arrow_type {
+            ArrowType::Boolean => "BOOLEAN".to_string(),
+            ArrowType::Int8
+            | ArrowType::Int16
+            | ArrowType::Int32
+            | ArrowType::UInt8
+            | ArrowType::UInt16 => "INT".to_string(),
+            ArrowType::Int64 if from_csv && self.fits_i32() => "INT".to_string(),
+            ArrowType::Int64 | ArrowType::UInt32 | ArrowType::UInt64 => "BIGINT".to_string(),
+            ArrowType::Float32 => "FLOAT".to_string(),
+            ArrowType::Float64 => "DOUBLE".to_string(),
+            ArrowType::Date32 | ArrowType::Date64 | ArrowType::Timestamp(_, _) => {
+                "DATE".to_string()
+            }
+            ArrowType::Utf8 | ArrowType::LargeUtf8 if self.min_len.is_some() => {
+                if self.all_uuid {
+                    "UUID".to_string()
+                } else {
+                    match [16, 32, 64, 128, 255].iter().find(|&&n| self.max_len <= n) {
+                        Some(n) => format!("VARCHAR({})", n),
+                        None => "TEXT".to_string(),
+                    }
+                }
+            }
+            _ => "TEXT".to_string(),
+        };
+        sql::parse_data_type(&name).unwrap()
+    }
+
+    /// A comment describing the observed values, for a reader picking generators
+    fn hint(&self, rows: usize, data_type: &DataType, options: &InferOptions) -> String {
+        let non_null = self.non_null(rows);
+        let mut hints = Vec::new();
+        // A line break would end the comment, such values only get their length
+        let enumerable = !self.distinct_overflow
+            && self.distinct.len() <= options.max_enum_values
+            && self.distinct.len() * 2 <= non_null
+            && !self.distinct.iter().any(|v| v.contains(char::is_control));
+
+        match data_type {
+            _ if non_null == 0 => {}
+            DataType::Boolean(_) => hints.push(format!(
+                "{:.0}% true",
+                self.trues as f64 / non_null as f64 * 100.0
+            )),
+            DataType::UUID(_) => hints.push("UUIDs".to_string()),
+            DataType::String(_) | DataType::VarChar(_) if enumerable => {
+                let mut values: Vec<&String> = self.distinct.iter().collect();
+                values.sort();
+                let quoted: Vec<String> = values
+                    .iter()
+                    .map(|v| format!("'{}'", v.replace('\'', "''")))
+                    .collect();
+                hints.push(format!("values {}", quoted.join(", ")));
+            }
+            DataType::String(_) | DataType::VarChar(_) => hints.push(format!(
+                "{} to {} characters",
+                self.min_len.unwrap_or(0),
+                self.max_len
+            )),
+            DataType::DateTime(_) => {
+                let (min, max) = (self.min.unwrap_or(0.0), self.max.unwrap_or(0.0));
+                hints.push(format!(
+                    "from {} to {}",
+                    Value::Date(min as i32),
+                    Value::Date(max as i32)
+                ));
+            }
+            _ => {
+                let (min, max) = (self.min.unwrap_or(0.0), self.max.unwrap_or(0.0));
+                hints.push(format!("from {} to {}", min, max));
+            }
+        }
+        if self.is_unique(rows) && non_null > 1 {
+            hints.push("unique".to_string());
+        }
+        if self.nulls > 0 {
+            hints.push(format!(
+                "{:.0}% null",
+                self.nulls as f64 / rows as f64 * 100.0
+            ));
+        }
+        format!("observed: {}", hints.join(", "))
+    }
+}
+
+/// A table recovered from files, with a comment on the values of each column
+#[derive(Debug, Clone)]
+pub struct InferredTable {
+    pub table: Table,
+    pub hints: Vec<String>,
+    pub source: String,
+    pub num_rows: usize,
+}
+
+/// Replace anything data.sql cannot parse as an identifier with `_`
+fn identifier(name: &str) -> String {
+    let cleaned: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match cleaned.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => cleaned,
+        _ => format!("_{}", cleaned),
+    }
+}
+
+fn is_csv(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("csv") | Some("tsv")
+    )
+}
+
+/// Widen two Arrow types seen for the same column in different files
+fn merge_types(a: &ArrowType, b: &ArrowType) -> ArrowType {
+    use ArrowType::*;
+    match (a, b) {
+        _ if a == b => a.clone(),
+        (Int8 | Int16 | Int32, Int8 | Int16 | Int32) => Int32,
+        (Int8 | Int16 | Int32 | Int64, Int8 | Int16 | Int32 | Int64) => Int64,
+        (Float32, Float32) => Float32,
+        (
+            Int8 | Int16 | Int32 | Int64 | Float32 | Float64,
+            Int8 | Int16 | Int32 | Int64 | Float32 | Float64,
+        ) => Float64,
+        _ => Utf8,
+    }
+}
+
+/// Call `f` with every record batch of a Parquet or CSV file
+pub fn for_each_batch<F: FnMut(&RecordBatch)>(
+    path: &Path,
+    options: &InferOptions,
+    mut f: F,
+) -> Result<(), String> {
+    let read_error =
+        |e: &dyn std::fmt::Display| format!("Error reading '{}': {}", path.display(), e);
+    if is_csv(path) {
+        let delimiter = match path.extension().and_then(|e| e.to_str()) {
+            Some("tsv") => b'\t',
+            _ => options.delimiter,
+        };
+        let file =
+            File::open(path).map_err(|e| format!("Unable to open '{}': {}", path.display(), e))?;
+        let reader = arrow::csv::ReaderBuilder::new()
+            .has_header(true)
+            .with_delimiter(delimiter)
+            .infer_schema(None)
+            .build(file)
+            .map_err(|e| read_error(&e))?;
+        for batch in reader {
+            f(&batch.map_err(|e| read_error(&e))?);
+        }
+    } else {
+        let file_reader = inspect::open_parquet(path)?;
+        let mut reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+        let batches = reader.get_record_reader(8192).map_err(|e| read_error(&e))?;
+        for batch in batches {
+            f(&batch.map_err(|e| read_error(&e))?);
+        }
+    }
+    Ok(())
+}
+
+/// Infer one table from a CSV file, a Parquet file or a directory of Parquet
+/// files (such as a partitioned dataset). The table is named after the file
+/// stem or directory.
+pub fn infer_table(path: &Path, options: &InferOptions) -> Result<InferredTable, String> {
+    let files = if path.is_dir() {
+        inspect::find_parquet_files(path)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    if files.is_empty() {
+        return Err(format!("No Parquet files found in '{}'", path.display()));
+    }
+    let from_csv = files.iter().all(|f| is_csv(f));
+
+    let mut observations: Vec<ColumnObservation> = Vec::new();
+    let mut num_rows = 0;
+    for file in &files {
+        for_each_batch(file, options, |batch| {
+            let schema = batch.schema();
+            let mut seen = vec![false; observations.len()];
+            for (field, array) in schema.fields().iter().zip(batch.columns()) {
+                let index = match observations.iter().position(|o| o.name == *field.name()) {
+                    Some(i) => {
+                        observations[i].arrow_type =
+                            merge_types(&observations[i].arrow_type, field.data_type());
+                        i
+                    }
+                    None => {
+                        // Columns missing from earlier files were null there
+                        let mut observation =
+                            ColumnObservation::new(field.name(), field.data_type().clone());
+                        observation.nulls = num_rows;
+                        observations.push(observation);
+                        seen.push(false);
+                        observations.len() - 1
+                    }
+                };
+                seen[index] = true;
+                for row in 0..array.len() {
+                    observations[index].observe(&model::array_value(array.as_ref(), row), from_csv);
+                }
+            }
+            for (observation, _) in observations.iter_mut().zip(&seen).filter(|(_, s)| !**s) {
+                observation.nulls += batch.num_rows();
+            }
+            num_rows += batch.num_rows();
+        })?;
+    }
+
+    let types: Vec<DataType> = observations.iter().map(|o| o.sql_type(from_csv)).collect();
+    let key_like = |t: &DataType| {
+        matches!(
+            t,
+            DataType::Int(_) | DataType::BigInt(_) | DataType::UUID(_) | DataType::VarChar(_)
+        )
+    };
+    let candidates: Vec<usize> = (0..observations.len())
+        .filter(|&i| {
+            num_rows > 0
+                && observations[i].nulls == 0
+                && observations[i].is_unique(num_rows)
+                && key_like(&types[i])
+        })
+        .collect();
+    // Prefer a column called ID, then one like user_id or UserId, then the
+    // first candidate
+    let primary_key = candidates
+        .iter()
+        .find(|&&i| observations[i].name.eq_ignore_ascii_case("id"))
+        .or_else(|| {
+            candidates.iter().find(|&&i| {
+                let name = &observations[i].name;
+                name.to_lowercase().ends_with("_id") || name.ends_with("Id") || name.ends_with("ID")
+            })
+        })
+        .or_else(|| candidates.first())
+        .copied();
+
+    let mut columns = Vec::new();
+    let mut hints = Vec::new();
+    for (i, (observation, data_type)) in observations.iter().zip(types).enumerate() {
+        let mut constraints: Vec<&str> = Vec::new();
+        if primary_key == Some(i) {
+            constraints.extend(["PRIMARY", "KEY"]);
+        } else {
+            if observation.nulls == 0 && num_rows > 0 {
+                constraints.extend(["NOT", "NULL"]);
+            }
+            if candidates.contains(&i) {
+                constraints.push("UNIQUE");
+            }
+        }
+        hints.push(observation.hint(num_rows, &data_type, options));
+        columns.push(Column {
+            name: identifier(&observation.name),
+            data_type,
+            constraints: constraints.into_iter().map(String::from).collect(),
+        });
+    }
+
+    let stem = if path.is_dir() {
+        path.file_name()
+    } else {
+        path.file_stem()
+    };
+    Ok(InferredTable {
+        table: Table {
+            name: identifier(&stem.unwrap_or_default().to_string_lossy()),
+            namespace: Vec::new(),
+            columns,
+        },
+        hints,
+        source: path.display().to_string(),
+        num_rows,
+    })
+}
+
+/// Render the tables as a data.sql that `sql::parse_schema` reads back,
+/// with a trailing comment on each column's observed values when `with_hints`
+pub fn to_data_sql(tables: &[InferredTable], with_hints: bool) -> String {
+    let mut out = String::from("-- SQL Database Schema\n");
+    for inferred in tables {
+        out.push_str(&format!(
+            "\n-- Inferred from {} ({} rows)\n",
+            inferred.source, inferred.num_rows
+        ));
+        if !with_hints {
+            out.push_str(&format!("{}\n", inferred.table));
+            continue;
+        }
+        out.push_str(&format!(
+            "CREATE TABLE {} (\n",
+            inferred.table.sql_name('"')
+        ));
+        let columns = &inferred.table.columns;
+        for (i, (col, hint)) in columns.iter().zip(&inferred.hints).enumerate() {
+            let separator = if i + 1 < columns.len() { "," } else { "" };
+            out.push_str(&format!("    {}{} -- {}\n", col, separator, hint));
+        }
+        out.push_str(");\n");
+    }
+    out
+}
+
+/// Print a one line summary per inferred table
+pub fn print_summary(tables: &[InferredTable]) {
+    for inferred in tables {
+        let key = inferred
+            .table
+            .primary_key()
+            .map(|col| col.name.clone())
+            .unwrap_or_else(|| "none".to_string());
+        println!(
+            "🔎 {} from '{}': {} columns, {} rows, primary key {}",
+            inferred.table.name.bold().yellow(),
+            inferred.source,
+            inferred.table.columns.len().to_string().bold().cyan(),
+            inferred.num_rows.to_string().bold().cyan(),
+            key.bold().green()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_csv_round_trips_through_parse_schema() {
+        let dir = tempdir::TempDir::new("infer").unwrap();
+        let path = dir.path().join("players.csv");
+        std::fs::write(
+            &path,
+            "id,name,team,age,score,joined,active,email,motto\n\
+             1,Ann,Lions,31,10.5,2023-01-02,true,ann@example.com,\"Go\nteam\"\n\
+             2,Bob,Lions,25,7.25,2023-02-03,false,,\"Go\nteam\"\n\
+             3,Cid,Bears,40,3.0,2023-03-04,true,cid@example.com,Win\n\
+             4,Dee,Lions,22,9.75,2023-04-05,true,dee@example.com,Win\n\
+             5,Eve,Bears,35,1.5,2023-05-06,false,eve@example.com,Win\n",
+        )
+        .unwrap();
+
+        let inferred = infer_table(&path, &InferOptions::default()).unwrap();
+        let sql = to_data_sql(std::slice::from_ref(&inferred), true);
+        let parsed = sql::parse_schema(&sql).unwrap().tables;
+        assert_eq!(parsed, vec![inferred.table.clone()]);
+
+        let table = &parsed[0];
+        assert_eq!(table.name, "players");
+        let column = |name: &str| table.columns[table.column_index(name).unwrap()].to_string();
+        assert_eq!(column("id"), "id INT PRIMARY KEY");
+        assert_eq!(column("name"), "name VARCHAR(16) NOT NULL UNIQUE");
+        assert_eq!(column("score"), "score DOUBLE NOT NULL");
+        assert_eq!(column("joined"), "joined DATE NOT NULL");
+        assert_eq!(column("active"), "active BOOLEAN NOT NULL");
+        assert_eq!(column("email"), "email VARCHAR(16)");
+        assert!(sql.contains("-- observed: values 'Bears', 'Lions'"));
+        assert!(sql.contains("-- observed: from 22 to 40"));
+        assert!(sql.contains("20% null"));
+        assert!(sql.contains("-- observed: 3 to 7 characters"));
+    }
+
+    #[test]
+    fn test_infer_parquet_keeps_generated_types() {
+        let table = sql::parse_create_table(
+            "CREATE TABLE Orders (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Ref UUID,
+            Total BIGINT,
+            Placed DATE
+        )",
+        );
+        let dir = tempdir::TempDir::new("infer").unwrap();
+        let path = dir.path().join("Orders.parquet");
+        let rows = model::generate_table_rows(&table, 50).unwrap();
+        model::write_rows(&path, &table, &rows, model::FileFormat::Parquet).unwrap();
+
+        let inferred = infer_table(&path, &InferOptions::default()).unwrap();
+        assert_eq!(inferred.num_rows, 50);
+        let types: Vec<String> = inferred
+            .table
+            .columns
+            .iter()
+            .map(|c| c.data_type.to_string())
+            .collect();
+        assert_eq!(types, vec!["INT", "UUID", "BIGINT", "DATE"]);
+        assert!(inferred.table.columns[0].is_primary_key());
+    }
+}
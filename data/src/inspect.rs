@@ -0,0 +1,354 @@
+//! Inspect any Parquet file or directory of Parquet files: schema,
+//! key-value metadata, row group layout, column chunk statistics and a
+//! preview of the first rows.
+use chrono::{Duration, NaiveDate};
+use colored::Colorize;
+use serde_json::json;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use parquet::basic::ConvertedType;
+use parquet::file::metadata::ColumnChunkMetaData;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use parquet::record::{Field, Row};
+use parquet::schema::printer::print_schema;
+
+/// Every `.parquet` file at `path`, recursing into directories. Hidden and
+/// `_`-prefixed entries (Spark's `_SUCCESS`, our `_evolution.json`) are skipped.
+pub fn find_parquet_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let entries = std::fs::read_dir(path)
+        .map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        let name = entry_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.starts_with('.') || name.starts_with('_') {
+            continue;
+        }
+        if entry_path.is_dir() {
+            files.extend(find_parquet_files(&entry_path)?);
+        } else if name.ends_with(".parquet") {
+            files.push(entry_path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn open_parquet(path: &Path) -> Result<SerializedFileReader<File>, String> {
+    let file =
+        File::open(path).map_err(|e| format!("Unable to open '{}': {}", path.display(), e))?;
+    SerializedFileReader::new(file)
+        .map_err(|e| format!("'{}' is not a readable Parquet file: {}", path.display(), e))
+}
+
+/// Min and max of a column chunk, rendered using the column's logical type
+fn stat_min_max(stats: &Statistics, converted: ConvertedType) -> Option<(String, String)> {
+    if !stats.has_min_max_set() {
+        return None;
+    }
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)?;
+    let int32 = |v: i32| match converted {
+        ConvertedType::DATE => (epoch + Duration::days(v as i64))
+            .format("%Y-%m-%d")
+            .to_string(),
+        _ => v.to_string(),
+    };
+    let int64 = |v: i64| match converted {
+        ConvertedType::TIMESTAMP_MILLIS => (epoch + Duration::milliseconds(v)).to_string(),
+        ConvertedType::TIMESTAMP_MICROS => (epoch + Duration::microseconds(v)).to_string(),
+        _ => v.to_string(),
+    };
+    let bytes = |v: &[u8]| match std::str::from_utf8(v) {
+        Ok(s) if converted == ConvertedType::UTF8 => s.to_string(),
+        _ => hex::encode(v),
+    };
+
+    Some(match stats {
+        Statistics::Boolean(s) => (s.min().to_string(), s.max().to_string()),
+        Statistics::Int32(s) => (int32(*s.min()), int32(*s.max())),
+        Statistics::Int64(s) => (int64(*s.min()), int64(*s.max())),
+        Statistics::Int96(s) => (s.min().to_string(), s.max().to_string()),
+        Statistics::Float(s) => (s.min().to_string(), s.max().to_string()),
+        Statistics::Double(s) => (s.min().to_string(), s.max().to_string()),
+        Statistics::ByteArray(s) => (bytes(s.min_bytes()), bytes(s.max_bytes())),
+        Statistics::FixedLenByteArray(s) => {
+            (hex::encode(s.min_bytes()), hex::encode(s.max_bytes()))
+        }
+    })
+}
+
+/// JSON for a record field, covering every type the row reader produces
+pub fn field_to_json(field: &Field) -> serde_json::Value {
+    match field {
+        Field::Null => serde_json::Value::Null,
+        Field::Bool(v) => json!(v),
+        Field::Byte(v) => json!(v),
+        Field::Short(v) => json!(v),
+        Field::Int(v) => json!(v),
+        Field::Long(v) => json!(v),
+        Field::UByte(v) => json!(v),
+        Field::UShort(v) => json!(v),
+        Field::UInt(v) => json!(v),
+        Field::ULong(v) => json!(v),
+        Field::Float(v) => json!(v),
+        Field::Double(v) => json!(v),
+        Field::Str(v) => json!(v),
+        Field::Bytes(v) => json!(hex::encode(v.data())),
+        // Dates, timestamps and decimals use the reader's own formatting
+        Field::Decimal(_)
+        | Field::Date(_)
+        | Field::TimestampMillis(_)
+        | Field::TimestampMicros(_) => {
+            json!(field.to_string())
+        }
+        Field::Group(row) => row_to_json(row),
+        Field::ListInternal(list) => {
+            serde_json::Value::Array(list.elements().iter().map(field_to_json).collect())
+        }
+        Field::MapInternal(map) => serde_json::Value::Object(
+            map.entries()
+                .iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Field::Str(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    (key, field_to_json(v))
+                })
+                .collect(),
+        ),
+    }
+}
+
+pub fn row_to_json(row: &Row) -> serde_json::Value {
+    serde_json::Value::Object(
+        row.get_column_iter()
+            .map(|(name, field)| (name.clone(), field_to_json(field)))
+            .collect(),
+    )
+}
+
+fn chunk_to_json(chunk: &ColumnChunkMetaData) -> serde_json::Value {
+    let converted = chunk.column_descr().converted_type();
+    let stats = chunk.statistics();
+    let min_max = stats.and_then(|s| stat_min_max(s, converted));
+    json!({
+        "column": chunk.column_path().string(),
+        "physical_type": chunk.column_type().to_string(),
+        "compression": chunk.compression().to_string(),
+        "encodings": chunk.encodings().iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        "num_values": chunk.num_values(),
+        "compressed_size": chunk.compressed_size(),
+        "uncompressed_size": chunk.uncompressed_size(),
+        "null_count": stats.map(|s| s.null_count()),
+        "distinct_count": stats.and_then(|s| s.distinct_count()),
+        "min": min_max.as_ref().map(|(min, _)| min.clone()),
+        "max": min_max.map(|(_, max)| max),
+    })
+}
+
+/// Everything `inspect` reports about one file, as JSON
+pub fn inspect_file(path: &Path, preview_rows: usize) -> Result<serde_json::Value, String> {
+    let reader = open_parquet(path)?;
+    let metadata = reader.metadata();
+    let file_metadata = metadata.file_metadata();
+
+    let columns: Vec<serde_json::Value> = file_metadata
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|col| {
+            json!({
+                "column": col.path().string(),
+                "physical_type": col.physical_type().to_string(),
+                "converted_type": col.converted_type().to_string(),
+                "logical_type": col.logical_type().map(|t| format!("{:?}", t)),
+                "nullable": col.max_def_level() > 0,
+                "repeated": col.max_rep_level() > 0,
+            })
+        })
+        .collect();
+
+    let key_value_metadata: serde_json::Map<String, serde_json::Value> = file_metadata
+        .key_value_metadata()
+        .iter()
+        .flatten()
+        .map(|kv| (kv.key.clone(), json!(kv.value)))
+        .collect();
+
+    let row_groups: Vec<serde_json::Value> = metadata
+        .row_groups()
+        .iter()
+        .enumerate()
+        .map(|(i, rg)| {
+            json!({
+                "index": i,
+                "num_rows": rg.num_rows(),
+                "total_byte_size": rg.total_byte_size(),
+                "columns": rg.columns().iter().map(chunk_to_json).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    let preview: Vec<serde_json::Value> = reader
+        .get_row_iter(None)
+        .map_err(|e| format!("Unable to read rows of '{}': {}", path.display(), e))?
+        .take(preview_rows)
+        .map(|row| row_to_json(&row))
+        .collect();
+
+    let mut schema_text = Vec::new();
+    print_schema(&mut schema_text, file_metadata.schema());
+
+    Ok(json!({
+        "path": path.display().to_string(),
+        "version": file_metadata.version(),
+        "created_by": file_metadata.created_by(),
+        "num_rows": file_metadata.num_rows(),
+        "schema": String::from_utf8_lossy(&schema_text),
+        "columns": columns,
+        "key_value_metadata": key_value_metadata,
+        "row_groups": row_groups,
+        "preview": preview,
+    }))
+}
+
+fn text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Render a report from `inspect_file` for the terminal
+pub fn print_report(report: &serde_json::Value) {
+    println!(
+        "🔎 {} ({} rows, format version {}, created by {})",
+        text(&report["path"]).bold().green(),
+        text(&report["num_rows"]).bold().cyan(),
+        text(&report["version"]),
+        text(&report["created_by"])
+    );
+
+    println!("{}", "Schema".bold().underline());
+    print!("{}", text(&report["schema"]));
+
+    if let Some(kv) = report["key_value_metadata"].as_object() {
+        if !kv.is_empty() {
+            println!("{}", "Key-value metadata".bold().underline());
+            for (key, value) in kv {
+                let mut value = text(value);
+                if value.len() > 80 {
+                    let head: String = value.chars().take(77).collect();
+                    value = format!("{}... ({} bytes)", head, value.len());
+                }
+                println!("  {}: {}", key.cyan(), value);
+            }
+        }
+    }
+
+    for rg in report["row_groups"].as_array().into_iter().flatten() {
+        println!(
+            "{} {}: {} rows, {} bytes",
+            "Row group".bold().underline(),
+            text(&rg["index"]),
+            text(&rg["num_rows"]).cyan(),
+            text(&rg["total_byte_size"])
+        );
+        for chunk in rg["columns"].as_array().into_iter().flatten() {
+            let encodings: Vec<String> = chunk["encodings"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(text)
+                .collect();
+            println!(
+                "  {} {} {} [{}] {} -> {} bytes, nulls {}, min {}, max {}",
+                text(&chunk["column"]).bold().cyan(),
+                text(&chunk["physical_type"]),
+                text(&chunk["compression"]).yellow(),
+                encodings.join(", "),
+                text(&chunk["uncompressed_size"]),
+                text(&chunk["compressed_size"]),
+                text(&chunk["null_count"]),
+                text(&chunk["min"]).yellow(),
+                text(&chunk["max"]).yellow()
+            );
+        }
+    }
+
+    let preview = report["preview"].as_array().cloned().unwrap_or_default();
+    if !preview.is_empty() {
+        println!("{}", "Preview".bold().underline());
+        for row in preview {
+            for (name, value) in row.as_object().into_iter().flatten() {
+                println!("  {}: {}", name.bold().cyan(), text(value).yellow());
+            }
+            println!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{self, FileFormat};
+    use crate::sql;
+
+    #[test]
+    fn test_inspect_generated_file() {
+        let table = sql::parse_create_table(
+            "CREATE TABLE Games (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Played DATE,
+            Score DOUBLE,
+            Home TEXT,
+            Overtime BOOLEAN
+        )",
+        );
+        let dir = tempdir::TempDir::new("inspect").unwrap();
+        let nested = dir.path().join("dt=2024-01-01");
+        std::fs::create_dir_all(&nested).unwrap();
+        let rows = model::generate_table_rows(&table, 25).unwrap();
+        model::write_rows(
+            &nested.join("part-00000.parquet"),
+            &table,
+            &rows,
+            FileFormat::Parquet,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("_SUCCESS"), "").unwrap();
+
+        let files = find_parquet_files(dir.path()).unwrap();
+        assert_eq!(files.len(), 1);
+
+        let report = inspect_file(&files[0], 3).unwrap();
+        assert_eq!(report["num_rows"], 25);
+        assert_eq!(report["columns"].as_array().unwrap().len(), 5);
+        assert_eq!(report["preview"].as_array().unwrap().len(), 3);
+        assert_eq!(report["preview"][0]["ID"], 1);
+
+        let id_chunk = &report["row_groups"][0]["columns"][0];
+        assert_eq!(id_chunk["min"], "1");
+        assert_eq!(id_chunk["max"], "25");
+        assert_eq!(id_chunk["null_count"], 0);
+        let date_chunk = &report["row_groups"][0]["columns"][1];
+        assert!(text(&date_chunk["min"]).starts_with("1970-"));
+        assert!(report["key_value_metadata"]
+            .as_object()
+            .unwrap()
+            .contains_key("ARROW:schema"));
+    }
+}
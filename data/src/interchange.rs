@@ -0,0 +1,321 @@
+//! Schemas for the services around the pipeline: JSON Schema (draft
+//! 2020-12) for validating events, Avro records and Protobuf messages for
+//! serializing them. NOT NULL maps to required, VARCHAR(n) to a maximum
+//! length and ENUM to each format's enumeration.
+use serde_json::json;
+
+use crate::spark::is_nullable;
+use crate::sql::{Column, DataType, Table};
+
+const JSON_SCHEMA_DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+/// Avro namespace and Protobuf package of every generated schema
+pub const NAMESPACE: &str = "lernspark";
+
+fn json_schema_type(col: &Column) -> serde_json::Value {
+    let mut schema = match &col.data_type {
+        DataType::Int(_) => json!({
+            "type": "integer",
+            "minimum": i32::MIN,
+            "maximum": i32::MAX,
+        }),
+        DataType::BigInt(_) => json!({ "type": "integer" }),
+        DataType::Float(_) | DataType::Double(_) => json!({ "type": "number" }),
+        DataType::String(_) => json!({ "type": "string" }),
+        DataType::VarChar(n) => json!({ "type": "string", "maxLength": n }),
+        DataType::UUID(_) => json!({ "type": "string", "format": "uuid" }),
+        DataType::DateTime(_) => json!({ "type": "string", "format": "date" }),
+        DataType::Boolean(_) => json!({ "type": "boolean" }),
+        DataType::Enum(values) => json!({ "type": "string", "enum": values }),
+    };
+    if is_nullable(col) {
+        let base = schema["type"].clone();
+        schema["type"] = json!([base, "null"]);
+        if let Some(values) = schema["enum"].as_array_mut() {
+            values.push(serde_json::Value::Null);
+        }
+    }
+    let mut notes = Vec::new();
+    if col.is_primary_key() {
+        notes.push("Primary key".to_string());
+    } else if col.is_unique() {
+        notes.push("Unique".to_string());
+    }
+    if let Some((table, column)) = col.references() {
+        notes.push(format!("References {}({})", table, column));
+    }
+    if !notes.is_empty() {
+        schema["description"] = json!(notes.join(". "));
+    }
+    schema
+}
+
+/// A JSON Schema validating one row of the table as a JSON object
+pub fn json_schema(table: &Table) -> serde_json::Value {
+    let properties: serde_json::Map<String, serde_json::Value> = table
+        .columns
+        .iter()
+        .map(|col| (col.name.clone(), json_schema_type(col)))
+        .collect();
+    let required: Vec<&str> = table
+        .columns
+        .iter()
+        .filter(|col| !is_nullable(col))
+        .map(|col| col.name.as_str())
+        .collect();
+    json!({
+        "$schema": JSON_SCHEMA_DRAFT,
+        "$id": format!("{}.schema.json", table.flat_name()),
+        "title": table.qualified_name(),
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Avro and Protobuf names must start with a letter or `_` and contain only
+/// letters, digits and `_`
+fn symbol(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match cleaned.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => cleaned,
+        _ => format!("_{}", cleaned),
+    }
+}
+
+/// Whether `name` can be an Avro name or enum symbol as it is
+fn is_avro_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Avro encodes an enum value by its symbol, so every value must be a valid
+/// symbol as written and appear once
+fn avro_symbols(table: &Table, col: &Column, values: &[String]) -> Result<Vec<String>, String> {
+    let mut symbols: Vec<String> = Vec::new();
+    for value in values {
+        if !is_avro_name(value) {
+            return Err(format!(
+                "ENUM value '{}' of {}.{} is not a valid Avro symbol, use letters, digits and _",
+                value, table.name, col.name
+            ));
+        }
+        if symbols.contains(value) {
+            return Err(format!(
+                "ENUM value '{}' of {}.{} appears twice",
+                value, table.name, col.name
+            ));
+        }
+        symbols.push(value.clone());
+    }
+    Ok(symbols)
+}
+
+fn avro_type(table: &Table, col: &Column) -> Result<serde_json::Value, String> {
+    Ok(match &col.data_type {
+        DataType::Int(_) => json!("int"),
+        DataType::BigInt(_) => json!("long"),
+        DataType::Float(_) => json!("float"),
+        DataType::Double(_) => json!("double"),
+        DataType::String(_) => json!("string"),
+        DataType::VarChar(n) => json!({ "type": "string", "maxLength": n }),
+        DataType::UUID(_) => json!({ "type": "string", "logicalType": "uuid" }),
+        DataType::DateTime(_) => json!({ "type": "int", "logicalType": "date" }),
+        DataType::Boolean(_) => json!("boolean"),
+        DataType::Enum(values) => json!({
+            "type": "enum",
+            "name": format!("{}{}", symbol(&table.flat_name()), symbol(&col.name)),
+            "symbols": avro_symbols(table, col, values)?,
+        }),
+    })
+}
+
+/// An Avro record schema (`.avsc`). Nullable columns are unions with
+/// `null` that default to null, so older writers stay compatible. Column
+/// names that are not Avro names are sanitized and keep the original as an
+/// alias; ENUM values that are not valid Avro symbols are an error.
+pub fn avro_schema(table: &Table) -> Result<serde_json::Value, String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut fields = Vec::new();
+    for col in &table.columns {
+        let name = symbol(&col.name);
+        if names.contains(&name) {
+            return Err(format!(
+                "Columns of {} collide on the Avro field name '{}'",
+                table.name, name
+            ));
+        }
+        let mut field = json!({ "name": name, "type": avro_type(table, col)? });
+        if is_nullable(col) {
+            field["type"] = json!(["null", field["type"]]);
+            field["default"] = serde_json::Value::Null;
+        }
+        if name != col.name {
+            field["aliases"] = json!([col.name]);
+        }
+        names.push(name);
+        fields.push(field);
+    }
+    Ok(json!({
+        "type": "record",
+        "name": symbol(&table.flat_name()),
+        "namespace": NAMESPACE,
+        "fields": fields,
+    }))
+}
+
+/// `SCREAMING_SNAKE_CASE` for Protobuf enum values
+fn proto_constant(value: &str) -> String {
+    symbol(value).trim_start_matches('_').to_uppercase()
+}
+
+/// Nested enum type of a column, named apart from the field itself since
+/// both share the message's scope
+fn proto_enum_name(col: &Column) -> String {
+    format!("{}Value", symbol(&col.name))
+}
+
+/// proto3 messages, one per table. Nullable columns are `optional` so a
+/// missing value is distinguishable from the default, required ones are
+/// plain fields.
+pub fn proto_file(tables: &[Table]) -> String {
+    let mut out = format!(
+        "// Generated from data.sql, do not edit\nsyntax = \"proto3\";\n\npackage {};\n",
+        NAMESPACE
+    );
+    for table in tables {
+        out.push_str(&format!("\nmessage {} {{\n", symbol(&table.flat_name())));
+        for col in &table.columns {
+            if let DataType::Enum(values) = &col.data_type {
+                let name = proto_enum_name(col);
+                let prefix = proto_constant(&col.name);
+                out.push_str(&format!(
+                    "  enum {} {{\n    {}_UNSPECIFIED = 0;\n",
+                    name, prefix
+                ));
+                for (i, value) in values.iter().enumerate() {
+                    out.push_str(&format!(
+                        "    {}_{} = {};\n",
+                        prefix,
+                        proto_constant(value),
+                        i + 1
+                    ));
+                }
+                out.push_str("  }\n");
+            }
+        }
+        for (i, col) in table.columns.iter().enumerate() {
+            let (field_type, note) = match &col.data_type {
+                DataType::Int(_) => ("int32".to_string(), String::new()),
+                DataType::BigInt(_) => ("int64".to_string(), String::new()),
+                DataType::Float(_) => ("float".to_string(), String::new()),
+                DataType::Double(_) => ("double".to_string(), String::new()),
+                DataType::String(_) => ("string".to_string(), String::new()),
+                DataType::VarChar(n) => ("string".to_string(), format!(" // max length {}", n)),
+                DataType::UUID(_) => ("string".to_string(), " // UUID".to_string()),
+                DataType::DateTime(_) => (
+                    "int32".to_string(),
+                    " // DATE, days since 1970-01-01".to_string(),
+                ),
+                DataType::Boolean(_) => ("bool".to_string(), String::new()),
+                DataType::Enum(_) => (proto_enum_name(col), String::new()),
+            };
+            let label = if is_nullable(col) { "optional " } else { "" };
+            out.push_str(&format!(
+                "  {}{} {} = {};{}\n",
+                label,
+                field_type,
+                symbol(&col.name),
+                i + 1,
+                note
+            ));
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql;
+
+    #[test]
+    fn test_constraint_mapping() {
+        let table = sql::parse_create_table(
+            "CREATE TABLE Orders (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Status ENUM('new', 'shipped', 'in-transit') NOT NULL,
+            Note VARCHAR(40),
+            Placed DATE
+        )",
+        );
+        assert_eq!(
+            table.columns[1].data_type,
+            DataType::Enum(vec![
+                "new".to_string(),
+                "shipped".to_string(),
+                "in-transit".to_string()
+            ])
+        );
+
+        let schema = json_schema(&table);
+        assert_eq!(schema["$schema"], JSON_SCHEMA_DRAFT);
+        assert_eq!(schema["required"], json!(["ID", "Status"]));
+        assert_eq!(schema["properties"]["Note"]["maxLength"], 40);
+        assert_eq!(
+            schema["properties"]["Note"]["type"],
+            json!(["string", "null"])
+        );
+        assert_eq!(
+            schema["properties"]["Status"]["enum"],
+            json!(["new", "shipped", "in-transit"])
+        );
+
+        // `in-transit` could not be written under a renamed symbol
+        let error = avro_schema(&table).unwrap_err();
+        assert!(error.contains("'in-transit'"), "{}", error);
+        let mut avro_table = table.clone();
+        avro_table.columns[1].data_type =
+            DataType::Enum(vec!["new".to_string(), "in_transit".to_string()]);
+        let avro = avro_schema(&avro_table).unwrap();
+        assert_eq!(avro["fields"][0]["type"], "int");
+        assert_eq!(
+            avro["fields"][1]["type"]["symbols"],
+            json!(["new", "in_transit"])
+        );
+        assert_eq!(avro["fields"][2]["type"][0], "null");
+        assert_eq!(avro["fields"][3]["type"][1]["logicalType"], "date");
+        avro_table.columns[1].data_type = DataType::Enum(vec!["new".to_string(); 2]);
+        assert!(avro_schema(&avro_table).unwrap_err().contains("twice"));
+
+        let quoted = sql::parse_create_table(
+            "CREATE TABLE Items (\"Item Id\" INT PRIMARY KEY, \"Item-Id\" INT)",
+        );
+        let error = avro_schema(&quoted).unwrap_err();
+        assert!(error.contains("'Item_Id'"), "{}", error);
+        let avro = avro_schema(&Table {
+            columns: quoted.columns[..1].to_vec(),
+            ..quoted
+        })
+        .unwrap();
+        assert_eq!(avro["fields"][0]["name"], "Item_Id");
+        assert_eq!(avro["fields"][0]["aliases"], json!(["Item Id"]));
+
+        let mut namespaced = table.clone();
+        namespaced.namespace = vec!["sales".to_string()];
+        assert!(proto_file(&[table.clone(), namespaced]).contains("message sales_Orders {"));
+
+        let proto = proto_file(&[table]);
+        assert!(proto.contains("  int32 ID = 1;\n"));
+        assert!(proto.contains("    STATUS_IN_TRANSIT = 3;\n"));
+        assert!(proto.contains("  StatusValue Status = 2;\n"));
+        assert!(proto.contains("  optional string Note = 3; // max length 40\n"));
+    }
+}
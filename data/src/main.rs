@@ -1,20 +1,958 @@
 mod aws;
+mod cli;
+mod codegen;
+mod ddl;
+mod diff;
+mod evolution;
+mod infer;
+mod inspect;
+mod interchange;
 mod model;
+mod preflight;
+mod profile;
+mod provision;
+mod restore;
+mod sample;
+mod spark;
 mod sql;
+mod store;
+mod sync;
+mod upload;
+mod validate;
+
+/// Typed structs for the tables in data.sql, generated by build.rs. The
+/// shared runtime imports every Arrow array type, whichever data.sql uses.
+#[allow(dead_code, unused_imports)]
+mod tables {
+    include!(concat!(env!("OUT_DIR"), "/tables.rs"));
+}
+
+use cli::Args;
+use colored::Colorize;
+use std::path::Path;
+
+const USAGE: &str = "Usage: data [command] [options]
+
+Without a command, checks your AWS setup with read-only calls (listing the
+configured bucket, if any) and builds examples.tar.gz. Run preflight to test
+writes.
+
+Commands:
+  cdc <table>   Generate a CDC log and its expected snapshot
+                [--key <column>] [--events <n>] [--updates <ratio>]
+                [--deletes <ratio>] [--format parquet|jsonl] [--out <dir>]
+  dirty <table> Generate rows with injected anomalies and a sidecar listing them
+                [--rows <n>] [--inject <column:anomaly[:rate]>,...] [--rate <r>]
+                [--format parquet|jsonl] [--out <dir>]
+                anomalies: out_of_range, malformed_email, varchar_overflow,
+                duplicate_key, future_date, casing, whitespace
+  skew <table>  Generate rows where a key column is skewed towards a few hot keys
+                --column <column> [--hot-keys <n>] [--hot-fraction <f>]
+                [--cardinality <n>] [--rows <n>] [--format parquet|jsonl] [--out <dir>]
+  evolve <table> Write partitions under successive schema versions and a
+                compatibility report, from two data.sql versions or ALTER statements
+                (--from <old.sql> [--to <new.sql>] | --changes <alter.sql>)
+                [--partitions-per-version <n>] [--rows <n>] [--out <dir>]
+  inspect <path> Show schema, metadata, row groups, column statistics and a
+                preview of any Parquet file or directory [--rows <n>] [--json]
+  infer <path>... Reverse-engineer a data.sql from Parquet or CSV files, one table
+                per file or Parquet directory [--delimiter <c>] [--hints] (comment
+                each column with its observed values)
+                [--enum-values <n>] [--out <data.sql>]
+  validate <path>... Check Parquet files or directories against their data.sql
+                table, named after the file or directory unless --table is given:
+                columns, types, NOT NULL, UNIQUE/PRIMARY KEY, VARCHAR lengths and
+                foreign keys between the datasets [--table <name>] [--json]
+  profile <path> Per column null rate, distinct estimate, min/max/mean/stddev, top
+                values, string lengths and histograms of a Parquet dataset
+                [--top <k>] [--bins <n>] [--json] [--html <report.html>]
+  ddl           Write data.sql as DDL for another engine, Spark and Hive tables
+                read the Parquet files under <location>/<table>
+                [--dialect spark|postgres|duckdb|hive] [--location <uri>]
+                [--partition-by <column>,...] [--out <file>]
+  export        Export data.sql tables as explicit schemas for other tools
+                --format spark-json|scala|pyspark|json-schema|avro|proto|rust|all
+                [--table <name>] [--out <file>, or a directory for --format all]
+  diff <old.sql> [<new.sql>] Compare two data.sql versions (default new: data.sql),
+                list ALTER TABLE statements and whether existing Parquet data
+                stays readable [--json] [--out <migration.sql>]
+  upload <dir> <destination> Upload a generated dataset to s3://bucket/prefix
+                or a local directory as <prefix>/<table>/<partition>/part-*.parquet
+                with a _manifest.json, then check every object arrived, or generate
+                every data.sql table first with --generate
+                (upload --generate <destination> [--rows <n>] [--partitions <n>])
+                Files above --part-size MB (default 8) are uploaded in parts, and
+                progress is kept in <dir>/.upload-journal.json so rerunning an
+                interrupted upload resumes it [--concurrency <n>] [--dry-run]
+                [--storage-class STANDARD|INTELLIGENT_TIERING|GLACIER_IR|DEEP_ARCHIVE]
+                for the data files of an S3 upload, the manifest stays STANDARD
+
+  sync <dir> <destination> Upload only the files of a dataset directory that are
+                new or changed, by size and ETag/MD5, and rewrite its _manifest.json
+                [--delete] to remove stored objects with no local file,
+                [--dry-run] to only print the plan, [--part-size <MB>] [--concurrency <n>]
+                [--storage-class <class>] as for upload
+  sample <source> <out-dir> Copy a subset of a stored dataset (s3://bucket/prefix
+                or a directory) to a local directory with the same layout, for
+                fast Spark runs [--partition <column><op><value>,...] with = != < <= > >=
+                [--files <n>] Parquet files per table, [--fraction <f>] of the rows
+                of each file, the first ones, fetching only the row groups holding them
+  restore <s3://bucket/prefix> Request Glacier restores of the archived objects
+                of a dataset and wait until all of them are readable
+                [--days <n>] (default 7) [--tier standard|bulk|expedited]
+                [--interval <seconds>] (default 300) [--no-wait] [--concurrency <n>]
+  preflight [s3://bucket/prefix] Probe every S3 action lernspark needs (list, get,
+                put, delete, multipart and create bucket) on the bucket, default the
+                configured one, print a pass/fail table and a minimal IAM policy
+                granting whatever was denied. Bucket settings applied by
+                provision and restores are not checked
+  provision <config.json> Create the buckets listed in the config, or bring
+                existing ones in line with its region, versioning, encryption
+                (sse-s3, aws:kms with kms_key_id, none), public access block,
+                lifecycle rules and tags, reporting every setting that drifted
+                [--check] to only report drift, failing when there is any
+                {\"region\": \"eu-west-1\", \"tags\": {\"project\": \"lernspark\"},
+                 \"buckets\": [{\"name\": \"lernspark-raw\", \"role\": \"input\",
+                   \"versioning\": true, \"encryption\": \"sse-s3\",
+                   \"block_public_access\": true, \"lifecycle\": [{\"id\": \"archive\",
+                   \"prefix\": \"raw/\", \"transition_days\": 30,
+                   \"storage_class\": \"DEEP_ARCHIVE\"}]}]}
+
+S3 is reached with AWS_PROFILE, or with a MinIO/LocalStack endpoint and static
+credentials from ~/.config/lernspark/s3.json (or $LERNSPARK_S3_CONFIG):
+  {\"endpoint_url\": \"http://localhost:9000\", \"force_path_style\": true,
+   \"access_key_id\": \"...\", \"secret_access_key\": \"...\", \"bucket\": \"...\"}
+overridden by AWS_ENDPOINT_URL, LERNSPARK_S3_PATH_STYLE, AWS_REGION,
+AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_SESSION_TOKEN and LERNSPARK_S3_BUCKET.";
+
+/// Flags that never take a value, whatever follows them
+const SWITCHES: &[&str] = &[
+    "check", "delete", "dry-run", "generate", "help", "hints", "json", "no-wait",
+];
 
 #[tokio::main] // This attribute effectively makes your main function asynchronous
 async fn main() {
-    let _ = aws::check_aws_profile();
-    if let Err(e) = aws::check_permissions().await {
-        eprintln!("You do not have the right permissions: {}", e);
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let args = Args::parse(args, SWITCHES);
+
+    let result = match command.as_deref() {
+        None => run_default().await,
+        Some("cdc") => run_cdc(&args),
+        Some("dirty") => run_dirty(&args),
+        Some("skew") => run_skew(&args),
+        Some("evolve") => run_evolve(&args),
+        Some("inspect") => run_inspect(&args),
+        Some("infer") => run_infer(&args),
+        Some("validate") => run_validate(&args),
+        Some("profile") => run_profile(&args),
+        Some("ddl") => run_ddl(&args),
+        Some("export") => run_export(&args),
+        Some("diff") => run_diff(&args),
+        Some("upload") => run_upload(&args).await,
+        Some("sync") => run_sync(&args).await,
+        Some("sample") => run_sample(&args).await,
+        Some("provision") => run_provision(&args).await,
+        Some("restore") => run_restore(&args).await,
+        Some("preflight") => run_preflight(&args).await,
+        Some("help") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => Err(format!("Unknown command '{}'\n\n{}", other, USAGE)),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e.red());
+        std::process::exit(1);
     }
-    if let Err(e) = aws::check_aws_config().await {
+}
+
+async fn run_default() -> Result<(), String> {
+    let _ = aws::check_aws_profile();
+    // Only reads, write probes leave versions behind on versioned buckets
+    let checked = match aws::S3Settings::load().map(|settings| settings.bucket) {
+        Ok(Some(bucket)) => match aws::S3Store::connect(&bucket).await {
+            Ok(store) => store.check_list().await.map(|()| {
+                println!(
+                    "✅ Bucket {} is readable, run `data preflight` to check writes",
+                    bucket.cyan()
+                )
+            }),
+            Err(e) => Err(e),
+        },
+        Ok(None) => aws::check_aws_config().await,
+        // Falling back to the profile would hide what is wrong with the settings
+        Err(e) => Err(e),
+    };
+    if let Err(e) = checked {
         eprintln!("Failed to check AWS config: {}", e);
     }
     //if let Err(e) = aws::check_s3_deep_glacier().await {
     //    eprintln!("Failed to upload test data to bucket: {}", e);
     //}
     // Create examples.tar.gz for sandbox exploration
-    let tables = model::load_data_model();
+    let tables = model::load_schema()?.tables;
     model::generate_sandbox_example_random_files(&tables);
+    Ok(())
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Unable to read '{}': {}", path, e))
+}
+
+/// Look up a table from data.sql by name
+fn find_table<'a>(tables: &'a [sql::Table], name: &str) -> Result<&'a sql::Table, String> {
+    sql::Table::position(tables, name)?
+        .map(|i| &tables[i])
+        .ok_or_else(|| format!("No table named '{}' in data.sql", name))
+}
+
+fn run_cdc(args: &Args) -> Result<(), String> {
+    let tables = model::load_schema()?.tables;
+    let table = find_table(&tables, args.required(0, "table")?)?;
+    let key = match args.value("key") {
+        Some(key) => key.to_string(),
+        None => table
+            .primary_key()
+            .map(|col| col.name.clone())
+            .ok_or_else(|| format!("Table '{}' has no PRIMARY KEY, pass --key", table.name))?,
+    };
+
+    let defaults = model::CdcOptions::default();
+    let options = model::CdcOptions {
+        num_events: args.value_or("events", defaults.num_events)?,
+        update_ratio: args.value_or("updates", defaults.update_ratio)?,
+        delete_ratio: args.value_or("deletes", defaults.delete_ratio)?,
+    };
+    let format = args.value_or("format", model::FileFormat::Parquet)?;
+    let out = args.value("out").unwrap_or(".");
+
+    let stream = model::generate_cdc_stream(table, &key, &options)?;
+    model::write_cdc_stream(table, &stream, Path::new(out), format)?;
+    Ok(())
+}
+
+fn run_dirty(args: &Args) -> Result<(), String> {
+    let tables = model::load_schema()?.tables;
+    let table = find_table(&tables, args.required(0, "table")?)?;
+    let rules = match args.value("inject") {
+        Some(spec) => spec
+            .split(',')
+            .map(|rule| rule.trim().parse::<model::AnomalyRule>())
+            .collect::<Result<Vec<_>, _>>()?,
+        None => model::AnomalyRule::all_for(
+            table,
+            args.value_or("rate", model::AnomalyRule::DEFAULT_RATE)?,
+        )?,
+    };
+    let num_rows = args.value_or("rows", 1000usize)?;
+    let format = args.value_or("format", model::FileFormat::Parquet)?;
+    let out = args.value("out").unwrap_or(".");
+
+    let mut rows = model::generate_table_rows(table, num_rows)?;
+    let corruptions = model::inject_anomalies(table, &mut rows, &rules)?;
+    model::write_dirty_dataset(table, &rows, &corruptions, Path::new(out), format)?;
+    Ok(())
+}
+
+fn run_skew(args: &Args) -> Result<(), String> {
+    let tables = model::load_schema()?.tables;
+    let table = find_table(&tables, args.required(0, "table")?)?;
+    let column = args
+        .value("column")
+        .ok_or_else(|| "Missing required option --column".to_string())?;
+    let defaults = model::SkewOptions::default();
+    let options = model::SkewOptions {
+        hot_keys: args.value_or("hot-keys", defaults.hot_keys)?,
+        hot_fraction: args.value_or("hot-fraction", defaults.hot_fraction)?,
+        cardinality: args.value_or("cardinality", defaults.cardinality)?,
+    };
+    let num_rows = args.value_or("rows", 10000usize)?;
+    let format = args.value_or("format", model::FileFormat::Parquet)?;
+    let out = args.value("out").unwrap_or(".");
+
+    model::generate_skewed_dataset(
+        table,
+        &tables,
+        column,
+        num_rows,
+        &options,
+        Path::new(out),
+        format,
+    )?;
+    Ok(())
+}
+
+fn run_evolve(args: &Args) -> Result<(), String> {
+    let name = args.required(0, "table")?;
+    let current = model::load_schema()?.tables;
+
+    let (base, changes) = match (args.value("from"), args.value("changes")) {
+        (Some(from), None) => {
+            let old_tables = sql::parse_schema(&read_file(from)?)?.tables;
+            let new_tables = match args.value("to") {
+                Some(to) => sql::parse_schema(&read_file(to)?)?.tables,
+                None => current,
+            };
+            let old = find_table(&old_tables, name)?;
+            let new = find_table(&new_tables, name)?;
+            (old.clone(), evolution::diff_table(old, new))
+        }
+        (None, Some(changes)) => {
+            let base = find_table(&current, name)?.clone();
+            (base, sql::parse_alter_statements(&read_file(changes)?)?)
+        }
+        _ => return Err("Pass either --from <old.sql> or --changes <alter.sql>".to_string()),
+    };
+
+    let defaults = evolution::EvolutionOptions::default();
+    let options = evolution::EvolutionOptions {
+        partitions_per_version: args
+            .value_or("partitions-per-version", defaults.partitions_per_version)?,
+        rows_per_partition: args.value_or("rows", defaults.rows_per_partition)?,
+    };
+    let out = args.value("out").unwrap_or(".");
+
+    evolution::generate_evolution_dataset(&base, &changes, &options, Path::new(out))?;
+    Ok(())
+}
+
+fn run_inspect(args: &Args) -> Result<(), String> {
+    let path = Path::new(args.required(0, "path")?);
+    let preview_rows = args.value_or("rows", 5usize)?;
+    let files = inspect::find_parquet_files(path)?;
+    if files.is_empty() {
+        return Err(format!("No Parquet files found in '{}'", path.display()));
+    }
+
+    let reports = files
+        .iter()
+        .map(|file| inspect::inspect_file(file, preview_rows))
+        .collect::<Result<Vec<_>, _>>()?;
+    if args.has("json") {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    } else {
+        for report in &reports {
+            inspect::print_report(report);
+        }
+    }
+    Ok(())
+}
+
+fn run_infer(args: &Args) -> Result<(), String> {
+    args.required(0, "path")?;
+    let defaults = infer::InferOptions::default();
+    let delimiter = match args.value("delimiter") {
+        Some("\\t") | Some("tab") => b'\t',
+        Some(d) if d.len() == 1 => d.as_bytes()[0],
+        Some(d) => return Err(format!("Invalid value '{}' for --delimiter", d)),
+        None => defaults.delimiter,
+    };
+    let options = infer::InferOptions {
+        delimiter,
+        max_enum_values: args.value_or("enum-values", defaults.max_enum_values)?,
+    };
+
+    let tables = args
+        .positional
+        .iter()
+        .map(|path| infer::infer_table(Path::new(path), &options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data_sql = infer::to_data_sql(&tables, args.has("hints"));
+    match args.value("out") {
+        Some(out) => {
+            std::fs::write(out, data_sql).map_err(|e| format!("Error writing '{}': {}", out, e))?;
+            infer::print_summary(&tables);
+            println!("📝 data.sql written to '{}'", out.bold().green());
+        }
+        None => print!("{}", data_sql),
+    }
+    Ok(())
+}
+
+fn run_validate(args: &Args) -> Result<(), String> {
+    args.required(0, "path")?;
+    let tables = model::load_schema()?.tables;
+    let datasets = args
+        .positional
+        .iter()
+        .map(|path| {
+            let path = Path::new(path);
+            let name = match args.value("table") {
+                Some(name) => name.to_string(),
+                None if path.is_dir() => path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                None => path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            };
+            Ok((path.to_path_buf(), find_table(&tables, &name)?.clone()))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let reports = validate::validate_datasets(&datasets)?;
+    if args.has("json") {
+        let json: Vec<_> = reports.iter().map(|r| r.to_json()).collect();
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    } else {
+        for report in &reports {
+            report.print();
+        }
+    }
+
+    let violations: usize = reports.iter().map(|r| r.violations()).sum();
+    if violations > 0 {
+        return Err(format!("Validation failed with {} violations", violations));
+    }
+    println!("{}", "✅ Every dataset matches data.sql".green());
+    Ok(())
+}
+
+fn run_profile(args: &Args) -> Result<(), String> {
+    let path = Path::new(args.required(0, "path")?);
+    let defaults = profile::ProfileOptions::default();
+    let options = profile::ProfileOptions {
+        top_k: args.value_or("top", defaults.top_k)?,
+        bins: args.value_or("bins", defaults.bins)?,
+    };
+
+    let report = profile::profile_dataset(path, &options)?;
+    if args.has("json") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report.to_json()).unwrap()
+        );
+    } else {
+        report.print();
+    }
+    if let Some(html) = args.value("html") {
+        std::fs::write(html, report.to_html())
+            .map_err(|e| format!("Error writing '{}': {}", html, e))?;
+        println!("🌐 HTML report written to '{}'", html.bold().green());
+    }
+    Ok(())
+}
+
+fn run_diff(args: &Args) -> Result<(), String> {
+    let old_path = args.required(0, "old data.sql")?;
+    let old = sql::parse_schema(&read_file(old_path)?)?.tables;
+    let (new_path, new) = match args.positional.get(1) {
+        Some(path) => (path.as_str(), sql::parse_schema(&read_file(path)?)?.tables),
+        None => ("data.sql", model::load_schema()?.tables),
+    };
+
+    let diff = diff::diff_schemas(&old, &new)?;
+    if let Some(out) = args.value("out") {
+        std::fs::write(out, diff.migration_sql())
+            .map_err(|e| format!("Error writing '{}': {}", out, e))?;
+    }
+    if args.has("json") {
+        println!("{}", serde_json::to_string_pretty(&diff.to_json()).unwrap());
+    } else {
+        diff.print(old_path, new_path);
+        if let Some(out) = args.value("out") {
+            println!("📜 Migration written to '{}'", out.bold().green());
+        }
+    }
+    Ok(())
+}
+
+fn run_ddl(args: &Args) -> Result<(), String> {
+    let schema = model::load_schema()?;
+    let dialect = args.value_or("dialect", ddl::Dialect::Spark)?;
+    let options = ddl::DdlOptions {
+        location: args.value("location").map(String::from),
+        partitioned_by: args
+            .value("partition-by")
+            .map(|p| p.split(',').map(|c| c.trim().to_string()).collect())
+            .unwrap_or_default(),
+    };
+
+    let ddl = ddl::emit_ddl(&schema, dialect, &options)?;
+    match args.value("out") {
+        Some(out) => {
+            std::fs::write(out, ddl).map_err(|e| format!("Error writing '{}': {}", out, e))?;
+            println!(
+                "📜 {} DDL for {} tables written to '{}'",
+                dialect.as_str().bold().cyan(),
+                schema.tables.len().to_string().bold().cyan(),
+                out.bold().green()
+            );
+        }
+        None => print!("{}", ddl),
+    }
+    Ok(())
+}
+
+/// Formats written by `export --format all`, with the file each goes to
+const EXPORT_FILES: [(&str, &str); 7] = [
+    ("spark-json", "spark.json"),
+    ("scala", "Tables.scala"),
+    ("pyspark", "schemas.py"),
+    ("json-schema", "schema.json"),
+    ("avro", "avsc"),
+    ("proto", "tables.proto"),
+    ("rust", "tables.rs"),
+];
+
+/// Render tables in one export format. Per-table JSON formats print the bare
+/// schema for a single table and an object keyed by table name otherwise.
+fn export_schema(format: &str, tables: &[sql::Table]) -> Result<String, String> {
+    let per_table: fn(&sql::Table) -> Result<serde_json::Value, String> = match format {
+        "scala" => return Ok(spark::scala_case_classes(tables)),
+        "pyspark" => return Ok(spark::pyspark_struct_types(tables)),
+        "proto" => return Ok(interchange::proto_file(tables)),
+        "rust" => return codegen::rust_module(tables),
+        "spark-json" => |t| Ok(spark::struct_type_json(t)),
+        "json-schema" => |t| Ok(interchange::json_schema(t)),
+        "avro" => interchange::avro_schema,
+        other => return Err(format!("Unknown export format '{}'", other)),
+    };
+    Ok(match tables {
+        // A single Spark table prints exactly what DataType.fromJson accepts
+        [table] if format == "spark-json" => per_table(table)?.to_string(),
+        [table] => serde_json::to_string_pretty(&per_table(table)?).unwrap(),
+        _ => {
+            let schemas = tables
+                .iter()
+                .map(|t| Ok((t.qualified_name(), per_table(t)?)))
+                .collect::<Result<serde_json::Map<String, serde_json::Value>, String>>()?;
+            serde_json::to_string_pretty(&schemas).unwrap()
+        }
+    })
+}
+
+fn write_export(path: &Path, contents: &str) -> Result<(), String> {
+    std::fs::write(path, format!("{}\n", contents.trim_end()))
+        .map_err(|e| format!("Error writing '{}': {}", path.display(), e))
+}
+
+fn run_export(args: &Args) -> Result<(), String> {
+    let all_tables = model::load_schema()?.tables;
+    let tables = match args.value("table") {
+        Some(name) => vec![find_table(&all_tables, name)?.clone()],
+        None => all_tables,
+    };
+    let format = args
+        .value("format")
+        .ok_or_else(|| "Missing required option --format".to_string())?;
+
+    if format == "all" {
+        let dir = Path::new(
+            args.value("out")
+                .ok_or_else(|| "--format all needs --out <dir>".to_string())?,
+        );
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Error creating '{}': {}", dir.display(), e))?;
+        for (format, file) in EXPORT_FILES {
+            match format {
+                // One file per table, named like the tools expect
+                "json-schema" | "avro" => {
+                    for table in &tables {
+                        let name = format!("{}.{}", table.flat_name(), file);
+                        write_export(
+                            &dir.join(name),
+                            &export_schema(format, std::slice::from_ref(table))?,
+                        )?;
+                    }
+                }
+                _ => write_export(&dir.join(file), &export_schema(format, &tables)?)?,
+            }
+        }
+        println!(
+            "📤 Every schema format for {} tables written to '{}'",
+            tables.len().to_string().bold().cyan(),
+            dir.display().to_string().bold().green()
+        );
+        return Ok(());
+    }
+
+    let exported = export_schema(format, &tables)?;
+    match args.value("out") {
+        Some(out) => {
+            write_export(Path::new(out), &exported)?;
+            println!(
+                "📤 {} schema for {} tables written to '{}'",
+                format.bold().cyan(),
+                tables.len().to_string().bold().cyan(),
+                out.bold().green()
+            );
+        }
+        None => println!("{}", exported.trim_end()),
+    }
+    Ok(())
+}
+
+const MB: u64 = 1024 * 1024;
+
+/// `--part-size`, `--concurrency` and `--storage-class` for upload and sync
+fn upload_options(
+    args: &Args,
+    destination: &str,
+    journal: Option<std::path::PathBuf>,
+) -> Result<upload::UploadOptions, String> {
+    let defaults = upload::UploadOptions::default();
+    let part_size_mb = args.value_or("part-size", defaults.part_size / MB)?;
+    if part_size_mb < 5 {
+        return Err("--part-size must be at least 5 MB, the S3 minimum".to_string());
+    }
+    let storage_class = args
+        .value("storage-class")
+        .map(store::StorageClass::parse)
+        .transpose()?;
+    if storage_class.is_some() && !destination.starts_with("s3://") {
+        return Err("--storage-class only applies to s3:// destinations".to_string());
+    }
+    Ok(upload::UploadOptions {
+        concurrency: args.value_or("concurrency", defaults.concurrency)?,
+        part_size: part_size_mb * MB,
+        journal,
+        storage_class,
+        resume_completed: true,
+    })
+}
+
+async fn run_upload(args: &Args) -> Result<(), String> {
+    let generated = args.has("generate");
+    let (dir, destination) = if generated {
+        let dir = std::env::temp_dir().join(format!("lernspark-upload-{}", uuid::Uuid::new_v4()));
+        (dir, args.required(0, "destination")?)
+    } else {
+        let dir = Path::new(args.required(0, "dir")?).to_path_buf();
+        (dir, args.required(1, "destination")?)
+    };
+    let _cleanup = scopeguard::guard(generated, |generated| {
+        if generated {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+    });
+
+    if generated {
+        let tables = model::load_schema()?.tables;
+        upload::generate_dataset(
+            &tables,
+            args.value_or("rows", 1000usize)?,
+            args.value_or("partitions", 1usize)?,
+            &dir,
+        )?;
+    }
+
+    let dry_run = args.has("dry-run");
+    // The dry run prints the keys the real run writes, without connecting
+    let (store, prefix) = if dry_run {
+        (None, store::key_prefix(destination)?)
+    } else {
+        let (store, prefix) = store::open(destination).await?;
+        (Some(store), prefix)
+    };
+    let objects = upload::plan_upload(&dir, &prefix)?;
+    if objects.is_empty() {
+        return Err(format!("No files found in '{}'", dir.display()));
+    }
+
+    match store {
+        None => {
+            for object in &objects {
+                println!("  {} -> {}", object.path.display(), object.key.green());
+            }
+        }
+        Some(store) => {
+            // A generated dataset is deleted afterwards, so there is nothing to resume
+            let options = upload_options(
+                args,
+                destination,
+                (!generated).then(|| dir.join(upload::JOURNAL)),
+            )?;
+            let manifest = upload::manifest(&dir, destination, &objects);
+            upload::upload_objects(
+                store.clone(),
+                &objects,
+                &upload::manifest_key(&prefix),
+                &manifest,
+                &options,
+            )
+            .await?;
+            upload::verify_upload(store.as_ref(), &prefix, &objects, options.part_size).await?;
+        }
+    }
+    upload::print_summary(&objects, destination, dry_run);
+    Ok(())
+}
+
+async fn run_sync(args: &Args) -> Result<(), String> {
+    let dir = Path::new(args.required(0, "dir")?);
+    let destination = args.required(1, "destination")?;
+    let delete_extras = args.has("delete");
+    let mut options = upload_options(args, destination, Some(dir.join(upload::JOURNAL)))?;
+    // Only the parts of an interrupted multipart upload carry over, a file
+    // journaled as uploaded is in the plan because the store disagrees
+    options.resume_completed = false;
+
+    let (store, prefix) = store::open(destination).await?;
+    let objects = upload::plan_upload(dir, &prefix)?;
+    let remote = store.list(&upload::list_prefix(&prefix)).await?;
+    let manifest_key = upload::manifest_key(&prefix);
+    let plan = sync::plan_sync(&objects, &remote, options.part_size, &manifest_key)?;
+    plan.print(delete_extras);
+    if args.has("dry-run") || plan.is_empty(delete_extras) {
+        return Ok(());
+    }
+
+    let changed: Vec<upload::UploadObject> = plan.uploads.iter().map(|(_, o)| o.clone()).collect();
+    let manifest = upload::manifest(dir, destination, &objects);
+    upload::upload_objects(store.clone(), &changed, &manifest_key, &manifest, &options).await?;
+    if delete_extras {
+        for key in &plan.extras {
+            store.delete(key).await?;
+        }
+    }
+    upload::verify_upload(store.as_ref(), &prefix, &objects, options.part_size).await?;
+    println!(
+        "✅ {} is in sync with {}",
+        destination.bold().green(),
+        dir.display().to_string().bold()
+    );
+    Ok(())
+}
+
+async fn run_sample(args: &Args) -> Result<(), String> {
+    let source = args.required(0, "source")?;
+    let out = Path::new(args.required(1, "out-dir")?);
+    let fraction: Option<f64> = args
+        .value("fraction")
+        .map(|_| args.value_or("fraction", 1.0))
+        .transpose()?;
+    if fraction.is_some_and(|f| f <= 0.0 || f > 1.0) {
+        return Err("--fraction must be above 0 and at most 1".to_string());
+    }
+    let options = sample::SampleOptions {
+        filters: match args.value("partition") {
+            Some(spec) => sample::PartitionFilter::parse_list(spec)?,
+            None => Vec::new(),
+        },
+        files: args
+            .value("files")
+            .map(|_| args.value_or("files", 0))
+            .transpose()?,
+        fraction,
+    };
+
+    let (store, prefix) = store::open(source).await?;
+    let listed = store.list(&upload::list_prefix(&prefix)).await?;
+    let objects = sample::select(&listed, &prefix, &options);
+    if objects.is_empty() {
+        return Err(format!("No files of '{}' match the sample", source));
+    }
+
+    let mut rows = std::collections::BTreeMap::new();
+    let mut bytes = 0;
+    for object in &objects {
+        let path = out.join(&object.relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Error creating '{}': {}", parent.display(), e))?;
+        }
+        let fetched = match fraction {
+            Some(fraction) if object.table.is_some() => {
+                let (total, kept, fetched) = sample::sample_parquet(
+                    store.as_ref(),
+                    &object.key,
+                    object.size,
+                    fraction,
+                    &path,
+                )
+                .await?;
+                rows.insert(object.relative.clone(), (total, kept));
+                fetched
+            }
+            _ => {
+                let body = store.get(&object.key).await?;
+                std::fs::write(&path, &body)
+                    .map_err(|e| format!("Error writing '{}': {}", path.display(), e))?;
+                body.len() as u64
+            }
+        };
+        bytes += fetched;
+        println!(
+            "  ⬇️  {} ({} downloaded)",
+            object.relative,
+            upload::human_bytes(fetched)
+        );
+    }
+
+    let description = sample::describe(source, &options, &objects, &rows);
+    let path = out.join(sample::SAMPLE);
+    std::fs::write(&path, serde_json::to_string_pretty(&description).unwrap())
+        .map_err(|e| format!("Error writing '{}': {}", path.display(), e))?;
+    let (total, kept) = rows
+        .values()
+        .fold((0, 0), |(total, kept), (t, k)| (total + t, kept + k));
+    let rows = if fraction.is_some() {
+        format!(", {} of {} rows", kept, total)
+    } else {
+        String::new()
+    };
+    println!(
+        "🧪 Sampled {} files ({} downloaded{}) of {} into '{}'",
+        objects.len().to_string().bold().green(),
+        upload::human_bytes(bytes),
+        rows,
+        source.bold(),
+        out.display().to_string().bold().green()
+    );
+    Ok(())
+}
+
+async fn run_provision(args: &Args) -> Result<(), String> {
+    let path = Path::new(args.required(0, "config.json")?);
+    let check_only = args.has("check");
+    let config = provision::ProvisionConfig::load(path)?;
+    let desired = config.desired(&aws::default_region().await?)?;
+
+    let mut drifted = 0;
+    for (name, wanted) in &desired {
+        let store = aws::S3Store::connect_in(name, &wanted.region).await?;
+        let (actual, created) = match store.bucket_state().await? {
+            Some(actual) => (actual, false),
+            None if check_only => {
+                println!("🪣 {} {}", name.bold(), "is missing".red());
+                drifted += 1;
+                continue;
+            }
+            // New buckets come with defaults such as blocked public access,
+            // so compare against what S3 actually created
+            None => {
+                store.create_bucket(&wanted.region).await?;
+                let created = store
+                    .bucket_state()
+                    .await?
+                    .ok_or_else(|| format!("Bucket '{}' is not visible after creating it", name))?;
+                (created, true)
+            }
+        };
+        let drifts = provision::drift(wanted, &actual);
+        if created {
+            println!(
+                "🪣 {} created in {}",
+                name.bold().green(),
+                wanted.region.cyan()
+            );
+        } else if drifts.is_empty() {
+            println!("🪣 {} ✅ matches the config", name.bold().green());
+            continue;
+        } else {
+            println!(
+                "🪣 {} has {} settings that drifted",
+                name.bold().yellow(),
+                drifts.len()
+            );
+            provision::print_drift(&drifts);
+            drifted += 1;
+        }
+        if check_only {
+            continue;
+        }
+        store.apply_bucket_state(wanted, &actual, &drifts).await?;
+        if drifts
+            .iter()
+            .any(|d| d.setting == provision::Setting::Region)
+        {
+            println!(
+                "    {} buckets cannot move region, recreate it to fix",
+                "⚠️".yellow()
+            );
+        }
+        if !created {
+            println!("    🔧 settings applied");
+        }
+    }
+
+    if check_only && drifted > 0 {
+        return Err(format!(
+            "{} of {} buckets differ from '{}'",
+            drifted,
+            desired.len(),
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+async fn run_restore(args: &Args) -> Result<(), String> {
+    let source = args.required(0, "s3://bucket/prefix")?;
+    let (bucket, prefix) = upload::parse_s3_uri(source)?;
+    let defaults = restore::RestoreOptions::default();
+    let options = restore::RestoreOptions {
+        days: args.value_or("days", defaults.days)?,
+        tier: match args.value("tier") {
+            Some(tier) => restore::parse_tier(tier)?,
+            None => defaults.tier,
+        },
+        wait: !args.has("no-wait"),
+        interval: std::time::Duration::from_secs(
+            args.value_or("interval", defaults.interval.as_secs())?,
+        ),
+        concurrency: args.value_or("concurrency", defaults.concurrency)?,
+    };
+    if options.days < 1 {
+        return Err("--days must be at least 1".to_string());
+    }
+    let store = std::sync::Arc::new(aws::S3Store::connect(&bucket).await?);
+    restore::restore_dataset(store, &prefix, &options).await
+}
+
+async fn run_preflight(args: &Args) -> Result<(), String> {
+    let (bucket, prefix) = match args.positional.first() {
+        Some(uri) => upload::parse_s3_uri(uri)?,
+        None => match aws::S3Settings::load()?.bucket {
+            Some(bucket) => (bucket, String::new()),
+            None => {
+                return Err(
+                    "No bucket to check, pass s3://bucket/prefix or set LERNSPARK_S3_BUCKET"
+                        .to_string(),
+                )
+            }
+        },
+    };
+    preflight(&bucket, &prefix).await
+}
+
+/// Probe the actions lernspark needs on `bucket` and print a policy for the denied ones
+async fn preflight(bucket: &str, prefix: &str) -> Result<(), String> {
+    let store = aws::S3Store::connect(bucket).await?;
+    println!(
+        "🛫 Checking the S3 actions lernspark needs on {}",
+        format!("s3://{}/{}", bucket, prefix).bold()
+    );
+    let checks = store.preflight(prefix).await;
+    preflight::print_table(&checks);
+
+    let denied = preflight::denied(&checks);
+    if !denied.is_empty() {
+        let policy = preflight::minimal_policy(bucket, prefix, &denied);
+        println!("📜 A minimal IAM policy granting what is missing:");
+        println!("{}", serde_json::to_string_pretty(&policy).unwrap());
+        println!(
+            "{}",
+            "ℹ️  It covers data transfer and creating the bucket only, provision's bucket \
+             settings and restore need their own s3:Put*/Get* and s3:RestoreObject grants"
+                .dimmed()
+        );
+        return Err(format!("{} actions are denied", denied.len()));
+    }
+    let unknown = checks
+        .iter()
+        .filter(|c| c.outcome != preflight::Outcome::Allowed)
+        .count();
+    if unknown > 0 {
+        return Err(format!("{} probes could not tell", unknown));
+    }
+    println!("✅ All {} probes passed", checks.len());
+    Ok(())
 }
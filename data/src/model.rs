@@ -1,6 +1,6 @@
 //! Data model based off data.model created by the user
 //! during initialization of lernspark
-use chrono::NaiveDate;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
 use colored::Colorize;
 use fake::faker::{
     address::en::*, color::en::*, company::en::*, creditcard::en::*, internet::en::*, job::en::*,
@@ -9,9 +9,12 @@ use fake::faker::{
 use fake::Fake;
 use rand::Rng;
 use regex::Regex;
+use serde_json::json;
 
 use std::fs::File;
-use std::path::Path;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 
 use tar::Builder;
@@ -23,24 +26,40 @@ use parquet::arrow::ArrowWriter;
 use parquet::file::properties::WriterProperties;
 use parquet::file::reader::FileReader;
 use parquet::file::reader::SerializedFileReader;
-use parquet::record::RowAccessor;
 
-use arrow::array::{ArrayRef, BooleanArray, Date32Array, Float32Array, Int32Array, StringArray};
-use arrow::datatypes::{DataType, Field, Schema}; // Make sure this Schema is from the correct crate
+use arrow::array::{
+    Array, ArrayRef, BooleanArray, Date32Array, Date64Array, Float32Array, Float64Array,
+    Int16Array, Int32Array, Int64Array, Int8Array, LargeStringArray, StringArray,
+    TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
+    TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit}; // Make sure this Schema is from the correct crate
 use arrow::record_batch::RecordBatch; // Ensure this is the RecordBatch expected by parquet
 
-use crate::sql::{self, DataType as SqlDataType, Table};
+use arrow::util::display::array_value_to_string;
+
+use crate::sql::{self, Column, DataType as SqlDataType, Table};
+
+/// Load data.sql with its indexes and views
+pub fn load_schema() -> Result<sql::Schema, String> {
+    let data_sql_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data.sql");
+    let content = std::fs::read_to_string(&data_sql_path)
+        .map_err(|e| format!("Error reading '{}': {}", data_sql_path.display(), e))?;
+    sql::parse_schema(&content).map_err(|e| format!("Invalid '{}': {}", data_sql_path.display(), e))
+}
 
-/// Load Schema from data.sql
-pub fn load_data_model() -> Vec<Table> {
-    // Get the crate root directory
-    let crate_root = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+/// Directory for a table's files under `base`, one level per namespace part,
+/// so `analytics.events` is written to `<base>/analytics/events...`
+pub fn table_dir(base: &Path, table: &Table) -> PathBuf {
+    table
+        .namespace
+        .iter()
+        .fold(base.to_path_buf(), |dir, part| dir.join(file_name(part)))
+}
 
-    // Define the path to data.sql starting from root
-    let data_sql_path = Path::new(&crate_root).join("data.sql");
-    let data_sql_content =
-        std::fs::read_to_string(&data_sql_path).expect("Unable to read data.sql file");
-    sql::parse_sql_file(&data_sql_content)
+/// A name usable as a single path component
+pub fn file_name(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
 }
 
 pub fn generate_sandbox_example_random_files(tables: &Vec<Table>) {
@@ -58,12 +77,14 @@ pub fn generate_sandbox_example_random_files(tables: &Vec<Table>) {
         let mut tar_builder = Builder::new(&mut gz_encoder);
         // Iterate over each table and create a random Parquet file
         for table in tables {
-            let file_path = format!("{}.parquet", table.name);
+            let file_path = format!("{}.parquet", file_name(&table.qualified_name()));
             // Create a random Parquet file for the table
             create_random_parquet_from_datasql(&file_path, table);
-            // Add the Parquet file to the tar archive
+            // Add the Parquet file to the tar archive, namespaces as directories
+            let archive_path =
+                table_dir(Path::new(""), table).join(format!("{}.parquet", file_name(&table.name)));
             tar_builder
-                .append_file(&file_path, &mut File::open(&file_path).unwrap())
+                .append_file(&archive_path, &mut File::open(&file_path).unwrap())
                 .unwrap();
             // Remove the individual Parquet file
             std::fs::remove_file(&file_path).unwrap();
@@ -86,18 +107,32 @@ pub fn generate_sandbox_example_random_files(tables: &Vec<Table>) {
 }
 
 /// Mapping between SQL and Parquet structs
-fn map_sql_to_arrow_type(sql_type: &SqlDataType) -> DataType {
+pub fn map_sql_to_arrow_type(sql_type: &SqlDataType) -> DataType {
     match sql_type {
         SqlDataType::Int(_) => DataType::Int32,
+        SqlDataType::BigInt(_) => DataType::Int64,
         SqlDataType::Float(_) => DataType::Float32,
+        SqlDataType::Double(_) => DataType::Float64,
         SqlDataType::String(_) => DataType::Utf8,
         SqlDataType::DateTime(_) => DataType::Date32,
         SqlDataType::UUID(_) => DataType::Utf8,
         SqlDataType::Boolean(_) => DataType::Boolean,
         SqlDataType::VarChar(_) => DataType::Utf8,
+        SqlDataType::Enum(_) => DataType::Utf8,
     }
 }
 
+/// Arrow schema for a table, every column nullable
+fn arrow_schema(table: &Table) -> Schema {
+    Schema::new(
+        table
+            .columns
+            .iter()
+            .map(|col| Field::new(&col.name, map_sql_to_arrow_type(&col.data_type), true))
+            .collect(),
+    )
+}
+
 /// Function to create a random Parquet data file using Arrow and Parquet APIs
 fn create_random_parquet_file(file_path: &str) {
     let schema = Arc::new(Schema::new(vec![
@@ -258,13 +293,7 @@ fn generate_fake_string_data(
 
 /// Function to create a random Parquet data file using Arrow and Parquet APIs
 fn create_random_parquet_from_datasql(file_path: &str, table: &Table) {
-    let schema = Arc::new(Schema::new(
-        table
-            .columns
-            .iter()
-            .map(|col| Field::new(&col.name, map_sql_to_arrow_type(&col.data_type), true))
-            .collect(),
-    ));
+    let schema = Arc::new(arrow_schema(table));
 
     let file = File::create(Path::new(file_path)).unwrap();
     let props = WriterProperties::builder().build();
@@ -290,6 +319,13 @@ fn create_random_parquet_from_datasql(file_path: &str, table: &Table) {
                 }
                 arrays.push(Arc::new(Int32Array::from(data)));
             }
+            SqlDataType::BigInt(_) => {
+                let mut data = Vec::with_capacity(num_rows);
+                for _ in 0..num_rows {
+                    data.push(rand::thread_rng().gen_range(0..=1_000_000));
+                }
+                arrays.push(Arc::new(Int64Array::from(data)));
+            }
             SqlDataType::Float(_) => {
                 let mut data = Vec::with_capacity(num_rows);
                 for _ in 0..num_rows {
@@ -297,6 +333,13 @@ fn create_random_parquet_from_datasql(file_path: &str, table: &Table) {
                 }
                 arrays.push(Arc::new(Float32Array::from(data)));
             }
+            SqlDataType::Double(_) => {
+                let mut data = Vec::with_capacity(num_rows);
+                for _ in 0..num_rows {
+                    data.push(rand::thread_rng().gen_range(0.0..=1_000_000.0));
+                }
+                arrays.push(Arc::new(Float64Array::from(data)));
+            }
             SqlDataType::String(_) => {
                 let fake_data = generate_fake_string_data(&col.name, num_rows, None);
                 arrays.push(Arc::new(StringArray::from(fake_data)));
@@ -305,6 +348,13 @@ fn create_random_parquet_from_datasql(file_path: &str, table: &Table) {
                 let fake_data = generate_fake_string_data(&col.name, num_rows, Some(*size));
                 arrays.push(Arc::new(StringArray::from(fake_data)));
             }
+            SqlDataType::Enum(values) => {
+                let mut data = Vec::with_capacity(num_rows);
+                for _ in 0..num_rows {
+                    data.push(values[rand::thread_rng().gen_range(0..values.len())].clone());
+                }
+                arrays.push(Arc::new(StringArray::from(data)));
+            }
             SqlDataType::DateTime(_) => {
                 let mut data = Vec::with_capacity(num_rows);
                 for _ in 0..num_rows {
@@ -341,40 +391,1257 @@ fn create_random_parquet_from_datasql(file_path: &str, table: &Table) {
         )
     );
     println!("🔍 Example values from the Parquet file:");
-    print_example_values(file_path, &schema, 1);
+    print_example_values(file_path, 1);
 }
 
-fn print_example_values(file_path: &str, schema: &Schema, num_examples: usize) {
+fn print_example_values(file_path: &str, num_examples: usize) {
     let file = File::open(file_path).unwrap();
     let reader = SerializedFileReader::new(file).unwrap();
-    let mut iter = reader.get_row_iter(None).unwrap();
-
-    for _ in 0..num_examples {
-        if let Some(row) = iter.next() {
-            for i in 0..row.len() {
-                let column_name = schema.field(i).name();
-                let value = match schema.field(i).data_type() {
-                    DataType::Int32 => row.get_int(i).map(|v| v.to_string()).unwrap_or_default(),
-                    DataType::Float32 => {
-                        row.get_float(i).map(|v| v.to_string()).unwrap_or_default()
-                    }
-                    DataType::Utf8 => row.get_string(i).map(|v| v.to_string()).unwrap_or_default(),
-                    DataType::Date32 => {
-                        let date = row.get_int(i).unwrap_or_default();
-                        NaiveDate::from_num_days_from_ce_opt(date as i32)
-                            .map(|d| d.format("%Y-%m-%d").to_string())
-                            .unwrap_or_default()
+    let iter = reader.get_row_iter(None).unwrap();
+
+    for row in iter.take(num_examples) {
+        for (column_name, field) in row.get_column_iter() {
+            println!(
+                "  {}: {}",
+                column_name.bold().cyan(),
+                field.to_string().yellow()
+            );
+        }
+        println!();
+    }
+}
+
+/// A single generated cell, used where rows are built and mutated one at a
+/// time (CDC streams) instead of column by column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Str(String),
+    /// Days since the UNIX epoch, matching Arrow's Date32
+    Date(i32),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Int(v) => json!(v),
+            Value::Long(v) => json!(v),
+            Value::Float(v) => json!(v),
+            Value::Double(v) => json!(v),
+            Value::Str(v) => json!(v),
+            Value::Date(days) => json!(date32_to_string(*days)),
+            Value::Bool(v) => json!(v),
+            Value::Null => serde_json::Value::Null,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Long(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::Double(v) => write!(f, "{}", v),
+            Value::Str(v) => write!(f, "{}", v),
+            Value::Date(days) => write!(f, "{}", date32_to_string(*days)),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+fn date32_to_string(days: i32) -> String {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    (epoch + Duration::days(days as i64))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// Output format for generated row streams
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Parquet,
+    JsonLines,
+}
+
+impl FileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::Parquet => "parquet",
+            FileFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl FromStr for FileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "parquet" => Ok(FileFormat::Parquet),
+            "jsonl" | "json" | "jsonlines" => Ok(FileFormat::JsonLines),
+            other => Err(format!("Unknown file format '{}'", other)),
+        }
+    }
+}
+
+/// Random values for a column, drawn from the same distributions used by
+/// `create_random_parquet_from_datasql`
+fn random_column_values(col: &Column, num_rows: usize) -> Vec<Value> {
+    let mut rng = rand::thread_rng();
+    match &col.data_type {
+        SqlDataType::Int(_) => (0..num_rows)
+            .map(|_| Value::Int(rng.gen_range(0..=100)))
+            .collect(),
+        SqlDataType::BigInt(_) => (0..num_rows)
+            .map(|_| Value::Long(rng.gen_range(0..=1_000_000)))
+            .collect(),
+        SqlDataType::Float(_) => (0..num_rows)
+            .map(|_| Value::Float(rng.gen_range(0.0..=100.0)))
+            .collect(),
+        SqlDataType::Double(_) => (0..num_rows)
+            .map(|_| Value::Double(rng.gen_range(0.0..=1_000_000.0)))
+            .collect(),
+        SqlDataType::String(_) => generate_fake_string_data(&col.name, num_rows, None)
+            .into_iter()
+            .map(Value::Str)
+            .collect(),
+        SqlDataType::VarChar(size) => generate_fake_string_data(&col.name, num_rows, Some(*size))
+            .into_iter()
+            .map(Value::Str)
+            .collect(),
+        SqlDataType::Enum(values) => (0..num_rows)
+            .map(|_| Value::Str(values[rng.gen_range(0..values.len())].clone()))
+            .collect(),
+        SqlDataType::DateTime(_) => (0..num_rows)
+            .map(|_| Value::Date(rng.gen_range(0..=100)))
+            .collect(),
+        SqlDataType::UUID(_) => (0..num_rows)
+            .map(|_| Value::Str(uuid::Uuid::new_v4().to_string()))
+            .collect(),
+        SqlDataType::Boolean(_) => (0..num_rows)
+            .map(|_| Value::Bool(rng.gen_bool(0.5)))
+            .collect(),
+    }
+}
+
+/// Build a nullable Arrow array of the column's type from generated values
+fn values_to_array(sql_type: &SqlDataType, values: &[Value]) -> ArrayRef {
+    match sql_type {
+        SqlDataType::Int(_) => Arc::new(Int32Array::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Int(i) => Some(*i),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        SqlDataType::BigInt(_) => Arc::new(Int64Array::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Long(i) => Some(*i),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        SqlDataType::Float(_) => Arc::new(Float32Array::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Float(f) => Some(*f),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        SqlDataType::Double(_) => Arc::new(Float64Array::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Double(f) => Some(*f),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        SqlDataType::DateTime(_) => Arc::new(Date32Array::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Date(d) => Some(*d),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        SqlDataType::Boolean(_) => Arc::new(BooleanArray::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Bool(b) => Some(*b),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        SqlDataType::String(_)
+        | SqlDataType::VarChar(_)
+        | SqlDataType::UUID(_)
+        | SqlDataType::Enum(_) => Arc::new(StringArray::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Str(s) => Some(s.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+    }
+}
+
+/// Read one cell of an Arrow array back into a `Value`. Narrow integer types
+/// widen to `Long`, timestamps become dates and anything else falls back to
+/// its display string.
+pub fn array_value(array: &dyn Array, row: usize) -> Value {
+    if array.is_null(row) {
+        return Value::Null;
+    }
+    let any = array.as_any();
+    match array.data_type() {
+        DataType::Int8 => Value::Long(any.downcast_ref::<Int8Array>().unwrap().value(row) as i64),
+        DataType::Int16 => Value::Long(any.downcast_ref::<Int16Array>().unwrap().value(row) as i64),
+        DataType::Int32 => Value::Int(any.downcast_ref::<Int32Array>().unwrap().value(row)),
+        DataType::Int64 => Value::Long(any.downcast_ref::<Int64Array>().unwrap().value(row)),
+        DataType::UInt8 => Value::Long(any.downcast_ref::<UInt8Array>().unwrap().value(row) as i64),
+        DataType::UInt16 => {
+            Value::Long(any.downcast_ref::<UInt16Array>().unwrap().value(row) as i64)
+        }
+        DataType::UInt32 => {
+            Value::Long(any.downcast_ref::<UInt32Array>().unwrap().value(row) as i64)
+        }
+        DataType::UInt64 => {
+            Value::Long(any.downcast_ref::<UInt64Array>().unwrap().value(row) as i64)
+        }
+        DataType::Float32 => Value::Float(any.downcast_ref::<Float32Array>().unwrap().value(row)),
+        DataType::Float64 => Value::Double(any.downcast_ref::<Float64Array>().unwrap().value(row)),
+        DataType::Boolean => Value::Bool(any.downcast_ref::<BooleanArray>().unwrap().value(row)),
+        DataType::Utf8 => Value::Str(
+            any.downcast_ref::<StringArray>()
+                .unwrap()
+                .value(row)
+                .to_string(),
+        ),
+        DataType::LargeUtf8 => Value::Str(
+            any.downcast_ref::<LargeStringArray>()
+                .unwrap()
+                .value(row)
+                .to_string(),
+        ),
+        DataType::Date32 => Value::Date(any.downcast_ref::<Date32Array>().unwrap().value(row)),
+        DataType::Date64 => {
+            Value::Date((any.downcast_ref::<Date64Array>().unwrap().value(row) / 86_400_000) as i32)
+        }
+        DataType::Timestamp(unit, _) => {
+            let per_day = match unit {
+                TimeUnit::Second => 86_400,
+                TimeUnit::Millisecond => 86_400_000,
+                TimeUnit::Microsecond => 86_400_000_000,
+                TimeUnit::Nanosecond => 86_400_000_000_000,
+            };
+            let raw = match unit {
+                TimeUnit::Second => any
+                    .downcast_ref::<TimestampSecondArray>()
+                    .unwrap()
+                    .value(row),
+                TimeUnit::Millisecond => any
+                    .downcast_ref::<TimestampMillisecondArray>()
+                    .unwrap()
+                    .value(row),
+                TimeUnit::Microsecond => any
+                    .downcast_ref::<TimestampMicrosecondArray>()
+                    .unwrap()
+                    .value(row),
+                TimeUnit::Nanosecond => any
+                    .downcast_ref::<TimestampNanosecondArray>()
+                    .unwrap()
+                    .value(row),
+            };
+            Value::Date(raw.div_euclid(per_day) as i32)
+        }
+        _ => {
+            let array = arrow::array::make_array(array.data().clone());
+            Value::Str(array_value_to_string(&array, row).unwrap_or_default())
+        }
+    }
+}
+
+fn write_record_batch(path: &Path, batch: &RecordBatch) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("Error creating '{}': {}", path.display(), e))?;
+    let props = WriterProperties::builder().build();
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))
+        .map_err(|e| format!("Error opening Parquet writer: {}", e))?;
+    writer
+        .write(batch)
+        .map_err(|e| format!("Error writing '{}': {}", path.display(), e))?;
+    writer
+        .close()
+        .map_err(|e| format!("Error closing '{}': {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Write rows of a table as a Parquet file using the table's Arrow schema
+fn write_rows_parquet(path: &Path, table: &Table, rows: &[Vec<Value>]) -> Result<(), String> {
+    let schema = Arc::new(arrow_schema(table));
+    let arrays: Vec<ArrayRef> = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(c, col)| {
+            let values: Vec<Value> = rows.iter().map(|row| row[c].clone()).collect();
+            values_to_array(&col.data_type, &values)
+        })
+        .collect();
+    let batch = RecordBatch::try_new(schema, arrays).map_err(|e| e.to_string())?;
+    write_record_batch(path, &batch)
+}
+
+/// Write rows of a table in the requested format
+pub fn write_rows(
+    path: &Path,
+    table: &Table,
+    rows: &[Vec<Value>],
+    format: FileFormat,
+) -> Result<(), String> {
+    match format {
+        FileFormat::Parquet => write_rows_parquet(path, table, rows),
+        FileFormat::JsonLines => {
+            write_json_lines(path, rows.iter().map(|row| row_to_json(table, row)))
+        }
+    }
+}
+
+fn row_to_json(table: &Table, row: &[Value]) -> serde_json::Value {
+    let object: serde_json::Map<String, serde_json::Value> = table
+        .columns
+        .iter()
+        .zip(row)
+        .map(|(col, value)| (col.name.clone(), value.to_json()))
+        .collect();
+    serde_json::Value::Object(object)
+}
+
+fn write_json_lines<I>(path: &Path, lines: I) -> Result<(), String>
+where
+    I: IntoIterator<Item = serde_json::Value>,
+{
+    let file =
+        File::create(path).map_err(|e| format!("Error creating '{}': {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    for line in lines {
+        writeln!(writer, "{}", line)
+            .map_err(|e| format!("Error writing '{}': {}", path.display(), e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Error writing '{}': {}", path.display(), e))
+}
+
+/// The kind of change recorded by a CDC event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CdcOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl CdcOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CdcOp::Insert => "INSERT",
+            CdcOp::Update => "UPDATE",
+            CdcOp::Delete => "DELETE",
+        }
+    }
+}
+
+/// One change to a row. Inserts have no before image and deletes have no after image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CdcEvent {
+    pub op: CdcOp,
+    pub commit_ts: NaiveDateTime,
+    pub before: Option<Vec<Value>>,
+    pub after: Option<Vec<Value>>,
+}
+
+/// Options controlling the mix of operations in a CDC stream.
+/// Whatever is left after updates and deletes is inserts.
+#[derive(Debug, Clone)]
+pub struct CdcOptions {
+    pub num_events: usize,
+    pub update_ratio: f64,
+    pub delete_ratio: f64,
+}
+
+impl Default for CdcOptions {
+    fn default() -> Self {
+        CdcOptions {
+            num_events: 1000,
+            update_ratio: 0.3,
+            delete_ratio: 0.1,
+        }
+    }
+}
+
+/// A CDC log and the snapshot a correct MERGE of that log must produce
+#[derive(Debug)]
+pub struct CdcStream {
+    pub events: Vec<CdcEvent>,
+    pub snapshot: Vec<Vec<Value>>,
+}
+
+/// Unique key values for the `seq`-th inserted row
+fn primary_key_value(col: &Column, seq: usize) -> Result<Value, String> {
+    match &col.data_type {
+        SqlDataType::Int(_) => i32::try_from(seq + 1)
+            .map(Value::Int)
+            .map_err(|_| "Too many rows for an INT primary key".to_string()),
+        SqlDataType::BigInt(_) => Ok(Value::Long(seq as i64 + 1)),
+        SqlDataType::UUID(_) => Ok(Value::Str(uuid::Uuid::new_v4().to_string())),
+        SqlDataType::String(_) => Ok(Value::Str(format!("{}-{}", col.name, seq + 1))),
+        SqlDataType::VarChar(size) => {
+            let key = (seq + 1).to_string();
+            if key.len() > *size {
+                Err(format!(
+                    "VARCHAR({}) primary key '{}' is too short for {} rows",
+                    size,
+                    col.name,
+                    seq + 1
+                ))
+            } else {
+                Ok(Value::Str(key))
+            }
+        }
+        other => Err(format!(
+            "Unsupported primary key type {:?} for column '{}'",
+            other, col.name
+        )),
+    }
+}
+
+/// Generate a CDC log of inserts, updates and deletes for a table keyed on
+/// `primary_key`, along with the final snapshot after replaying it.
+pub fn generate_cdc_stream(
+    table: &Table,
+    primary_key: &str,
+    options: &CdcOptions,
+) -> Result<CdcStream, String> {
+    let key_index = table
+        .columns
+        .iter()
+        .position(|col| col.name.eq_ignore_ascii_case(primary_key))
+        .ok_or_else(|| format!("Table '{}' has no column '{}'", table.name, primary_key))?;
+    if options.update_ratio < 0.0
+        || options.delete_ratio < 0.0
+        || options.update_ratio + options.delete_ratio > 1.0
+    {
+        return Err("Update and delete ratios must be positive and sum to at most 1".to_string());
+    }
+
+    let num_events = options.num_events;
+    let pools: Vec<Vec<Value>> = table
+        .columns
+        .iter()
+        .map(|col| random_column_values(col, num_events))
+        .collect();
+
+    let mut rng = rand::thread_rng();
+    // Every inserted row in insertion order, None once deleted
+    let mut rows: Vec<Option<Vec<Value>>> = Vec::new();
+    let mut live: Vec<usize> = Vec::new();
+    let mut events = Vec::with_capacity(num_events);
+    let mut commit_ts = Utc::now().naive_utc();
+
+    for i in 0..num_events {
+        commit_ts += Duration::milliseconds(rng.gen_range(1..=1000));
+        let roll: f64 = rng.gen();
+        let op = if live.is_empty() {
+            CdcOp::Insert
+        } else if roll < options.delete_ratio {
+            CdcOp::Delete
+        } else if roll < options.delete_ratio + options.update_ratio && table.columns.len() > 1 {
+            CdcOp::Update
+        } else {
+            CdcOp::Insert
+        };
+
+        let event = match op {
+            CdcOp::Insert => {
+                let mut row: Vec<Value> = pools.iter().map(|pool| pool[i].clone()).collect();
+                row[key_index] = primary_key_value(&table.columns[key_index], rows.len())?;
+                live.push(rows.len());
+                rows.push(Some(row.clone()));
+                CdcEvent {
+                    op,
+                    commit_ts,
+                    before: None,
+                    after: Some(row),
+                }
+            }
+            CdcOp::Update => {
+                let slot = live[rng.gen_range(0..live.len())];
+                let before = rows[slot].clone().unwrap();
+                let mut after = before.clone();
+                // Always change one column so the update is never a no-op in intent
+                let forced = loop {
+                    let c = rng.gen_range(0..after.len());
+                    if c != key_index {
+                        break c;
                     }
-                    DataType::Boolean => row.get_bool(i).map(|v| v.to_string()).unwrap_or_default(),
-                    _ => "Unsupported data type".to_string(),
                 };
-                println!("  {}: {}", column_name.bold().cyan(), value.yellow());
+                for (c, pool) in pools.iter().enumerate() {
+                    if c != key_index && (c == forced || rng.gen_bool(0.5)) {
+                        after[c] = pool[i].clone();
+                    }
+                }
+                rows[slot] = Some(after.clone());
+                CdcEvent {
+                    op,
+                    commit_ts,
+                    before: Some(before),
+                    after: Some(after),
+                }
+            }
+            CdcOp::Delete => {
+                let slot = live.swap_remove(rng.gen_range(0..live.len()));
+                let before = rows[slot].take().unwrap();
+                CdcEvent {
+                    op,
+                    commit_ts,
+                    before: Some(before),
+                    after: None,
+                }
+            }
+        };
+        events.push(event);
+    }
+
+    Ok(CdcStream {
+        events,
+        snapshot: rows.into_iter().flatten().collect(),
+    })
+}
+
+/// Write the CDC log and expected snapshot into `output_dir` as
+/// `<table>_cdc.<ext>` and `<table>_snapshot.<ext>`.
+///
+/// In Parquet the before and after images are flattened into
+/// `before_<column>` and `after_<column>` columns next to `op` and `commit_ts`.
+pub fn write_cdc_stream(
+    table: &Table,
+    stream: &CdcStream,
+    output_dir: &Path,
+    format: FileFormat,
+) -> Result<(PathBuf, PathBuf), String> {
+    let output_dir = &table_dir(output_dir, table);
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Error creating '{}': {}", output_dir.display(), e))?;
+    let name = file_name(&table.name);
+    let log_path = output_dir.join(format!("{}_cdc.{}", name, format.extension()));
+    let snapshot_path = output_dir.join(format!("{}_snapshot.{}", name, format.extension()));
+
+    match format {
+        FileFormat::Parquet => {
+            let mut fields = vec![
+                Field::new("op", DataType::Utf8, false),
+                Field::new(
+                    "commit_ts",
+                    DataType::Timestamp(TimeUnit::Millisecond, None),
+                    false,
+                ),
+            ];
+            let mut arrays: Vec<ArrayRef> = vec![
+                Arc::new(StringArray::from(
+                    stream
+                        .events
+                        .iter()
+                        .map(|e| e.op.as_str())
+                        .collect::<Vec<_>>(),
+                )),
+                Arc::new(TimestampMillisecondArray::from(
+                    stream
+                        .events
+                        .iter()
+                        .map(|e| e.commit_ts.and_utc().timestamp_millis())
+                        .collect::<Vec<_>>(),
+                )),
+            ];
+            for (prefix, image) in [("before", false), ("after", true)] {
+                for (c, col) in table.columns.iter().enumerate() {
+                    fields.push(Field::new(
+                        &format!("{}_{}", prefix, col.name),
+                        map_sql_to_arrow_type(&col.data_type),
+                        true,
+                    ));
+                    let values: Vec<Value> = stream
+                        .events
+                        .iter()
+                        .map(|e| {
+                            let row = if image { &e.after } else { &e.before };
+                            row.as_ref().map(|r| r[c].clone()).unwrap_or(Value::Null)
+                        })
+                        .collect();
+                    arrays.push(values_to_array(&col.data_type, &values));
+                }
+            }
+            let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+                .map_err(|e| e.to_string())?;
+            write_record_batch(&log_path, &batch)?;
+        }
+        FileFormat::JsonLines => {
+            let image = |row: &Option<Vec<Value>>| match row {
+                Some(r) => row_to_json(table, r),
+                None => serde_json::Value::Null,
+            };
+            write_json_lines(
+                &log_path,
+                stream.events.iter().map(|e| {
+                    json!({
+                        "op": e.op.as_str(),
+                        "commit_ts": e.commit_ts.format("%Y-%m-%dT%H:%M:%S%.3f").to_string(),
+                        "before": image(&e.before),
+                        "after": image(&e.after),
+                    })
+                }),
+            )?;
+        }
+    }
+    write_rows(&snapshot_path, table, &stream.snapshot, format)?;
+
+    println!(
+        "🔁 CDC log with {} events for table '{}' written to '{}', expected snapshot of {} rows in '{}'",
+        stream.events.len().to_string().bold().cyan(),
+        table.name.bold().yellow(),
+        log_path.display().to_string().bold().green(),
+        stream.snapshot.len().to_string().bold().cyan(),
+        snapshot_path.display().to_string().bold().green()
+    );
+
+    Ok((log_path, snapshot_path))
+}
+
+/// Kinds of bad data that can be injected into an otherwise clean dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Anomaly {
+    OutOfRange,
+    MalformedEmail,
+    VarCharOverflow,
+    DuplicateKey,
+    FutureDate,
+    Casing,
+    Whitespace,
+}
+
+impl Anomaly {
+    pub const ALL: [Anomaly; 7] = [
+        Anomaly::OutOfRange,
+        Anomaly::MalformedEmail,
+        Anomaly::VarCharOverflow,
+        Anomaly::DuplicateKey,
+        Anomaly::FutureDate,
+        Anomaly::Casing,
+        Anomaly::Whitespace,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Anomaly::OutOfRange => "out_of_range",
+            Anomaly::MalformedEmail => "malformed_email",
+            Anomaly::VarCharOverflow => "varchar_overflow",
+            Anomaly::DuplicateKey => "duplicate_key",
+            Anomaly::FutureDate => "future_date",
+            Anomaly::Casing => "casing",
+            Anomaly::Whitespace => "whitespace",
+        }
+    }
+
+    /// Whether this anomaly makes sense for the column's type and constraints
+    pub fn applies_to(&self, col: &Column) -> bool {
+        let is_text = matches!(
+            col.data_type,
+            SqlDataType::String(_)
+                | SqlDataType::VarChar(_)
+                | SqlDataType::UUID(_)
+                | SqlDataType::Enum(_)
+        );
+        match self {
+            Anomaly::OutOfRange => {
+                matches!(
+                    col.data_type,
+                    SqlDataType::Int(_)
+                        | SqlDataType::BigInt(_)
+                        | SqlDataType::Float(_)
+                        | SqlDataType::Double(_)
+                )
+            }
+            Anomaly::MalformedEmail => is_text && col.name.to_lowercase().contains("email"),
+            Anomaly::VarCharOverflow => matches!(col.data_type, SqlDataType::VarChar(_)),
+            Anomaly::DuplicateKey => {
+                col.is_primary_key()
+                    || col
+                        .constraints
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case("UNIQUE"))
             }
-            println!();
+            Anomaly::FutureDate => matches!(col.data_type, SqlDataType::DateTime(_)),
+            Anomaly::Casing | Anomaly::Whitespace => is_text,
+        }
+    }
+}
+
+impl FromStr for Anomaly {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_lowercase().replace('-', "_");
+        Anomaly::ALL
+            .iter()
+            .find(|a| a.as_str() == normalized)
+            .copied()
+            .ok_or_else(|| format!("Unknown anomaly '{}'", s))
+    }
+}
+
+/// Corrupt roughly `rate` of the rows in `column` with `anomaly`.
+/// Parsed from `column:anomaly[:rate]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnomalyRule {
+    pub column: String,
+    pub anomaly: Anomaly,
+    pub rate: f64,
+}
+
+impl AnomalyRule {
+    pub const DEFAULT_RATE: f64 = 0.05;
+
+    /// A rate is a probability, so anything outside 0..=1 (or NaN) is an error
+    fn check_rate(rate: f64) -> Result<f64, String> {
+        if (0.0..=1.0).contains(&rate) {
+            Ok(rate)
+        } else {
+            Err(format!("Anomaly rate {} must be between 0 and 1", rate))
+        }
+    }
+
+    /// Every anomaly that applies to every column of the table
+    pub fn all_for(table: &Table, rate: f64) -> Result<Vec<AnomalyRule>, String> {
+        let rate = AnomalyRule::check_rate(rate)?;
+        Ok(table
+            .columns
+            .iter()
+            .flat_map(|col| {
+                Anomaly::ALL
+                    .iter()
+                    .filter(|a| a.applies_to(col))
+                    .map(|a| AnomalyRule {
+                        column: col.name.clone(),
+                        anomaly: *a,
+                        rate,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+}
+
+impl FromStr for AnomalyRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let (column, anomaly, rate) = match parts[..] {
+            [column, anomaly] => (column, anomaly, AnomalyRule::DEFAULT_RATE),
+            [column, anomaly, rate] => (
+                column,
+                anomaly,
+                rate.parse::<f64>()
+                    .map_err(|_| format!("Invalid anomaly rate '{}'", rate))?,
+            ),
+            _ => return Err(format!("Expected column:anomaly[:rate], got '{}'", s)),
+        };
+        Ok(AnomalyRule {
+            column: column.to_string(),
+            anomaly: anomaly.parse()?,
+            rate: AnomalyRule::check_rate(rate)?,
+        })
+    }
+}
+
+/// A cell that was deliberately corrupted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Corruption {
+    pub row: usize,
+    pub column: String,
+    pub anomaly: Anomaly,
+    pub original: Value,
+    pub corrupted: Value,
+}
+
+/// Clean rows for a table. Primary keys are unique, everything else is
+/// random like `create_random_parquet_from_datasql`.
+pub fn generate_table_rows(table: &Table, num_rows: usize) -> Result<Vec<Vec<Value>>, String> {
+    let pools: Vec<Vec<Value>> = table
+        .columns
+        .iter()
+        .map(|col| random_column_values(col, num_rows))
+        .collect();
+
+    (0..num_rows)
+        .map(|i| {
+            table
+                .columns
+                .iter()
+                .zip(&pools)
+                .map(|(col, pool)| {
+                    if col.is_primary_key() {
+                        primary_key_value(col, i)
+                    } else {
+                        Ok(pool[i].clone())
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn malformed_email(original: &str) -> String {
+    let local = original.split('@').next().unwrap_or("user");
+    let local = if local.is_empty() { "user" } else { local };
+    match rand::thread_rng().gen_range(0..5) {
+        0 => format!("{}.example.com", local),
+        1 => format!("{}@", local),
+        2 => "@example.com".to_string(),
+        3 => format!("{}@@example.com", local),
+        _ => format!("{} {}@example .com", local, local),
+    }
+}
+
+fn inconsistent_casing(original: &str) -> String {
+    let mut rng = rand::thread_rng();
+    match rng.gen_range(0..3) {
+        0 => original.to_uppercase(),
+        1 => original.to_lowercase(),
+        _ => original
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if i % 2 == 0 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect(),
+    }
+}
+
+fn messy_whitespace(original: &str) -> String {
+    match rand::thread_rng().gen_range(0..3) {
+        0 => format!("  {}", original),
+        1 => format!("{}\t ", original),
+        _ => format!(" {} ", original.replace(' ', "  ")),
+    }
+}
+
+/// The corrupted replacement for one cell, or None when this particular
+/// value cannot be corrupted that way (e.g. a casing change of "123").
+/// Duplicate keys need the other rows and are handled by `inject_anomalies`.
+fn corrupt_value(anomaly: Anomaly, col: &Column, original: &Value) -> Option<Value> {
+    let mut rng = rand::thread_rng();
+    let corrupted = match (anomaly, original) {
+        (Anomaly::OutOfRange, Value::Int(_)) => Value::Int(if rng.gen_bool(0.5) {
+            -rng.gen_range(1..=1000)
         } else {
-            break;
+            rng.gen_range(1_000_000..=i32::MAX)
+        }),
+        (Anomaly::OutOfRange, Value::Long(_)) => Value::Long(if rng.gen_bool(0.5) {
+            -rng.gen_range(1..=1000)
+        } else {
+            rng.gen_range(1_000_000_000..=i64::MAX)
+        }),
+        (Anomaly::OutOfRange, Value::Float(_)) => Value::Float(if rng.gen_bool(0.5) {
+            -rng.gen_range(1.0..=1000.0)
+        } else {
+            rng.gen_range(1.0e6..=1.0e9)
+        }),
+        (Anomaly::OutOfRange, Value::Double(_)) => Value::Double(if rng.gen_bool(0.5) {
+            -rng.gen_range(1.0..=1000.0)
+        } else {
+            rng.gen_range(1.0e9..=1.0e12)
+        }),
+        (Anomaly::MalformedEmail, Value::Str(s)) => Value::Str(malformed_email(s)),
+        (Anomaly::VarCharOverflow, Value::Str(s)) => match col.data_type {
+            SqlDataType::VarChar(size) => {
+                let extra: String = Word().fake();
+                let mut long = s.clone();
+                while long.chars().count() <= size {
+                    long.push(' ');
+                    long.push_str(&extra);
+                }
+                Value::Str(long)
+            }
+            _ => return None,
+        },
+        (Anomaly::FutureDate, Value::Date(_)) => {
+            let today = Utc::now().date_naive();
+            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+            let days = (today - epoch).num_days() as i32;
+            Value::Date(days + rng.gen_range(1..=3650))
+        }
+        (Anomaly::Casing, Value::Str(s)) => Value::Str(inconsistent_casing(s)),
+        (Anomaly::Whitespace, Value::Str(s)) => Value::Str(messy_whitespace(s)),
+        _ => return None,
+    };
+
+    if &corrupted == original {
+        None
+    } else {
+        Some(corrupted)
+    }
+}
+
+/// Corrupt rows in place according to `rules` and report every cell that was
+/// changed. A cell is corrupted at most once so the report stays exact.
+pub fn inject_anomalies(
+    table: &Table,
+    rows: &mut [Vec<Value>],
+    rules: &[AnomalyRule],
+) -> Result<Vec<Corruption>, String> {
+    let mut rng = rand::thread_rng();
+    let mut touched = std::collections::HashSet::new();
+    let mut corruptions = Vec::new();
+
+    for rule in rules {
+        let col_index = table
+            .columns
+            .iter()
+            .position(|col| col.name.eq_ignore_ascii_case(&rule.column))
+            .ok_or_else(|| format!("Table '{}' has no column '{}'", table.name, rule.column))?;
+        let col = &table.columns[col_index];
+        AnomalyRule::check_rate(rule.rate)?;
+        if !rule.anomaly.applies_to(col) {
+            return Err(format!(
+                "Anomaly '{}' does not apply to column '{}'",
+                rule.anomaly.as_str(),
+                col.name
+            ));
+        }
+
+        for row in 0..rows.len() {
+            if !rng.gen_bool(rule.rate) || touched.contains(&(row, col_index)) {
+                continue;
+            }
+            let original = rows[row][col_index].clone();
+            let corrupted = if rule.anomaly == Anomaly::DuplicateKey {
+                if rows.len() < 2 {
+                    continue;
+                }
+                let source = loop {
+                    let source = rng.gen_range(0..rows.len());
+                    if source != row {
+                        break source;
+                    }
+                };
+                // The copied key must not change later or the duplicate disappears
+                touched.insert((source, col_index));
+                Some(rows[source][col_index].clone()).filter(|key| key != &original)
+            } else {
+                corrupt_value(rule.anomaly, col, &original)
+            };
+            if let Some(corrupted) = corrupted {
+                rows[row][col_index] = corrupted.clone();
+                touched.insert((row, col_index));
+                corruptions.push(Corruption {
+                    row,
+                    column: col.name.clone(),
+                    anomaly: rule.anomaly,
+                    original,
+                    corrupted,
+                });
+            }
+        }
+    }
+
+    corruptions.sort_by_key(|c| c.row);
+    Ok(corruptions)
+}
+
+/// Write a dirty dataset as `<table>_dirty.<ext>` and the sidecar listing
+/// every corrupted cell as `<table>_anomalies.jsonl`.
+pub fn write_dirty_dataset(
+    table: &Table,
+    rows: &[Vec<Value>],
+    corruptions: &[Corruption],
+    output_dir: &Path,
+    format: FileFormat,
+) -> Result<(PathBuf, PathBuf), String> {
+    let output_dir = &table_dir(output_dir, table);
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Error creating '{}': {}", output_dir.display(), e))?;
+    let name = file_name(&table.name);
+    let data_path = output_dir.join(format!("{}_dirty.{}", name, format.extension()));
+    let sidecar_path = output_dir.join(format!("{}_anomalies.jsonl", name));
+
+    write_rows(&data_path, table, rows, format)?;
+    write_json_lines(
+        &sidecar_path,
+        corruptions.iter().map(|c| {
+            json!({
+                "row": c.row,
+                "column": c.column,
+                "anomaly": c.anomaly.as_str(),
+                "original": c.original.to_json(),
+                "corrupted": c.corrupted.to_json(),
+            })
+        }),
+    )?;
+
+    println!(
+        "🧪 {} of {} rows in '{}' corrupted with {} anomalies, listed in '{}'",
+        corruptions
+            .iter()
+            .map(|c| c.row)
+            .collect::<std::collections::HashSet<_>>()
+            .len()
+            .to_string()
+            .bold()
+            .cyan(),
+        rows.len().to_string().bold().cyan(),
+        data_path.display().to_string().bold().green(),
+        corruptions.len().to_string().bold().red(),
+        sidecar_path.display().to_string().bold().green()
+    );
+
+    Ok((data_path, sidecar_path))
+}
+
+/// Hot key mode: `hot_fraction` of the rows share `hot_keys` values, the rest
+/// are spread uniformly over a domain of `cardinality` distinct keys.
+#[derive(Debug, Clone)]
+pub struct SkewOptions {
+    pub hot_keys: usize,
+    pub hot_fraction: f64,
+    pub cardinality: usize,
+}
+
+impl Default for SkewOptions {
+    fn default() -> Self {
+        SkewOptions {
+            hot_keys: 3,
+            hot_fraction: 0.8,
+            cardinality: 100,
+        }
+    }
+}
+
+/// How often each key occurs, most frequent first
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyHistogram {
+    pub column: String,
+    pub total: usize,
+    pub counts: Vec<(String, usize)>,
+}
+
+impl KeyHistogram {
+    pub fn from_values(column: &str, values: &[Value]) -> KeyHistogram {
+        let mut counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+        for value in values {
+            *counts.entry(value.to_string()).or_insert(0) += 1;
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        KeyHistogram {
+            column: column.to_string(),
+            total: values.len(),
+            counts,
+        }
+    }
+
+    /// Share of all rows held by the `n` most frequent keys
+    pub fn top_share(&self, n: usize) -> f64 {
+        if self.total == 0 {
+            return 0.0;
         }
+        let top: usize = self.counts.iter().take(n).map(|(_, c)| c).sum();
+        top as f64 / self.total as f64
     }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "column": self.column,
+            "total": self.total,
+            "distinct": self.counts.len(),
+            "counts": self
+                .counts
+                .iter()
+                .map(|(key, count)| json!({ "key": key, "count": count }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Print the `top` most frequent keys as a bar chart
+    pub fn print(&self, top: usize) {
+        println!(
+            "📊 Key frequencies for '{}' ({} rows, {} distinct keys):",
+            self.column.bold().cyan(),
+            self.total.to_string().bold(),
+            self.counts.len().to_string().bold()
+        );
+        let max = self.counts.first().map(|(_, c)| *c).unwrap_or(1).max(1);
+        for (key, count) in self.counts.iter().take(top) {
+            let bar = "█".repeat((count * 40).div_ceil(max));
+            println!(
+                "  {:>20} {:>8} {:>6.2}% {}",
+                key.yellow(),
+                count,
+                *count as f64 * 100.0 / self.total as f64,
+                bar.green()
+            );
+        }
+        if self.counts.len() > top {
+            let rest: usize = self.counts.iter().skip(top).map(|(_, c)| c).sum();
+            println!(
+                "  {:>20} {:>8} {:>6.2}%",
+                format!("({} more)", self.counts.len() - top),
+                rest,
+                rest as f64 * 100.0 / self.total as f64
+            );
+        }
+    }
+}
+
+/// Draw `num_rows` keys from `domain`, sending `hot_fraction` of them to the
+/// first `hot_keys` entries of the domain
+pub fn skewed_column_values(
+    domain: &[Value],
+    num_rows: usize,
+    options: &SkewOptions,
+) -> Result<Vec<Value>, String> {
+    if domain.is_empty() {
+        return Err("Cannot draw keys from an empty domain".to_string());
+    }
+    if !(0.0..=1.0).contains(&options.hot_fraction) {
+        return Err(format!(
+            "Hot fraction {} must be between 0 and 1",
+            options.hot_fraction
+        ));
+    }
+    let hot_keys = options.hot_keys.clamp(1, domain.len());
+    let mut rng = rand::thread_rng();
+
+    Ok((0..num_rows)
+        .map(|_| {
+            if rng.gen_bool(options.hot_fraction) {
+                domain[rng.gen_range(0..hot_keys)].clone()
+            } else {
+                domain[rng.gen_range(0..domain.len())].clone()
+            }
+        })
+        .collect())
+}
+
+/// Generate `table` with `column` skewed towards a few hot keys.
+///
+/// A column with `REFERENCES Parent(Key)` draws from generated parent rows,
+/// which are written next to the child so the skewed join can be reproduced.
+/// Any other column is treated as categorical with `cardinality` distinct values.
+pub fn generate_skewed_dataset(
+    table: &Table,
+    tables: &[Table],
+    column: &str,
+    num_rows: usize,
+    options: &SkewOptions,
+    output_dir: &Path,
+    format: FileFormat,
+) -> Result<KeyHistogram, String> {
+    let col_index = table
+        .columns
+        .iter()
+        .position(|col| col.name.eq_ignore_ascii_case(column))
+        .ok_or_else(|| format!("Table '{}' has no column '{}'", table.name, column))?;
+    let col = &table.columns[col_index];
+
+    let domain: Vec<Value> = match col.references() {
+        Some((parent_name, parent_column)) => {
+            let parent = Table::position(tables, &parent_name)?
+                .map(|i| &tables[i])
+                .ok_or_else(|| format!("Referenced table '{}' not found", parent_name))?;
+            let key_index = parent
+                .columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(&parent_column))
+                .ok_or_else(|| {
+                    format!("Table '{}' has no column '{}'", parent.name, parent_column)
+                })?;
+            let parent_rows = generate_table_rows(parent, options.cardinality)?;
+            let parent_dir = table_dir(output_dir, parent);
+            std::fs::create_dir_all(&parent_dir)
+                .map_err(|e| format!("Error creating '{}': {}", parent_dir.display(), e))?;
+            let parent_path = parent_dir.join(format!(
+                "{}.{}",
+                file_name(&parent.name),
+                format.extension()
+            ));
+            write_rows(&parent_path, parent, &parent_rows, format)?;
+            parent_rows
+                .into_iter()
+                .map(|mut row| row.swap_remove(key_index))
+                .collect()
+        }
+        None => {
+            let mut seen = std::collections::HashSet::new();
+            random_column_values(col, options.cardinality)
+                .into_iter()
+                .filter(|v| seen.insert(v.to_string()))
+                .collect()
+        }
+    };
+
+    let mut rows = generate_table_rows(table, num_rows)?;
+    let keys = skewed_column_values(&domain, num_rows, options)?;
+    let histogram = KeyHistogram::from_values(&col.name, &keys);
+    for (row, key) in rows.iter_mut().zip(keys) {
+        row[col_index] = key;
+    }
+
+    let output_dir = table_dir(output_dir, table);
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Error creating '{}': {}", output_dir.display(), e))?;
+    let name = file_name(&table.name);
+    let data_path = output_dir.join(format!("{}_skewed.{}", name, format.extension()));
+    write_rows(&data_path, table, &rows, format)?;
+    let histogram_path =
+        output_dir.join(format!("{}_{}_histogram.json", name, file_name(&col.name)));
+    std::fs::write(
+        &histogram_path,
+        serde_json::to_string_pretty(&histogram.to_json()).unwrap(),
+    )
+    .map_err(|e| format!("Error writing '{}': {}", histogram_path.display(), e))?;
+
+    println!(
+        "🔥 Skewed table '{}' written to '{}', top {} keys hold {:.1}% of rows",
+        table.name.bold().yellow(),
+        data_path.display().to_string().bold().green(),
+        options.hot_keys,
+        histogram.top_share(options.hot_keys) * 100.0
+    );
+    histogram.print(10);
+
+    Ok(histogram)
 }
 
 #[cfg(test)]
@@ -396,7 +1663,7 @@ mod tests {
 
     #[test]
     fn test_create_random_parquet_files_from_datasql() {
-        let tables = load_data_model();
+        let tables = load_schema().unwrap().tables;
 
         for table in &tables {
             let file_path = format!("{}_{}.parquet", table.name, uuid::Uuid::new_v4());
@@ -412,7 +1679,7 @@ mod tests {
 
     #[test]
     fn test_creating_random_parquet_files_and_tar_from_datasql() {
-        let tables = load_data_model();
+        let tables = load_schema().unwrap().tables;
         generate_sandbox_example_random_files(&tables);
 
         // Check that example.tar.gz exists in the user downloads directory
@@ -424,4 +1691,212 @@ mod tests {
         // Clean up
         std::fs::remove_file(tar_file_path).unwrap();
     }
+
+    fn cdc_test_table() -> Table {
+        sql::parse_create_table(
+            "CREATE TABLE Accounts (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Email VARCHAR(50),
+            Balance FLOAT,
+            Active BOOLEAN
+        )",
+        )
+    }
+
+    #[test]
+    fn test_cdc_stream_replays_to_snapshot() {
+        let table = cdc_test_table();
+        let stream = generate_cdc_stream(&table, "ID", &CdcOptions::default()).unwrap();
+        let key_index = 0;
+        assert_eq!(stream.events.len(), 1000);
+        assert_eq!(stream.events[0].op, CdcOp::Insert);
+
+        // Replaying the log with upsert semantics must give the snapshot
+        let mut replayed: Vec<Vec<Value>> = Vec::new();
+        for event in &stream.events {
+            let key = event
+                .before
+                .as_ref()
+                .or(event.after.as_ref())
+                .map(|row| row[key_index].clone())
+                .unwrap();
+            let existing = replayed.iter().position(|row| row[key_index] == key);
+            match (event.op, existing) {
+                (CdcOp::Insert, None) => replayed.push(event.after.clone().unwrap()),
+                (CdcOp::Update, Some(i)) => replayed[i] = event.after.clone().unwrap(),
+                (CdcOp::Delete, Some(i)) => {
+                    replayed.remove(i);
+                }
+                (op, _) => panic!("{:?} event does not match the replayed state", op),
+            }
+        }
+        assert_eq!(replayed, stream.snapshot);
+    }
+
+    #[test]
+    fn test_write_cdc_stream() {
+        let table = cdc_test_table();
+        let options = CdcOptions {
+            num_events: 50,
+            ..CdcOptions::default()
+        };
+        let stream = generate_cdc_stream(&table, "id", &options).unwrap();
+        let dir = tempdir::TempDir::new("cdc").unwrap();
+
+        let (log, snapshot) =
+            write_cdc_stream(&table, &stream, dir.path(), FileFormat::Parquet).unwrap();
+        let reader = SerializedFileReader::new(File::open(&log).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 50);
+        assert!(snapshot.exists());
+
+        let (log, _) =
+            write_cdc_stream(&table, &stream, dir.path(), FileFormat::JsonLines).unwrap();
+        let content = fs::read_to_string(log).unwrap();
+        assert_eq!(content.lines().count(), 50);
+        let first: serde_json::Value =
+            serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(first["op"], "INSERT");
+        assert!(first["before"].is_null());
+        assert_eq!(first["after"]["ID"], 1);
+
+        assert!(generate_cdc_stream(&table, "Missing", &options).is_err());
+    }
+
+    #[test]
+    fn test_inject_anomalies_reports_every_corrupted_cell() {
+        let table = sql::parse_create_table(
+            "CREATE TABLE Members (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Email VARCHAR(40),
+            Age INT,
+            Joined DATE
+        )",
+        );
+        let clean = generate_table_rows(&table, 500).unwrap();
+        let mut rows = clean.clone();
+        let rules = AnomalyRule::all_for(&table, 0.1).unwrap();
+        assert_eq!(rules.len(), 8);
+        for rate in [2.0, -0.1, f64::NAN] {
+            assert!(AnomalyRule::all_for(&table, rate).is_err());
+        }
+
+        let corruptions = inject_anomalies(&table, &mut rows, &rules).unwrap();
+        assert!(!corruptions.is_empty());
+
+        for c in &corruptions {
+            let col = table
+                .columns
+                .iter()
+                .position(|col| col.name == c.column)
+                .unwrap();
+            assert_eq!(clean[c.row][col], c.original);
+            assert_eq!(rows[c.row][col], c.corrupted);
+            match (c.anomaly, &c.corrupted) {
+                (Anomaly::OutOfRange, Value::Int(v)) => assert!(!(0..=100).contains(v)),
+                (Anomaly::VarCharOverflow, Value::Str(s)) => assert!(s.chars().count() > 40),
+                (Anomaly::DuplicateKey, key) => {
+                    assert!(rows.iter().filter(|r| &r[0] == key).count() >= 2)
+                }
+                _ => {}
+            }
+        }
+
+        // Every cell that differs from the clean rows is in the report
+        let changed = rows
+            .iter()
+            .zip(&clean)
+            .map(|(dirty, clean)| dirty.iter().zip(clean).filter(|(a, b)| a != b).count())
+            .sum::<usize>();
+        assert_eq!(changed, corruptions.len());
+    }
+
+    #[test]
+    fn test_parse_anomaly_rule() {
+        let rule: AnomalyRule = "Email:malformed-email:0.2".parse().unwrap();
+        assert_eq!(rule.anomaly, Anomaly::MalformedEmail);
+        assert_eq!(rule.rate, 0.2);
+        let rule: AnomalyRule = "Age:out_of_range".parse().unwrap();
+        assert_eq!(rule.rate, AnomalyRule::DEFAULT_RATE);
+        assert!("Age".parse::<AnomalyRule>().is_err());
+        assert!("Age:bogus".parse::<AnomalyRule>().is_err());
+        assert!("Age:casing:2".parse::<AnomalyRule>().is_err());
+    }
+
+    #[test]
+    fn test_skewed_foreign_key_dataset() {
+        let tables = sql::parse_schema(
+            "CREATE TABLE Teams (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            City TEXT
+        );
+        CREATE TABLE Players (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            TeamID INT REFERENCES Teams(ID),
+            Position TEXT
+        );",
+        )
+        .unwrap()
+        .tables;
+        let dir = tempdir::TempDir::new("skew").unwrap();
+        let options = SkewOptions {
+            hot_keys: 2,
+            hot_fraction: 0.9,
+            cardinality: 50,
+        };
+
+        let histogram = generate_skewed_dataset(
+            &tables[1],
+            &tables,
+            "TeamID",
+            5000,
+            &options,
+            dir.path(),
+            FileFormat::Parquet,
+        )
+        .unwrap();
+
+        assert_eq!(histogram.total, 5000);
+        assert!(histogram.counts.len() <= 50);
+        assert!(histogram.top_share(2) > 0.85);
+        // Every key must exist in the parent table for the join to match
+        assert!(histogram
+            .counts
+            .iter()
+            .all(|(key, _)| (1..=50).contains(&key.parse::<i32>().unwrap())));
+        assert!(dir.path().join("Teams.parquet").exists());
+        assert!(dir.path().join("Players_skewed.parquet").exists());
+        assert!(dir.path().join("Players_TeamID_histogram.json").exists());
+    }
+
+    #[test]
+    fn test_namespaces_map_to_directories() {
+        let tables = sql::parse_schema(
+            r#"CREATE TABLE IF NOT EXISTS crm.Teams (ID INT PRIMARY KEY);
+            CREATE TABLE analytics."Player Stats" (
+                ID INT PRIMARY KEY,
+                TeamID INT REFERENCES crm.Teams(ID)
+            );"#,
+        )
+        .unwrap()
+        .tables;
+        let dir = tempdir::TempDir::new("namespaces").unwrap();
+
+        generate_skewed_dataset(
+            &tables[1],
+            &tables,
+            "TeamID",
+            100,
+            &SkewOptions::default(),
+            dir.path(),
+            FileFormat::JsonLines,
+        )
+        .unwrap();
+
+        assert!(dir.path().join("crm").join("Teams.jsonl").exists());
+        let analytics = dir.path().join("analytics");
+        assert!(analytics.join("Player Stats_skewed.jsonl").exists());
+        assert!(analytics
+            .join("Player Stats_TeamID_histogram.json")
+            .exists());
+    }
 }
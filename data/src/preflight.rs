@@ -0,0 +1,174 @@
+//! IAM preflight: try every S3 action lernspark needs against a bucket with a
+//! cheap real call, and write the smallest policy granting what is missing.
+use crate::aws::AwsError;
+use colored::*;
+use serde_json::{json, Value};
+
+/// The S3 actions uploads, syncs and samples use, plus creating the bucket.
+/// The bucket settings `provision` applies and `restore` are not probed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    ListBucket,
+    GetObject,
+    /// Also covers creating, filling and completing multipart uploads
+    PutObject,
+    DeleteObject,
+    AbortMultipartUpload,
+    CreateBucket,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::ListBucket => "s3:ListBucket",
+            Action::GetObject => "s3:GetObject",
+            Action::PutObject => "s3:PutObject",
+            Action::DeleteObject => "s3:DeleteObject",
+            Action::AbortMultipartUpload => "s3:AbortMultipartUpload",
+            Action::CreateBucket => "s3:CreateBucket",
+        }
+    }
+
+    /// Bucket actions are granted on the bucket, the others on its objects
+    fn on_bucket(&self) -> bool {
+        matches!(self, Action::ListBucket | Action::CreateBucket)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Allowed,
+    Denied(String),
+    /// The probe failed for another reason, or could not run
+    Unknown(String),
+}
+
+impl Outcome {
+    /// Allowed when the call went through, denied on AccessDenied
+    pub fn of<T>(result: Result<T, AwsError>) -> Outcome {
+        match result {
+            Ok(_) => Outcome::Allowed,
+            Err(AwsError::Permission(message)) => Outcome::Denied(message),
+            Err(e) => Outcome::Unknown(format!("{} error: {}", e.kind(), e.message())),
+        }
+    }
+}
+
+/// One probe: the action it checks, the API call made and what happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub action: Action,
+    pub probe: &'static str,
+    pub outcome: Outcome,
+}
+
+impl Check {
+    pub fn new(action: Action, probe: &'static str, outcome: Outcome) -> Check {
+        Check {
+            action,
+            probe,
+            outcome,
+        }
+    }
+}
+
+/// A pass/fail row per probe
+pub fn print_table(checks: &[Check]) {
+    println!(
+        "{}",
+        format!("{:<26} {:<24} {}", "Action", "Probe", "Result").bold()
+    );
+    for check in checks {
+        let result = match &check.outcome {
+            Outcome::Allowed => "✅ allowed".green().to_string(),
+            Outcome::Denied(_) => "❌ denied".red().to_string(),
+            Outcome::Unknown(reason) => format!("{} {}", "❔".yellow(), reason.yellow()),
+        };
+        println!("{:<26} {:<24} {}", check.action.name(), check.probe, result);
+    }
+}
+
+/// The actions some probe was denied, each once
+pub fn denied(checks: &[Check]) -> Vec<Action> {
+    let mut actions: Vec<Action> = checks
+        .iter()
+        .filter(|c| matches!(c.outcome, Outcome::Denied(_)))
+        .map(|c| c.action)
+        .collect();
+    actions.sort();
+    actions.dedup();
+    actions
+}
+
+/// A policy granting `actions` on `bucket`, object actions limited to `prefix`
+pub fn minimal_policy(bucket: &str, prefix: &str, actions: &[Action]) -> Value {
+    let objects = match prefix.trim_matches('/') {
+        "" => format!("arn:aws:s3:::{}/*", bucket),
+        prefix => format!("arn:aws:s3:::{}/{}/*", bucket, prefix),
+    };
+    let mut statements = Vec::new();
+    for (on_bucket, resource) in [(true, format!("arn:aws:s3:::{}", bucket)), (false, objects)] {
+        let granted: Vec<&str> = actions
+            .iter()
+            .filter(|a| a.on_bucket() == on_bucket)
+            .map(|a| a.name())
+            .collect();
+        if !granted.is_empty() {
+            statements.push(json!({
+                "Effect": "Allow",
+                "Action": granted,
+                "Resource": resource,
+            }));
+        }
+    }
+    json!({
+        "Version": "2012-10-17",
+        "Statement": statements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_grants_only_denied_actions() {
+        let checks = vec![
+            Check::new(Action::ListBucket, "ListObjectsV2", Outcome::Allowed),
+            Check::new(
+                Action::PutObject,
+                "PutObject",
+                Outcome::of::<()>(Err(AwsError::Permission("denied".into()))),
+            ),
+            Check::new(
+                Action::PutObject,
+                "CreateMultipartUpload",
+                Outcome::Denied("denied".into()),
+            ),
+            Check::new(
+                Action::CreateBucket,
+                "CreateBucket",
+                Outcome::Denied("denied".into()),
+            ),
+            Check::new(
+                Action::GetObject,
+                "GetObject",
+                Outcome::of::<()>(Err(AwsError::Network("down".into()))),
+            ),
+        ];
+        let actions = denied(&checks);
+        assert_eq!(actions, vec![Action::PutObject, Action::CreateBucket]);
+        assert_eq!(
+            minimal_policy("data", "raw/v1/", &actions),
+            json!({
+                "Version": "2012-10-17",
+                "Statement": [
+                    {"Effect": "Allow", "Action": ["s3:CreateBucket"],
+                     "Resource": "arn:aws:s3:::data"},
+                    {"Effect": "Allow", "Action": ["s3:PutObject"],
+                     "Resource": "arn:aws:s3:::data/raw/v1/*"},
+                ]
+            })
+        );
+    }
+}
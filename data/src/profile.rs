@@ -0,0 +1,647 @@
+//! Statistical profile of a dataset: null rate, distinct estimate,
+//! min/max/mean/stddev, top values, string lengths and histograms per
+//! column. Files are streamed batch by batch, a second pass fills the
+//! histograms once each column's range is known.
+use colored::Colorize;
+use serde_json::json;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use arrow::datatypes::DataType as ArrowType;
+
+use crate::infer::{self, InferOptions};
+use crate::inspect;
+use crate::model::{self, Value};
+
+/// Hashes kept by the distinct count sketch, estimates are exact below this
+const SKETCH_SIZE: usize = 1024;
+/// Counters kept for top values, exact while a column has fewer distinct values
+const TOP_CAPACITY: usize = 10_000;
+
+/// How much detail to compute
+#[derive(Debug, Clone)]
+pub struct ProfileOptions {
+    pub top_k: usize,
+    pub bins: usize,
+}
+
+impl Default for ProfileOptions {
+    fn default() -> Self {
+        ProfileOptions {
+            top_k: 10,
+            bins: 10,
+        }
+    }
+}
+
+/// K minimum values sketch estimating distinct counts in fixed memory
+#[derive(Debug, Clone, Default)]
+struct DistinctSketch {
+    hashes: BTreeSet<u64>,
+}
+
+impl DistinctSketch {
+    fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.hashes.len() < SKETCH_SIZE {
+            self.hashes.insert(hash);
+        } else if hash < *self.hashes.iter().next_back().unwrap() && self.hashes.insert(hash) {
+            self.hashes.pop_last();
+        }
+    }
+
+    fn estimate(&self) -> usize {
+        if self.hashes.len() < SKETCH_SIZE {
+            return self.hashes.len();
+        }
+        let kth = *self.hashes.iter().next_back().unwrap() as f64 / u64::MAX as f64;
+        ((SKETCH_SIZE - 1) as f64 / kth).round() as usize
+    }
+}
+
+/// Misra-Gries heavy hitters, counts are lower bounds once a column has
+/// more distinct values than counters
+#[derive(Debug, Clone, Default)]
+struct TopValues {
+    counts: HashMap<String, usize>,
+    exact: bool,
+}
+
+impl TopValues {
+    fn insert(&mut self, value: String) {
+        if let Some(count) = self.counts.get_mut(&value) {
+            *count += 1;
+        } else if self.counts.len() < TOP_CAPACITY {
+            self.counts.insert(value, 1);
+        } else {
+            self.exact = false;
+            self.counts.retain(|_, count| {
+                *count -= 1;
+                *count > 0
+            });
+        }
+    }
+
+    fn top(&self, k: usize) -> Vec<(String, usize)> {
+        let mut top: Vec<(String, usize)> =
+            self.counts.iter().map(|(v, c)| (v.clone(), *c)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(k);
+        top
+    }
+}
+
+/// Running count, mean, variance and range (Welford's algorithm)
+#[derive(Debug, Clone, Default)]
+pub struct Moments {
+    pub count: usize,
+    pub mean: f64,
+    m2: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Moments {
+    fn add(&mut self, x: f64) {
+        if self.count == 0 {
+            self.min = x;
+            self.max = x;
+        }
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    pub fn stddev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+    }
+}
+
+/// Statistics for one column
+#[derive(Debug, Clone)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub arrow_type: ArrowType,
+    pub count: usize,
+    pub nulls: usize,
+    distinct: DistinctSketch,
+    top: TopValues,
+    /// Numeric values, or days since the epoch for dates
+    pub values: Option<Moments>,
+    /// Character lengths of string values
+    pub lengths: Option<Moments>,
+    /// String lengths bucketed by powers of two: 0, 1, 2-3, 4-7, ...
+    pub length_buckets: Vec<usize>,
+    pub histogram: Vec<usize>,
+}
+
+impl ColumnProfile {
+    fn new(name: &str, arrow_type: ArrowType) -> ColumnProfile {
+        ColumnProfile {
+            name: name.to_string(),
+            arrow_type,
+            count: 0,
+            nulls: 0,
+            distinct: DistinctSketch::default(),
+            top: TopValues {
+                exact: true,
+                ..TopValues::default()
+            },
+            values: None,
+            lengths: None,
+            length_buckets: Vec::new(),
+            histogram: Vec::new(),
+        }
+    }
+
+    fn observe(&mut self, value: &Value) {
+        self.count += 1;
+        if let Some(x) = numeric(value) {
+            self.values.get_or_insert_with(Moments::default).add(x);
+        }
+        match value {
+            Value::Null => {
+                self.nulls += 1;
+                return;
+            }
+            Value::Str(s) => {
+                let len = s.chars().count();
+                self.lengths
+                    .get_or_insert_with(Moments::default)
+                    .add(len as f64);
+                let bucket = length_bucket(len);
+                if self.length_buckets.len() <= bucket {
+                    self.length_buckets.resize(bucket + 1, 0);
+                }
+                self.length_buckets[bucket] += 1;
+            }
+            _ => {}
+        }
+        let text = value.to_string();
+        self.distinct.insert(&text);
+        self.top.insert(text);
+    }
+
+    fn bin(&self, x: f64, bins: usize) -> usize {
+        let moments = self.values.as_ref().unwrap();
+        let width = moments.max - moments.min;
+        if width <= 0.0 {
+            return 0;
+        }
+        (((x - moments.min) / width * bins as f64) as usize).min(bins - 1)
+    }
+
+    pub fn null_rate(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.nulls as f64 / self.count as f64
+        }
+    }
+
+    pub fn distinct_estimate(&self) -> usize {
+        self.distinct.estimate()
+    }
+
+    pub fn top_values(&self, k: usize) -> Vec<(String, usize)> {
+        self.top.top(k)
+    }
+
+    fn is_date(&self) -> bool {
+        matches!(
+            self.arrow_type,
+            ArrowType::Date32 | ArrowType::Date64 | ArrowType::Timestamp(_, _)
+        )
+    }
+
+    /// A numeric statistic rendered as a date for date columns
+    fn format_stat(&self, x: f64) -> String {
+        if self.is_date() {
+            Value::Date(x.round() as i32).to_string()
+        } else {
+            format!("{:.4}", x)
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        }
+    }
+
+    /// Lower edge of each histogram bin
+    pub fn bin_edges(&self) -> Vec<String> {
+        match &self.values {
+            Some(m) => (0..self.histogram.len())
+                .map(|i| {
+                    let edge = m.min + (m.max - m.min) * i as f64 / self.histogram.len() as f64;
+                    self.format_stat(edge)
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self, options: &ProfileOptions) -> serde_json::Value {
+        let stats = self.values.as_ref().map(|m| {
+            json!({
+                "min": self.format_stat(m.min),
+                "max": self.format_stat(m.max),
+                "mean": self.format_stat(m.mean),
+                "stddev": if self.is_date() { m.stddev().round() } else { m.stddev() },
+            })
+        });
+        let lengths = self.lengths.as_ref().map(|m| {
+            json!({
+                "min": m.min as usize,
+                "max": m.max as usize,
+                "mean": m.mean,
+                "buckets": self.length_buckets.iter().enumerate().map(|(i, count)| json!({
+                    "lengths": length_bucket_label(i),
+                    "count": count,
+                })).collect::<Vec<_>>(),
+            })
+        });
+        json!({
+            "name": self.name,
+            "type": format!("{:?}", self.arrow_type),
+            "count": self.count,
+            "nulls": self.nulls,
+            "null_rate": self.null_rate(),
+            "distinct_estimate": self.distinct_estimate(),
+            "stats": stats,
+            "lengths": lengths,
+            "top_values": self.top_values(options.top_k).iter().map(|(v, c)| json!({
+                "value": v,
+                "count": c,
+            })).collect::<Vec<_>>(),
+            "top_values_exact": self.top.exact,
+            "histogram": self.bin_edges().iter().zip(&self.histogram).map(|(edge, count)| json!({
+                "from": edge,
+                "count": count,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(v) => Some(*v as f64),
+        Value::Long(v) => Some(*v as f64),
+        Value::Float(v) => Some(*v as f64),
+        Value::Double(v) => Some(*v),
+        Value::Date(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+fn length_bucket(len: usize) -> usize {
+    (usize::BITS - len.leading_zeros()) as usize
+}
+
+fn length_bucket_label(bucket: usize) -> String {
+    match bucket {
+        0 => "0".to_string(),
+        1 => "1".to_string(),
+        b => format!("{}-{}", 1usize << (b - 1), (1usize << b) - 1),
+    }
+}
+
+/// The profile of every column in a dataset
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    pub path: String,
+    pub files: usize,
+    pub num_rows: usize,
+    pub columns: Vec<ColumnProfile>,
+    pub options: ProfileOptions,
+}
+
+/// Profile a Parquet file, a directory of Parquet files or a CSV file
+pub fn profile_dataset(path: &Path, options: &ProfileOptions) -> Result<ProfileReport, String> {
+    let files: Vec<PathBuf> = inspect::find_parquet_files(path)?;
+    if files.is_empty() {
+        return Err(format!("No Parquet files found in '{}'", path.display()));
+    }
+    let read_options = InferOptions::default();
+
+    let mut columns: Vec<ColumnProfile> = Vec::new();
+    let mut num_rows = 0;
+    for file in &files {
+        infer::for_each_batch(file, &read_options, |batch| {
+            for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+                let index = match columns.iter().position(|c| c.name == *field.name()) {
+                    Some(i) => i,
+                    None => {
+                        columns.push(ColumnProfile::new(field.name(), field.data_type().clone()));
+                        columns.len() - 1
+                    }
+                };
+                for row in 0..array.len() {
+                    columns[index].observe(&model::array_value(array.as_ref(), row));
+                }
+            }
+            num_rows += batch.num_rows();
+        })?;
+    }
+
+    // Second pass, now that every column's range is known
+    let bins = options.bins.max(1);
+    if columns.iter().any(|c| c.values.is_some()) {
+        for column in columns.iter_mut().filter(|c| c.values.is_some()) {
+            column.histogram = vec![0; bins];
+        }
+        for file in &files {
+            infer::for_each_batch(file, &read_options, |batch| {
+                for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+                    let column = match columns
+                        .iter_mut()
+                        .find(|c| c.name == *field.name() && c.values.is_some())
+                    {
+                        Some(column) => column,
+                        None => continue,
+                    };
+                    for row in 0..array.len() {
+                        if let Some(x) = numeric(&model::array_value(array.as_ref(), row)) {
+                            let bin = column.bin(x, bins);
+                            column.histogram[bin] += 1;
+                        }
+                    }
+                }
+            })?;
+        }
+    }
+
+    Ok(ProfileReport {
+        path: path.display().to_string(),
+        files: files.len(),
+        num_rows,
+        columns,
+        options: options.clone(),
+    })
+}
+
+impl ProfileReport {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "path": self.path,
+            "files": self.files,
+            "num_rows": self.num_rows,
+            "columns": self.columns.iter().map(|c| c.to_json(&self.options)).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "\n📊 {} ({} files, {} rows)",
+            self.path.bold(),
+            self.files.to_string().cyan(),
+            self.num_rows.to_string().cyan()
+        );
+        for column in &self.columns {
+            println!(
+                "\n  {} {}",
+                column.name.bold().yellow(),
+                format!("{:?}", column.arrow_type).dimmed()
+            );
+            println!(
+                "    nulls     {} ({:.1}%)",
+                column.nulls,
+                column.null_rate() * 100.0
+            );
+            println!("    distinct  ~{}", column.distinct_estimate());
+            if let Some(m) = &column.values {
+                println!(
+                    "    range     {} .. {}  mean {}  stddev {:.4}",
+                    column.format_stat(m.min),
+                    column.format_stat(m.max),
+                    column.format_stat(m.mean),
+                    m.stddev()
+                );
+            }
+            if let Some(m) = &column.lengths {
+                println!("    length    {} .. {}  mean {:.1}", m.min, m.max, m.mean);
+            }
+            let top = column.top_values(self.options.top_k.min(5));
+            if !top.is_empty() {
+                let rendered: Vec<String> = top
+                    .iter()
+                    .map(|(value, count)| format!("{} ({})", truncate(value, 24), count))
+                    .collect();
+                println!("    top       {}", rendered.join(", "));
+            }
+            if !column.histogram.is_empty() {
+                println!("    histogram {}", sparkline(&column.histogram).green());
+            }
+        }
+    }
+
+    /// A single HTML page with inline styles, no external assets
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "<title>Profile of {}</title>\n",
+            escape_html(&self.path)
+        ));
+        html.push_str(
+            "<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+.column { border: 1px solid #ddd; border-radius: 6px; padding: 1em; margin: 1em 0; }
+table { border-collapse: collapse; }
+td, th { padding: 2px 10px; text-align: left; }
+.bar { background: #4c8bf5; height: 12px; }
+.type { color: #888; font-size: 0.9em; }
+svg rect { fill: #4c8bf5; }
+</style></head><body>\n",
+        );
+        html.push_str(&format!(
+            "<h1>{}</h1>\n<p>{} files, {} rows</p>\n",
+            escape_html(&self.path),
+            self.files,
+            self.num_rows
+        ));
+
+        for column in &self.columns {
+            html.push_str(&format!(
+                "<div class=\"column\"><h2>{} <span class=\"type\">{:?}</span></h2>\n<table>\n",
+                escape_html(&column.name),
+                column.arrow_type
+            ));
+            let mut row = |name: &str, value: String| {
+                html.push_str(&format!(
+                    "<tr><th>{}</th><td>{}</td></tr>\n",
+                    name,
+                    escape_html(&value)
+                ))
+            };
+            row(
+                "Nulls",
+                format!("{} ({:.1}%)", column.nulls, column.null_rate() * 100.0),
+            );
+            row("Distinct", format!("~{}", column.distinct_estimate()));
+            if let Some(m) = &column.values {
+                row("Min", column.format_stat(m.min));
+                row("Max", column.format_stat(m.max));
+                row("Mean", column.format_stat(m.mean));
+                row("Stddev", format!("{:.4}", m.stddev()));
+            }
+            if let Some(m) = &column.lengths {
+                row(
+                    "Length",
+                    format!("{} .. {}, mean {:.1}", m.min, m.max, m.mean),
+                );
+            }
+            html.push_str("</table>\n");
+
+            let top = column.top_values(self.options.top_k);
+            if let Some((_, most)) = top.first() {
+                html.push_str("<h3>Top values</h3>\n<table>\n");
+                for (value, count) in &top {
+                    html.push_str(&format!(
+                        "<tr><td>{}</td><td>{}</td><td><div class=\"bar\" style=\"width: {}px\"></div></td></tr>\n",
+                        escape_html(value),
+                        count,
+                        count * 200 / most.max(&1)
+                    ));
+                }
+                html.push_str("</table>\n");
+            }
+            if !column.histogram.is_empty() {
+                html.push_str("<h3>Histogram</h3>\n");
+                html.push_str(&svg_histogram(&column.histogram, &column.bin_edges()));
+            }
+            if !column.length_buckets.is_empty() {
+                let labels: Vec<String> = (0..column.length_buckets.len())
+                    .map(length_bucket_label)
+                    .collect();
+                html.push_str("<h3>String lengths</h3>\n");
+                html.push_str(&svg_histogram(&column.length_buckets, &labels));
+            }
+            html.push_str("</div>\n");
+        }
+        html.push_str("</body></html>\n");
+        html
+    }
+}
+
+fn svg_histogram(counts: &[usize], labels: &[String]) -> String {
+    let (bar, height) = (40, 100);
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mut svg = format!(
+        "<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">\n",
+        counts.len() * bar,
+        height + 20
+    );
+    for (i, (count, label)) in counts.iter().zip(labels).enumerate() {
+        let h = count * height / max;
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"><title>{}: {}</title></rect>\n",
+            i * bar + 2,
+            height - h,
+            bar - 4,
+            h,
+            escape_html(label),
+            count
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"9\">{}</text>\n",
+            i * bar + 2,
+            height + 12,
+            escape_html(&truncate(label, 8))
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn sparkline(counts: &[usize]) -> String {
+    let blocks = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    counts
+        .iter()
+        .map(|&c| blocks[c * (blocks.len() - 1) / max])
+        .collect()
+}
+
+fn truncate(value: &str, max: usize) -> String {
+    if value.chars().count() > max {
+        format!("{}…", value.chars().take(max).collect::<String>())
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::FileFormat;
+    use crate::sql;
+
+    #[test]
+    fn test_profile_dataset() {
+        let table = sql::parse_create_table(
+            "CREATE TABLE Scores (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Team VARCHAR(10),
+            Points DOUBLE
+        )",
+        );
+        let rows: Vec<Vec<Value>> = (0..100)
+            .map(|i| {
+                vec![
+                    Value::Int(i),
+                    if i % 4 == 0 {
+                        Value::Null
+                    } else {
+                        Value::Str(["Lions", "Bears", "Owls"][i as usize % 3].to_string())
+                    },
+                    Value::Double(i as f64),
+                ]
+            })
+            .collect();
+        let dir = tempdir::TempDir::new("profile").unwrap();
+        model::write_rows(
+            &dir.path().join("Scores.parquet"),
+            &table,
+            &rows,
+            FileFormat::Parquet,
+        )
+        .unwrap();
+
+        let report = profile_dataset(dir.path(), &ProfileOptions::default()).unwrap();
+        assert_eq!(report.num_rows, 100);
+
+        let team = &report.columns[1];
+        assert_eq!(team.nulls, 25);
+        assert_eq!(team.distinct_estimate(), 3);
+        assert_eq!(team.lengths.as_ref().unwrap().max, 5.0);
+
+        let points = &report.columns[2];
+        let moments = points.values.as_ref().unwrap();
+        assert_eq!((moments.min, moments.max, moments.mean), (0.0, 99.0, 49.5));
+        assert!((moments.stddev() - 29.0115).abs() < 1e-3);
+        assert_eq!(points.histogram, vec![10; 10]);
+
+        let html = report.to_html();
+        assert!(html.contains("<svg") && html.contains("Lions"));
+        assert_eq!(report.to_json()["columns"][0]["distinct_estimate"], 100);
+    }
+}
@@ -0,0 +1,369 @@
+//! Declarative S3 buckets: the settings the input and output buckets of a
+//! pipeline should have, and how an existing bucket differs from them.
+use colored::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// A `provision` config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProvisionConfig {
+    /// Region of buckets that do not name one, else AWS_REGION or us-east-1
+    pub region: Option<String>,
+    /// Tags every bucket gets
+    pub tags: BTreeMap<String, String>,
+    pub buckets: Vec<BucketConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BucketConfig {
+    pub name: String,
+    /// `input` or `output`, also tagged as `lernspark:role`
+    pub role: Option<String>,
+    pub region: Option<String>,
+    #[serde(default = "enabled")]
+    pub versioning: bool,
+    /// `sse-s3`, `aws:kms` or `none`
+    #[serde(default = "sse_s3")]
+    pub encryption: String,
+    /// KMS key for `aws:kms`, the AWS managed key when absent
+    pub kms_key_id: Option<String>,
+    #[serde(default = "enabled")]
+    pub block_public_access: bool,
+    #[serde(default)]
+    pub lifecycle: Vec<LifecycleRule>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+fn sse_s3() -> String {
+    "sse-s3".to_string()
+}
+
+/// Default encryption of new objects
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encryption {
+    SseS3,
+    Kms(Option<String>),
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encryption::SseS3 => write!(f, "SSE-S3"),
+            Encryption::Kms(Some(key)) => write!(f, "SSE-KMS ({})", key),
+            Encryption::Kms(None) => write!(f, "SSE-KMS (aws/s3)"),
+        }
+    }
+}
+
+/// Move objects under `prefix` to another storage class after some days,
+/// and optionally expire them
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LifecycleRule {
+    pub id: String,
+    #[serde(default)]
+    pub prefix: String,
+    pub transition_days: Option<i32>,
+    #[serde(default = "deep_archive")]
+    pub storage_class: String,
+    pub expiration_days: Option<i32>,
+}
+
+fn deep_archive() -> String {
+    "DEEP_ARCHIVE".to_string()
+}
+
+impl fmt::Display for LifecycleRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on '{}'", self.id, self.prefix)?;
+        if let Some(days) = self.transition_days {
+            write!(f, " → {} after {}d", self.storage_class, days)?;
+        }
+        if let Some(days) = self.expiration_days {
+            write!(f, " expire after {}d", days)?;
+        }
+        Ok(())
+    }
+}
+
+/// The settings of a bucket, as configured or as found in S3
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketState {
+    pub region: String,
+    pub versioning: bool,
+    pub encryption: Option<Encryption>,
+    pub block_public_access: bool,
+    pub lifecycle: Vec<LifecycleRule>,
+    pub tags: BTreeMap<String, String>,
+}
+
+impl ProvisionConfig {
+    pub fn load(path: &Path) -> Result<ProvisionConfig, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+        let config: ProvisionConfig = serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid provision config '{}': {}", path.display(), e))?;
+        if config.buckets.is_empty() {
+            return Err(format!("'{}' lists no buckets", path.display()));
+        }
+        Ok(config)
+    }
+
+    /// The state each bucket should be in, `default_region` applies when
+    /// neither the bucket nor the config name a region
+    pub fn desired(&self, default_region: &str) -> Result<Vec<(String, BucketState)>, String> {
+        self.buckets
+            .iter()
+            .map(|bucket| {
+                let encryption = match bucket.encryption.to_lowercase().as_str() {
+                    "sse-s3" | "aes256" => Some(Encryption::SseS3),
+                    "aws:kms" | "sse-kms" | "kms" => {
+                        Some(Encryption::Kms(bucket.kms_key_id.clone()))
+                    }
+                    "none" => None,
+                    other => {
+                        return Err(format!(
+                            "Unknown encryption '{}' for bucket '{}', use sse-s3, aws:kms or none",
+                            other, bucket.name
+                        ))
+                    }
+                };
+                let mut tags = self.tags.clone();
+                if let Some(role) = &bucket.role {
+                    tags.insert("lernspark:role".to_string(), role.clone());
+                }
+                tags.extend(bucket.tags.clone());
+                let mut lifecycle = bucket.lifecycle.clone();
+                lifecycle.sort_by(|a, b| a.id.cmp(&b.id));
+                let region = bucket
+                    .region
+                    .as_deref()
+                    .or(self.region.as_deref())
+                    .unwrap_or(default_region);
+                let state = BucketState {
+                    region: region.to_string(),
+                    versioning: bucket.versioning,
+                    encryption,
+                    block_public_access: bucket.block_public_access,
+                    lifecycle,
+                    tags,
+                };
+                Ok((bucket.name.clone(), state))
+            })
+            .collect()
+    }
+}
+
+/// A bucket setting that can drift
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Setting {
+    /// Buckets cannot move, only reported
+    Region,
+    Versioning,
+    Encryption,
+    PublicAccessBlock,
+    Lifecycle,
+    Tags,
+}
+
+impl Setting {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Setting::Region => "region",
+            Setting::Versioning => "versioning",
+            Setting::Encryption => "encryption",
+            Setting::PublicAccessBlock => "public access block",
+            Setting::Lifecycle => "lifecycle",
+            Setting::Tags => "tags",
+        }
+    }
+}
+
+/// A setting whose value in S3 is not the configured one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    pub setting: Setting,
+    pub expected: String,
+    pub actual: String,
+}
+
+fn on_off(enabled: bool) -> String {
+    if enabled { "enabled" } else { "disabled" }.to_string()
+}
+
+fn encryption_text(encryption: &Option<Encryption>) -> String {
+    match encryption {
+        Some(encryption) => encryption.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn rules_text(rules: &[LifecycleRule]) -> String {
+    if rules.is_empty() {
+        return "no rules".to_string();
+    }
+    rules
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn tags_text(tags: &BTreeMap<String, String>) -> String {
+    if tags.is_empty() {
+        return "no tags".to_string();
+    }
+    tags.iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Every setting of `actual` that differs from `desired`. Lifecycle rules are
+/// replaced as a whole, tags only need to include the configured ones.
+pub fn drift(desired: &BucketState, actual: &BucketState) -> Vec<Drift> {
+    let mut drifts = Vec::new();
+    let mut check = |setting, differs: bool, expected: String, actual: String| {
+        if differs {
+            drifts.push(Drift {
+                setting,
+                expected,
+                actual,
+            });
+        }
+    };
+    check(
+        Setting::Region,
+        desired.region != actual.region,
+        desired.region.clone(),
+        actual.region.clone(),
+    );
+    check(
+        Setting::Versioning,
+        desired.versioning != actual.versioning,
+        on_off(desired.versioning),
+        on_off(actual.versioning),
+    );
+    // `none` leaves whatever encryption a bucket has, S3 always encrypts now
+    check(
+        Setting::Encryption,
+        desired.encryption.is_some() && desired.encryption != actual.encryption,
+        encryption_text(&desired.encryption),
+        encryption_text(&actual.encryption),
+    );
+    check(
+        Setting::PublicAccessBlock,
+        desired.block_public_access != actual.block_public_access,
+        on_off(desired.block_public_access),
+        on_off(actual.block_public_access),
+    );
+    let mut rules = actual.lifecycle.clone();
+    rules.sort_by(|a, b| a.id.cmp(&b.id));
+    check(
+        Setting::Lifecycle,
+        desired.lifecycle != rules,
+        rules_text(&desired.lifecycle),
+        rules_text(&rules),
+    );
+    let missing: BTreeMap<String, String> = desired
+        .tags
+        .iter()
+        .filter(|(k, v)| actual.tags.get(*k) != Some(v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    check(
+        Setting::Tags,
+        !missing.is_empty(),
+        tags_text(&missing),
+        tags_text(&actual.tags),
+    );
+    drifts
+}
+
+/// One line per drifted setting
+pub fn print_drift(drifts: &[Drift]) {
+    for drift in drifts {
+        println!(
+            "    {} {}: expected {}, found {}",
+            "≠".yellow(),
+            drift.setting.as_str().bold(),
+            drift.expected.green(),
+            drift.actual.red()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_and_drift() {
+        let config: ProvisionConfig = serde_json::from_str(
+            r#"{
+                "region": "eu-west-1",
+                "tags": {"project": "lernspark"},
+                "buckets": [
+                    {"name": "raw", "role": "input", "lifecycle": [
+                        {"id": "archive", "prefix": "raw/", "transition_days": 30}
+                    ]},
+                    {"name": "out", "region": "us-east-2", "versioning": false,
+                     "encryption": "aws:kms", "kms_key_id": "alias/data"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let desired = config.desired("us-east-1").unwrap();
+        let (name, raw) = &desired[0];
+        assert_eq!(name, "raw");
+        assert_eq!(raw.region, "eu-west-1");
+        assert_eq!(raw.encryption, Some(Encryption::SseS3));
+        assert_eq!(raw.lifecycle[0].storage_class, "DEEP_ARCHIVE");
+        assert_eq!(raw.tags.get("lernspark:role").unwrap(), "input");
+        let out = &desired[1].1;
+        assert_eq!(out.region, "us-east-2");
+        assert_eq!(
+            out.encryption,
+            Some(Encryption::Kms(Some("alias/data".into())))
+        );
+
+        // A bucket with nothing configured needs everything but its region
+        let nothing = BucketState {
+            region: "eu-west-1".to_string(),
+            versioning: false,
+            encryption: None,
+            block_public_access: false,
+            lifecycle: Vec::new(),
+            tags: BTreeMap::new(),
+        };
+        let settings: Vec<Setting> = drift(raw, &nothing).iter().map(|d| d.setting).collect();
+        assert_eq!(
+            settings,
+            vec![
+                Setting::Versioning,
+                Setting::Encryption,
+                Setting::PublicAccessBlock,
+                Setting::Lifecycle,
+                Setting::Tags,
+            ]
+        );
+
+        // Extra tags are fine, a moved region is not
+        let mut actual = raw.clone();
+        actual.tags.insert("owner".into(), "team".into());
+        assert!(drift(raw, &actual).is_empty());
+        actual.region = "us-east-1".into();
+        actual.versioning = false;
+        let drifts = drift(raw, &actual);
+        assert_eq!(drifts.len(), 2);
+        assert_eq!(drifts[1].expected, "enabled");
+        assert_eq!(drifts[1].actual, "disabled");
+    }
+}
@@ -0,0 +1,234 @@
+//! Glacier restores: find the archived objects of a dataset, ask S3 to bring
+//! them back and wait until every one can be read.
+use crate::aws::S3Store;
+use crate::store::{ObjectMeta, ObjectStore};
+use crate::upload;
+use colored::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// Whether an object can be read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestoreState {
+    Readable,
+    /// Archived with no restore requested
+    Archived,
+    Restoring,
+    /// A temporary copy is readable until the restore expires
+    Restored,
+}
+
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    /// How long restored copies of GLACIER and DEEP_ARCHIVE objects stay readable
+    pub days: i32,
+    /// `Standard`, `Bulk` or `Expedited`, how fast and at what price
+    pub tier: &'static str,
+    /// Poll until everything is readable instead of returning after the requests
+    pub wait: bool,
+    pub interval: Duration,
+    /// Objects checked at once
+    pub concurrency: usize,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            days: 7,
+            tier: "Standard",
+            wait: true,
+            interval: Duration::from_secs(300),
+            concurrency: 8,
+        }
+    }
+}
+
+pub fn parse_tier(name: &str) -> Result<&'static str, String> {
+    match name.to_lowercase().as_str() {
+        "standard" => Ok("Standard"),
+        "bulk" => Ok("Bulk"),
+        "expedited" => Ok("Expedited"),
+        _ => Err(format!(
+            "Unknown restore tier '{}', use standard, bulk or expedited",
+            name
+        )),
+    }
+}
+
+/// Listed storage classes whose objects can need a restore. Intelligent-Tiering
+/// objects only do once moved to an archive tier, which only their headers show.
+pub fn may_be_archived(storage_class: Option<&str>) -> bool {
+    matches!(
+        storage_class,
+        Some("GLACIER" | "DEEP_ARCHIVE" | "INTELLIGENT_TIERING")
+    )
+}
+
+/// The state of an object from its storage class and its `x-amz-archive-status`
+/// and `x-amz-restore` headers, the latter like `ongoing-request="false",
+/// expiry-date="Fri, 21 Dec 2012 00:00:00 GMT"`
+pub fn restore_state(
+    storage_class: Option<&str>,
+    archive_status: Option<&str>,
+    restore: Option<&str>,
+) -> RestoreState {
+    let archived =
+        matches!(storage_class, Some("GLACIER" | "DEEP_ARCHIVE")) || archive_status.is_some();
+    match restore {
+        Some(restore) if restore.contains("ongoing-request=\"true\"") => RestoreState::Restoring,
+        Some(restore) if restore.contains("ongoing-request=\"false\"") => RestoreState::Restored,
+        _ if archived => RestoreState::Archived,
+        _ => RestoreState::Readable,
+    }
+}
+
+/// The state of every object, `concurrency` at a time
+async fn states(
+    store: &Arc<S3Store>,
+    objects: &[ObjectMeta],
+    concurrency: usize,
+) -> Result<Vec<RestoreState>, String> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = Vec::new();
+    for object in objects {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| e.to_string())?;
+        let (store, key) = (store.clone(), object.key.clone());
+        tasks.push(tokio::spawn(async move {
+            let _permit = permit;
+            store.restore_state(&key).await
+        }));
+    }
+    let mut states = Vec::new();
+    for task in tasks {
+        states.push(
+            task.await
+                .map_err(|e| format!("Error joining restore task: {}", e))??,
+        );
+    }
+    Ok(states)
+}
+
+/// Request a restore of every archived object under `prefix`, then, with
+/// `wait`, poll every `interval` until all of them are readable
+pub async fn restore_dataset(
+    store: Arc<S3Store>,
+    prefix: &str,
+    options: &RestoreOptions,
+) -> Result<(), String> {
+    let objects = store.list(&upload::list_prefix(prefix)).await?;
+    let total = objects.len();
+    let candidates: Vec<ObjectMeta> = objects
+        .into_iter()
+        .filter(|o| may_be_archived(o.storage_class.as_deref()))
+        .collect();
+    let mut readable = total - candidates.len();
+    let mut requested = 0;
+    let mut pending = Vec::new();
+    for (object, state) in candidates
+        .iter()
+        .zip(states(&store, &candidates, options.concurrency).await?)
+    {
+        match state {
+            RestoreState::Archived => {
+                // Intelligent-Tiering restores move the object back to a
+                // frequent access tier and take no number of days
+                let days = (object.storage_class.as_deref() != Some("INTELLIGENT_TIERING"))
+                    .then_some(options.days);
+                if store
+                    .request_restore(&object.key, days, options.tier)
+                    .await?
+                {
+                    requested += 1;
+                }
+                pending.push(object.clone());
+            }
+            RestoreState::Restoring => pending.push(object.clone()),
+            RestoreState::Readable | RestoreState::Restored => readable += 1,
+        }
+    }
+    println!(
+        "🧊 {} objects: {} readable, {} restores requested, {} already in progress",
+        total.to_string().bold(),
+        readable.to_string().bold().green(),
+        requested.to_string().bold().cyan(),
+        (pending.len() - requested).to_string().bold().yellow()
+    );
+    if !options.wait {
+        return Ok(());
+    }
+
+    while !pending.is_empty() {
+        println!(
+            "⏳ {} objects still restoring ({} tier), checking again in {}s",
+            pending.len().to_string().yellow(),
+            options.tier,
+            options.interval.as_secs()
+        );
+        tokio::time::sleep(options.interval).await;
+        let states = states(&store, &pending, options.concurrency).await?;
+        pending = pending
+            .into_iter()
+            .zip(states)
+            .filter(|(_, state)| matches!(state, RestoreState::Archived | RestoreState::Restoring))
+            .map(|(object, _)| object)
+            .collect();
+    }
+    println!(
+        "✅ All {} objects under {}/{} are readable",
+        total.to_string().bold().green(),
+        store.location(),
+        prefix
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_state_from_headers() {
+        let done = r#"ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT""#;
+        let cases = [
+            (Some("STANDARD"), None, None, RestoreState::Readable),
+            (Some("GLACIER_IR"), None, None, RestoreState::Readable),
+            (Some("DEEP_ARCHIVE"), None, None, RestoreState::Archived),
+            (
+                Some("GLACIER"),
+                None,
+                Some(r#"ongoing-request="true""#),
+                RestoreState::Restoring,
+            ),
+            (
+                Some("DEEP_ARCHIVE"),
+                None,
+                Some(done),
+                RestoreState::Restored,
+            ),
+            (
+                Some("INTELLIGENT_TIERING"),
+                Some("ARCHIVE_ACCESS"),
+                None,
+                RestoreState::Archived,
+            ),
+            (
+                Some("INTELLIGENT_TIERING"),
+                None,
+                None,
+                RestoreState::Readable,
+            ),
+        ];
+        for (class, archive_status, restore, expected) in cases {
+            assert_eq!(restore_state(class, archive_status, restore), expected);
+        }
+        assert!(may_be_archived(Some("INTELLIGENT_TIERING")));
+        assert!(!may_be_archived(Some("GLACIER_IR")));
+        assert_eq!(parse_tier("BULK").unwrap(), "Bulk");
+        assert!(parse_tier("fast").is_err());
+    }
+}
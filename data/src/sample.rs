@@ -0,0 +1,473 @@
+//! Copy a small subset of a stored dataset to a local directory, keeping its
+//! layout and schema, so Spark jobs can be tried on it quickly.
+use crate::store::{ObjectMeta, ObjectStore};
+use crate::upload::{self, MANIFEST};
+use arrow::record_batch::RecordBatch;
+use bytes::{Bytes, BytesMut};
+use chrono::Utc;
+use parquet::arrow::{ArrowReader, ArrowWriter, ParquetFileArrowReader};
+use parquet::errors::ParquetError;
+use parquet::file::footer;
+use parquet::file::reader::{ChunkReader, Length, SerializedFileReader};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Describes the sample in the output directory
+pub const SAMPLE: &str = "_sample.json";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A condition on a partition column such as `dt>=2024-05-01`, compared as text
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionFilter {
+    pub column: String,
+    op: Op,
+    pub value: String,
+}
+
+impl PartitionFilter {
+    /// Parse a comma separated list of `column(=|!=|<|<=|>|>=)value` conditions
+    pub fn parse_list(spec: &str) -> Result<Vec<PartitionFilter>, String> {
+        spec.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(PartitionFilter::parse)
+            .collect()
+    }
+
+    pub fn parse(condition: &str) -> Result<PartitionFilter, String> {
+        // Two character operators first so `>=` is not read as `>`
+        let ops = [
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        for (symbol, op) in ops {
+            if let Some((column, value)) = condition.split_once(symbol) {
+                if column.is_empty() || column.ends_with(['!', '<', '>']) {
+                    continue;
+                }
+                return Ok(PartitionFilter {
+                    column: column.trim().to_string(),
+                    op,
+                    value: value.trim().to_string(),
+                });
+            }
+        }
+        Err(format!(
+            "Invalid partition filter '{}', expected e.g. dt>=2024-05-01",
+            condition
+        ))
+    }
+
+    /// Whether a partition path like `dt=2024-05-01/region=eu` passes, a
+    /// partition without the column never does
+    pub fn matches(&self, partition: &str) -> bool {
+        let value = partition
+            .split('/')
+            .filter_map(|d| d.split_once('='))
+            .find(|(column, _)| *column == self.column)
+            .map(|(_, value)| value);
+        let value = match value {
+            Some(value) => value,
+            None => return false,
+        };
+        let wanted = self.value.as_str();
+        match self.op {
+            Op::Eq => value == wanted,
+            Op::Ne => value != wanted,
+            Op::Lt => value < wanted,
+            Op::Le => value <= wanted,
+            Op::Gt => value > wanted,
+            Op::Ge => value >= wanted,
+        }
+    }
+}
+
+impl std::fmt::Display for PartitionFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let op = match self.op {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{}{}{}", self.column, op, self.value)
+    }
+}
+
+/// How much of the dataset to keep
+#[derive(Debug, Clone, Default)]
+pub struct SampleOptions {
+    pub filters: Vec<PartitionFilter>,
+    /// Parquet files kept per table, in key order
+    pub files: Option<usize>,
+    /// Share of the rows kept from each Parquet file
+    pub fraction: Option<f64>,
+}
+
+/// A stored object picked for the sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampledObject {
+    pub key: String,
+    /// The key below the dataset prefix, also its path in the output directory
+    pub relative: String,
+    pub table: Option<String>,
+    pub partition: Option<String>,
+    pub size: u64,
+}
+
+/// Pick the objects of the dataset under `prefix` that make up the sample.
+/// Parquet files are filtered by partition and counted per table; other
+/// files are kept when they sit in a directory of a sampled table.
+pub fn select(objects: &[ObjectMeta], prefix: &str, options: &SampleOptions) -> Vec<SampledObject> {
+    let list_prefix = upload::list_prefix(prefix);
+    let mut per_table: BTreeMap<String, usize> = BTreeMap::new();
+    let mut selected = Vec::new();
+    let mut others = Vec::new();
+    for object in objects {
+        let relative = match object.key.strip_prefix(&list_prefix) {
+            Some(relative) if !relative.is_empty() && relative != MANIFEST => relative,
+            _ => continue,
+        };
+        let components: Vec<String> = relative.split('/').map(str::to_string).collect();
+        let (file, dirs) = components.split_last().unwrap();
+        let stem = match file.strip_suffix(".parquet") {
+            Some(stem) => stem,
+            None => {
+                others.push((relative.to_string(), object.size));
+                continue;
+            }
+        };
+
+        let (table, partition) = upload::table_and_partition(dirs, stem);
+        let passes = options
+            .filters
+            .iter()
+            .all(|f| partition.as_deref().is_some_and(|p| f.matches(p)));
+        if !passes {
+            continue;
+        }
+        let count = per_table.entry(table.clone()).or_insert(0);
+        if options.files.is_some_and(|files| *count >= files) {
+            continue;
+        }
+        *count += 1;
+        selected.push(SampledObject {
+            key: object.key.clone(),
+            relative: relative.to_string(),
+            table: Some(table),
+            partition,
+            size: object.size,
+        });
+    }
+
+    let tables: BTreeSet<String> = per_table
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(table, _)| table)
+        .collect();
+    for (relative, size) in others {
+        if tables
+            .iter()
+            .any(|t| relative.starts_with(&format!("{}/", t)))
+        {
+            selected.push(SampledObject {
+                key: format!("{}{}", list_prefix, relative),
+                relative,
+                table: None,
+                partition: None,
+                size,
+            });
+        }
+    }
+    selected.sort_by(|a, b| a.key.cmp(&b.key));
+    selected
+}
+
+/// The `_sample.json` written next to the sample, with the rows kept of
+/// every file whose rows were sampled
+pub fn describe(
+    source: &str,
+    options: &SampleOptions,
+    objects: &[SampledObject],
+    rows: &BTreeMap<String, (i64, i64)>,
+) -> serde_json::Value {
+    json!({
+        "source": source,
+        "created_at": Utc::now().to_rfc3339(),
+        "filters": options.filters.iter().map(|f| f.to_string()).collect::<Vec<_>>(),
+        "files_per_table": options.files,
+        "fraction": options.fraction,
+        "files": objects.iter().map(|o| {
+            let mut file = json!({"key": o.relative, "table": o.table, "partition": o.partition});
+            if let Some((total, kept)) = rows.get(&o.relative) {
+                file["rows"] = json!(total);
+                file["sampled_rows"] = json!(kept);
+            }
+            file
+        }).collect::<Vec<_>>(),
+    })
+}
+
+/// How much of the end of a Parquet object is fetched first, enough for the
+/// footer of most files
+const FOOTER_READ: u64 = 64 * 1024;
+
+/// A Parquet object of which only some byte ranges were fetched: the footer
+/// and the row groups that are read
+struct PartialFile {
+    len: u64,
+    chunks: Vec<(u64, Bytes)>,
+}
+
+impl PartialFile {
+    /// Fetch what `range` adds to the first chunk, the end of the file. A
+    /// range reaching that chunk is joined to it, so every read the Parquet
+    /// reader makes stays within one chunk.
+    async fn fetch(
+        &mut self,
+        store: &dyn ObjectStore,
+        key: &str,
+        range: Range<u64>,
+    ) -> Result<(), String> {
+        let (tail_start, tail) = self.chunks[0].clone();
+        if range.start >= tail_start {
+            return Ok(());
+        }
+        if range.end < tail_start {
+            self.chunks
+                .push((range.start, store.get_range(key, range).await?));
+            return Ok(());
+        }
+        let head = store.get_range(key, range.start..tail_start).await?;
+        let mut joined = BytesMut::with_capacity(head.len() + tail.len());
+        joined.extend_from_slice(&head);
+        joined.extend_from_slice(&tail);
+        self.chunks[0] = (range.start, joined.freeze());
+        Ok(())
+    }
+}
+
+impl Length for PartialFile {
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl ChunkReader for PartialFile {
+    type T = Cursor<Bytes>;
+
+    fn get_read(&self, start: u64, length: usize) -> parquet::errors::Result<Cursor<Bytes>> {
+        let end = start + length as u64;
+        self.chunks
+            .iter()
+            .find(|(at, chunk)| *at <= start && end <= at + chunk.len() as u64)
+            .map(|(at, chunk)| Cursor::new(chunk.slice((start - at) as usize..(end - at) as usize)))
+            .ok_or_else(|| {
+                ParquetError::General(format!("Bytes {}..{} were not fetched", start, end))
+            })
+    }
+}
+
+/// Write the first `fraction` of the rows of the Parquet object `key`, of
+/// `size` bytes, to `path`. Only the footer and the leading row groups that
+/// hold those rows are fetched, so the sample is the start of every file, not
+/// a random subset of its rows. The Arrow schema, with its metadata, is kept.
+/// Returns the rows in the file, the rows written and the bytes fetched.
+pub async fn sample_parquet(
+    store: &dyn ObjectStore,
+    key: &str,
+    size: u64,
+    fraction: f64,
+    path: &Path,
+) -> Result<(i64, i64, u64), String> {
+    let read_error = |e: ParquetError| format!("Unable to read Parquet '{}': {}", key, e);
+    if size < 12 {
+        return Err(format!("'{}' is too small to be a Parquet file", key));
+    }
+    let start = size.saturating_sub(FOOTER_READ);
+    let tail = store.get_range(key, start..size).await?;
+    let at = tail.len() - 8;
+    let footer = u32::from_le_bytes(tail[at..at + 4].try_into().unwrap()) as u64 + 8;
+    if footer > size {
+        return Err(format!("'{}' has a corrupt Parquet footer", key));
+    }
+    let mut file = PartialFile {
+        len: size,
+        chunks: vec![(start, tail)],
+    };
+    file.fetch(store, key, size - footer..size).await?;
+
+    let metadata = footer::parse_metadata(&file).map_err(read_error)?;
+    let total = metadata.file_metadata().num_rows();
+    let target = ((total as f64 * fraction).ceil() as i64).clamp(total.min(1), total);
+    let mut covered = 0;
+    let mut keep = 0;
+    let mut bytes: Option<Range<u64>> = None;
+    for group in metadata.row_groups() {
+        if covered >= target {
+            break;
+        }
+        covered += group.num_rows();
+        keep += 1;
+        for column in group.columns() {
+            let (start, length) = column.byte_range();
+            let end = start + length;
+            bytes = Some(match bytes {
+                Some(range) => range.start.min(start)..range.end.max(end),
+                None => start..end,
+            });
+        }
+    }
+    if let Some(range) = bytes {
+        file.fetch(store, key, range).await?;
+    }
+    let fetched = file
+        .chunks
+        .iter()
+        .map(|(_, chunk)| chunk.len() as u64)
+        .sum();
+
+    let mut reader = SerializedFileReader::new(file).map_err(read_error)?;
+    reader.filter_row_groups(&|_, i| i < keep);
+    let mut arrow = ParquetFileArrowReader::new(Arc::new(reader));
+    let schema = Arc::new(arrow.get_schema().map_err(read_error)?);
+    let batches = arrow.get_record_reader(8192).map_err(read_error)?;
+
+    let write_error = |e: ParquetError| format!("Error writing '{}': {}", path.display(), e);
+    let file =
+        File::create(path).map_err(|e| format!("Error creating '{}': {}", path.display(), e))?;
+    let mut writer = ArrowWriter::try_new(file, schema, None).map_err(write_error)?;
+    let mut written = 0;
+    for batch in batches {
+        if written >= target {
+            break;
+        }
+        let batch: RecordBatch =
+            batch.map_err(|e| format!("Unable to read Parquet '{}': {}", key, e))?;
+        let take = (batch.num_rows() as i64).min(target - written);
+        writer
+            .write(&batch.slice(0, take as usize))
+            .map_err(write_error)?;
+        written += take;
+    }
+    writer.close().map_err(write_error)?;
+    Ok((total, written, fetched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::reader::FileReader;
+    use tempdir::TempDir;
+
+    fn meta(key: &str) -> ObjectMeta {
+        ObjectMeta {
+            key: key.to_string(),
+            size: 1,
+            etag: None,
+            storage_class: None,
+        }
+    }
+
+    #[test]
+    fn test_select_filters_partitions_and_counts_files() {
+        let objects: Vec<ObjectMeta> = [
+            "raw/_manifest.json",
+            "raw/events/_evolution.json",
+            "raw/events/dt=2024-05-01/part-00000.parquet",
+            "raw/events/dt=2024-05-02/part-00000.parquet",
+            "raw/events/dt=2024-05-02/part-00001.parquet",
+            "raw/events/dt=2024-05-03/part-00000.parquet",
+            "raw/teams/_evolution.json",
+            "raw/teams/part-00000.parquet",
+        ]
+        .iter()
+        .map(|k| meta(k))
+        .collect();
+        let options = SampleOptions {
+            filters: PartitionFilter::parse_list("dt>=2024-05-02").unwrap(),
+            files: Some(2),
+            fraction: None,
+        };
+        let relative: Vec<String> = select(&objects, "raw", &options)
+            .into_iter()
+            .map(|o| o.relative)
+            .collect();
+        assert_eq!(
+            relative,
+            vec![
+                "events/_evolution.json",
+                "events/dt=2024-05-02/part-00000.parquet",
+                "events/dt=2024-05-02/part-00001.parquet",
+            ]
+        );
+        assert!(PartitionFilter::parse("dt").is_err());
+        assert!(PartitionFilter::parse("dt!=a").unwrap().matches("dt=b/h=1"));
+    }
+
+    #[tokio::test]
+    async fn test_sample_parquet_fetches_only_leading_row_groups() {
+        let dir = TempDir::new("sample").unwrap();
+        let source = dir.path().join("source.parquet");
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let ids: Vec<i32> = (0..300_000)
+            .map(|i: i32| i.wrapping_mul(-1640531535))
+            .collect();
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(ids))]).unwrap();
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(100_000)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(File::create(&source).unwrap(), schema, Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let store = MemoryStore::default();
+        let body = Bytes::from(std::fs::read(&source).unwrap());
+        let size = body.len() as u64;
+        store.put("t/part-00000.parquet", body, None).await.unwrap();
+        let out = dir.path().join("sample.parquet");
+        let (total, written, fetched) =
+            sample_parquet(&store, "t/part-00000.parquet", size, 0.3, &out)
+                .await
+                .unwrap();
+        assert_eq!((total, written), (300_000, 90_000));
+        // The footer and the first of three row groups
+        assert!(fetched < size / 2, "{} of {} bytes fetched", fetched, size);
+        let reader = crate::inspect::open_parquet(&out).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 90_000);
+        assert_eq!(
+            reader
+                .metadata()
+                .file_metadata()
+                .schema_descr()
+                .num_columns(),
+            1
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Explicit Spark schemas generated from data.sql: `StructType` JSON as
+//! accepted by `DataType.fromJson`, Scala case classes and PySpark
+//! `StructType` definitions.
+use serde_json::json;
+
+use crate::sql::{Column, DataType, Table};
+
+/// Spark's simple type name, as used in `StructType` JSON
+pub fn spark_type(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Int(_) => "integer",
+        DataType::BigInt(_) => "long",
+        DataType::Float(_) => "float",
+        DataType::Double(_) => "double",
+        DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_) | DataType::Enum(_) => {
+            "string"
+        }
+        DataType::DateTime(_) => "date",
+        DataType::Boolean(_) => "boolean",
+    }
+}
+
+/// True when the column may hold nulls
+pub fn is_nullable(col: &Column) -> bool {
+    !(col.is_not_null() || col.is_primary_key())
+}
+
+/// The table as a `StructType` JSON value. VARCHAR columns keep their length
+/// in the metadata key Spark itself uses for char/varchar columns.
+pub fn struct_type_json(table: &Table) -> serde_json::Value {
+    let fields: Vec<serde_json::Value> = table
+        .columns
+        .iter()
+        .map(|col| {
+            let metadata = match col.data_type {
+                DataType::VarChar(n) => {
+                    json!({ "__CHAR_VARCHAR_TYPE_STRING": format!("varchar({})", n) })
+                }
+                _ => json!({}),
+            };
+            json!({
+                "name": col.name,
+                "type": spark_type(&col.data_type),
+                "nullable": is_nullable(col),
+                "metadata": metadata,
+            })
+        })
+        .collect();
+    json!({ "type": "struct", "fields": fields })
+}
+
+const SCALA_KEYWORDS: &str = "abstract case catch class def do else extends false final \
+    finally for forSome given if implicit import lazy match new null object override package \
+    private protected return sealed super this throw trait try true type val var while with yield";
+
+/// Column names are kept as is so `Dataset.as[T]` matches them, backticked
+/// when they are not plain Scala identifiers
+fn scala_identifier(name: &str) -> String {
+    let plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if plain && !SCALA_KEYWORDS.split_whitespace().any(|k| k == name) {
+        name.to_string()
+    } else {
+        format!("`{}`", name)
+    }
+}
+
+fn scala_type(col: &Column) -> String {
+    let base = match col.data_type {
+        DataType::Int(_) => "Int",
+        DataType::BigInt(_) => "Long",
+        DataType::Float(_) => "Float",
+        DataType::Double(_) => "Double",
+        DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_) | DataType::Enum(_) => {
+            "String"
+        }
+        DataType::DateTime(_) => "java.sql.Date",
+        DataType::Boolean(_) => "Boolean",
+    };
+    if is_nullable(col) {
+        format!("Option[{}]", base)
+    } else {
+        base.to_string()
+    }
+}
+
+/// A case class per table with a companion object holding its schema
+pub fn scala_case_classes(tables: &[Table]) -> String {
+    let mut out = String::from(
+        "// Generated from data.sql, do not edit\nimport org.apache.spark.sql.types.{DataType, StructType}\n",
+    );
+    for table in tables {
+        let fields: Vec<String> = table
+            .columns
+            .iter()
+            .map(|col| format!("  {}: {}", scala_identifier(&col.name), scala_type(col)))
+            .collect();
+        out.push_str(&format!(
+            "\ncase class {}(\n{}\n)\n",
+            scala_identifier(&table.flat_name()),
+            fields.join(",\n")
+        ));
+        out.push_str(&format!(
+            "\nobject {} {{\n  val schema: StructType =\n    DataType.fromJson(\"\"\"{}\"\"\").asInstanceOf[StructType]\n}}\n",
+            scala_identifier(&table.flat_name()),
+            struct_type_json(table)
+        ));
+    }
+    out
+}
+
+fn pyspark_type(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Int(_) => "IntegerType()",
+        DataType::BigInt(_) => "LongType()",
+        DataType::Float(_) => "FloatType()",
+        DataType::Double(_) => "DoubleType()",
+        DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_) | DataType::Enum(_) => {
+            "StringType()"
+        }
+        DataType::DateTime(_) => "DateType()",
+        DataType::Boolean(_) => "BooleanType()",
+    }
+}
+
+/// `snake_case` variable name for a table's schema
+fn python_name(name: &str) -> String {
+    let mut snake = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let boundary = i > 0
+            && c.is_uppercase()
+            && (chars[i - 1].is_lowercase()
+                || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+        if boundary && !snake.ends_with('_') {
+            snake.push('_');
+        }
+        if c.is_alphanumeric() {
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push('_');
+        }
+    }
+    snake
+}
+
+/// A `<table>_schema` `StructType` per table
+pub fn pyspark_struct_types(tables: &[Table]) -> String {
+    let mut out = String::from(
+        "# Generated from data.sql, do not edit\nfrom pyspark.sql.types import (\n    BooleanType,\n    DateType,\n    DoubleType,\n    FloatType,\n    IntegerType,\n    LongType,\n    StringType,\n    StructField,\n    StructType,\n)\n",
+    );
+    for table in tables {
+        out.push_str(&format!(
+            "\n{}_schema = StructType(\n    [\n",
+            python_name(&table.flat_name())
+        ));
+        for col in &table.columns {
+            let comment = match col.data_type {
+                DataType::VarChar(n) => format!("  # VARCHAR({})", n),
+                DataType::UUID(_) => "  # UUID".to_string(),
+                DataType::Enum(_) => format!("  # {}", col.data_type),
+                _ => String::new(),
+            };
+            out.push_str(&format!(
+                "        StructField(\"{}\", {}, {}),{}\n",
+                col.name,
+                pyspark_type(&col.data_type),
+                if is_nullable(col) { "True" } else { "False" },
+                comment
+            ));
+        }
+        out.push_str("    ]\n)\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql;
+
+    #[test]
+    fn test_spark_schemas() {
+        let table = sql::parse_create_table(
+            "CREATE TABLE PlayerStats (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            Nick VARCHAR(20) NOT NULL,
+            Rating DOUBLE,
+            type TEXT
+        )",
+        );
+
+        assert_eq!(
+            struct_type_json(&table).to_string(),
+            r#"{"type":"struct","fields":[{"name":"ID","type":"integer","nullable":false,"metadata":{}},{"name":"Nick","type":"string","nullable":false,"metadata":{"__CHAR_VARCHAR_TYPE_STRING":"varchar(20)"}},{"name":"Rating","type":"double","nullable":true,"metadata":{}},{"name":"type","type":"string","nullable":true,"metadata":{}}]}"#
+        );
+
+        let scala = scala_case_classes(std::slice::from_ref(&table));
+        assert!(scala.contains(
+            "case class PlayerStats(\n  ID: Int,\n  Nick: String,\n  Rating: Option[Double],\n  `type`: Option[String]\n)"
+        ));
+        assert!(scala.contains("DataType.fromJson(\"\"\"{\"type\":\"struct\""));
+
+        let python = pyspark_struct_types(&[table]);
+        assert!(python.contains("player_stats_schema = StructType("));
+        assert!(python.contains("StructField(\"Nick\", StringType(), False),  # VARCHAR(20)"));
+        assert!(python.contains("StructField(\"Rating\", DoubleType(), True),"));
+    }
+}
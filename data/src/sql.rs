@@ -2,72 +2,775 @@ use chrono::NaiveDateTime;
 use regex::Regex;
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     Int(u32),
+    BigInt(u64),
     Float(f32),
+    Double(f64),
     String(String),
     DateTime(NaiveDateTime),
     UUID(Uuid),
     Boolean(bool),
     VarChar(usize),
+    /// `ENUM('a', 'b')`, stored as strings
+    Enum(Vec<String>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Column {
     pub name: String,
     pub data_type: DataType,
     pub constraints: Vec<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Table {
     pub name: String,
+    /// Database or schema parts before the name, `["analytics"]` for
+    /// `analytics.events`
+    pub namespace: Vec<String>,
     pub columns: Vec<Column>,
 }
 
-/// Parse the data.sql into the tables
-pub fn parse_sql_file(sql_content: &str) -> Vec<Table> {
-    let lines: Vec<&str> = sql_content.lines().collect();
-    let sql_content = if lines
-        .get(0)
-        .map(|line| line.trim_start().starts_with("--"))
-        .unwrap_or(false)
-    {
-        lines[1..].join("\n")
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Int(_) => write!(f, "INT"),
+            DataType::BigInt(_) => write!(f, "BIGINT"),
+            DataType::Float(_) => write!(f, "FLOAT"),
+            DataType::Double(_) => write!(f, "DOUBLE"),
+            DataType::String(_) => write!(f, "TEXT"),
+            DataType::DateTime(_) => write!(f, "DATE"),
+            DataType::UUID(_) => write!(f, "UUID"),
+            DataType::Boolean(_) => write!(f, "BOOLEAN"),
+            DataType::VarChar(len) => write!(f, "VARCHAR({})", len),
+            DataType::Enum(values) => {
+                let quoted: Vec<String> = values
+                    .iter()
+                    .map(|v| format!("'{}'", v.replace('\'', "''")))
+                    .collect();
+                write!(f, "ENUM({})", quoted.join(", "))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            quote_identifier(&self.name, '"'),
+            self.data_type
+        )?;
+        for constraint in &self.constraints {
+            write!(f, " {}", constraint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "CREATE TABLE {} (", self.sql_name('"'))?;
+        for (i, col) in self.columns.iter().enumerate() {
+            let separator = if i + 1 < self.columns.len() { "," } else { "" };
+            writeln!(f, "    {}{}", col, separator)?;
+        }
+        write!(f, ");")
+    }
+}
+
+impl Column {
+    /// True when the column was declared with `PRIMARY KEY`
+    pub fn is_primary_key(&self) -> bool {
+        self.constraints
+            .windows(2)
+            .any(|w| w[0].eq_ignore_ascii_case("PRIMARY") && w[1].eq_ignore_ascii_case("KEY"))
+    }
+
+    /// True when the column was declared `NOT NULL`
+    pub fn is_not_null(&self) -> bool {
+        self.constraints
+            .windows(2)
+            .any(|w| w[0].eq_ignore_ascii_case("NOT") && w[1].eq_ignore_ascii_case("NULL"))
+    }
+
+    /// True when values must be unique, through `UNIQUE` or `PRIMARY KEY`
+    pub fn is_unique(&self) -> bool {
+        self.is_primary_key()
+            || self
+                .constraints
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case("UNIQUE"))
+    }
+
+    /// The `(table, column)` named by a `REFERENCES Table(Column)` constraint
+    pub fn references(&self) -> Option<(String, String)> {
+        let at = self
+            .constraints
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case("REFERENCES"))?;
+        // `Table(Column)` may have been split into `Table` and `(Column)`
+        let target = self.constraints[at + 1..].join(" ");
+        let (table, rest) = read_identifier(&target)?;
+        let (column, _) = read_identifier(rest.trim_start().strip_prefix('(')?)?;
+        Some((table.join("."), column.join(".")))
+    }
+}
+
+impl Table {
+    /// `namespace.name`, unquoted
+    pub fn qualified_name(&self) -> String {
+        let mut parts = self.namespace.clone();
+        parts.push(self.name.clone());
+        parts.join(".")
+    }
+
+    /// The qualified name joined with `_`, for formats without namespaces
+    pub fn flat_name(&self) -> String {
+        let mut parts = self.namespace.clone();
+        parts.push(self.name.clone());
+        parts.join("_")
+    }
+
+    /// The qualified name as a SQL identifier, parts quoted with `quote` as needed
+    pub fn sql_name(&self, quote: char) -> String {
+        self.namespace
+            .iter()
+            .chain(std::iter::once(&self.name))
+            .map(|part| quote_identifier(part, quote))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// True for the bare or the qualified name, ignoring case
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.qualified_name().eq_ignore_ascii_case(name)
+    }
+
+    /// The position of the table `name` refers to. A qualified name that
+    /// matches exactly wins, a bare name shared across namespaces is an error.
+    pub fn position(tables: &[Table], name: &str) -> Result<Option<usize>, String> {
+        if let Some(i) = tables
+            .iter()
+            .position(|t| t.qualified_name().eq_ignore_ascii_case(name))
+        {
+            return Ok(Some(i));
+        }
+        let matches: Vec<usize> = (0..tables.len())
+            .filter(|&i| tables[i].is_named(name))
+            .collect();
+        match matches[..] {
+            [] => Ok(None),
+            [i] => Ok(Some(i)),
+            _ => Err(format!(
+                "Ambiguous table name '{}', qualify it as one of {}",
+                name,
+                matches
+                    .iter()
+                    .map(|&i| tables[i].sql_name('"'))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// The first column declared as the primary key, if any
+    pub fn primary_key(&self) -> Option<&Column> {
+        self.columns.iter().find(|col| col.is_primary_key())
+    }
+
+    /// Position of a column, ignoring case like SQL does
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|col| col.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A single ALTER-style change to one table
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    AddColumn {
+        table: String,
+        column: Column,
+    },
+    DropColumn {
+        table: String,
+        column: String,
+    },
+    RenameColumn {
+        table: String,
+        from: String,
+        to: String,
+    },
+    ChangeType {
+        table: String,
+        column: String,
+        data_type: DataType,
+    },
+}
+
+impl SchemaChange {
+    pub fn table(&self) -> &str {
+        match self {
+            SchemaChange::AddColumn { table, .. }
+            | SchemaChange::DropColumn { table, .. }
+            | SchemaChange::RenameColumn { table, .. }
+            | SchemaChange::ChangeType { table, .. } => table,
+        }
+    }
+
+    /// Apply the change to `table`, which must be the table it names
+    pub fn apply(&self, table: &mut Table) -> Result<(), String> {
+        let missing = |column: &str| format!("Table '{}' has no column '{}'", table.name, column);
+        match self {
+            SchemaChange::AddColumn { column, .. } => {
+                if table.column_index(&column.name).is_some() {
+                    return Err(format!(
+                        "Table '{}' already has a column '{}'",
+                        table.name, column.name
+                    ));
+                }
+                table.columns.push(column.clone());
+            }
+            SchemaChange::DropColumn { column, .. } => {
+                let index = table.column_index(column).ok_or_else(|| missing(column))?;
+                table.columns.remove(index);
+            }
+            SchemaChange::RenameColumn { from, to, .. } => {
+                let index = table.column_index(from).ok_or_else(|| missing(from))?;
+                table.columns[index].name = to.clone();
+            }
+            SchemaChange::ChangeType {
+                column, data_type, ..
+            } => {
+                let index = table.column_index(column).ok_or_else(|| missing(column))?;
+                table.columns[index].data_type = data_type.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let table = quote_qualified(self.table(), '"');
+        let quote = |name: &str| quote_identifier(name, '"');
+        match self {
+            SchemaChange::AddColumn { column, .. } => {
+                write!(f, "ALTER TABLE {} ADD COLUMN {}", table, column)
+            }
+            SchemaChange::DropColumn { column, .. } => {
+                write!(f, "ALTER TABLE {} DROP COLUMN {}", table, quote(column))
+            }
+            SchemaChange::RenameColumn { from, to, .. } => write!(
+                f,
+                "ALTER TABLE {} RENAME COLUMN {} TO {}",
+                table,
+                quote(from),
+                quote(to)
+            ),
+            SchemaChange::ChangeType {
+                column, data_type, ..
+            } => write!(
+                f,
+                "ALTER TABLE {} ALTER COLUMN {} TYPE {}",
+                table,
+                quote(column),
+                data_type
+            ),
+        }
+    }
+}
+
+/// Words that must be quoted to be used as a name
+const RESERVED_WORDS: &str = "ALL AND AS ASC BETWEEN BY CASE CHECK COLUMN CONSTRAINT CREATE \
+    CROSS DEFAULT DELETE DESC DISTINCT DROP ELSE END EXISTS FOREIGN FROM FULL GROUP HAVING IN \
+    INDEX INNER INSERT INTO IS JOIN KEY LEFT LIKE LIMIT NOT NULL ON OR ORDER OUTER PRIMARY \
+    REFERENCES RIGHT SELECT SET TABLE THEN TO UNION UNIQUE UPDATE USER USING VALUES VIEW WHEN \
+    WHERE WITH";
+
+/// `name` as a SQL identifier, wrapped in `quote` (`"` for ANSI SQL, a
+/// backtick for Spark and Hive) unless it is a plain word
+pub fn quote_identifier(name: &str, quote: char) -> String {
+    let plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RESERVED_WORDS
+            .split_whitespace()
+            .any(|w| w.eq_ignore_ascii_case(name));
+    if plain {
+        name.to_string()
     } else {
-        sql_content.to_string()
-    };
+        let doubled = format!("{}{}", quote, quote);
+        format!("{}{}{}", quote, name.replace(quote, &doubled), quote)
+    }
+}
+
+/// A dotted `namespace.name` with each part quoted as needed
+pub fn quote_qualified(name: &str, quote: char) -> String {
+    name.split('.')
+        .map(|part| quote_identifier(part, quote))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Read a possibly dotted identifier such as `analytics.events`,
+/// `"Order Items"` or `` `select` `` from the start of `input`. Returns its
+/// unquoted parts and the rest of the input.
+fn read_identifier(input: &str) -> Option<(Vec<String>, &str)> {
+    let mut parts = Vec::new();
+    let mut rest = input.trim_start();
+    loop {
+        let (part, after) = match rest.chars().next()? {
+            open @ ('"' | '`' | '[') => {
+                let close = if open == '[' { ']' } else { open };
+                let body = &rest[1..];
+                let mut part = String::new();
+                let mut chars = body.char_indices().peekable();
+                let end = loop {
+                    let (i, c) = chars.next()?;
+                    if c != close {
+                        part.push(c);
+                    } else if chars.peek().is_some_and(|&(_, next)| next == close) {
+                        // A doubled quote stands for the quote itself
+                        part.push(close);
+                        chars.next();
+                    } else {
+                        break i;
+                    }
+                };
+                (part, &body[end + close.len_utf8()..])
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "(),.;\"`[".contains(c))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    return None;
+                }
+                (rest[..end].to_string(), &rest[end..])
+            }
+        };
+        parts.push(part);
+        match after.strip_prefix('.') {
+            Some(next) => rest = next,
+            None => return Some((parts, after)),
+        }
+    }
+}
+
+/// A qualified name as written in a statement, unquoted and joined with `.`
+fn read_name(input: &str) -> Option<(String, &str)> {
+    read_identifier(input).map(|(parts, rest)| (parts.join("."), rest))
+}
+
+/// A column of an index and its sort direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexColumn {
+    pub name: String,
+    pub descending: bool,
+}
+
+/// `CREATE [UNIQUE] INDEX`, kept as metadata for the files we write: btree
+/// indexes give a sort order, `hash` and `bloom` ones candidates for bloom
+/// filters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<IndexColumn>,
+    pub unique: bool,
+    /// The `USING` method, such as `btree`, `hash` or `bloom`
+    pub method: Option<String>,
+}
+
+/// `CREATE VIEW`, with the query stored as written
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub name: String,
+    pub query: String,
+}
+
+/// Everything data.sql declares, after applying its statements in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+    pub indexes: Vec<Index>,
+    pub views: Vec<View>,
+}
+
+impl Schema {
+    fn table_mut(&mut self, name: &str) -> Result<&mut Table, String> {
+        let index = Table::position(&self.tables, name)?
+            .ok_or_else(|| format!("Table '{}' does not exist", name))?;
+        Ok(&mut self.tables[index])
+    }
+
+    fn apply_change(&mut self, change: &SchemaChange) -> Result<(), String> {
+        change.apply(self.table_mut(change.table())?)?;
+        let table = self.table_mut(change.table())?.qualified_name();
+        let on_table = |index: &Index| index.table.eq_ignore_ascii_case(&table);
+        match change {
+            // Like PostgreSQL, dropping a column drops the indexes using it
+            SchemaChange::DropColumn { column, .. } => self.indexes.retain(|index| {
+                !on_table(index)
+                    || !index
+                        .columns
+                        .iter()
+                        .any(|c| c.name.eq_ignore_ascii_case(column))
+            }),
+            SchemaChange::RenameColumn { from, to, .. } => {
+                for index in self.indexes.iter_mut().filter(|index| on_table(index)) {
+                    for col in &mut index.columns {
+                        if col.name.eq_ignore_ascii_case(from) {
+                            col.name = to.clone();
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-    let statements: Vec<&str> = sql_content
-        .split(';')
+    fn create_index(&mut self, mut index: Index) -> Result<(), String> {
+        let table = self.table_mut(&index.table)?;
+        index.table = table.qualified_name();
+        for col in &index.columns {
+            if table.column_index(&col.name).is_none() {
+                return Err(format!(
+                    "Index '{}' uses missing column '{}.{}'",
+                    index.name, table.name, col.name
+                ));
+            }
+        }
+        // A unique index on one column makes the generator keep it unique
+        if let (true, [col]) = (index.unique, &index.columns[..]) {
+            let i = table.column_index(&col.name).unwrap();
+            if !table.columns[i].is_unique() {
+                table.columns[i].constraints.push("UNIQUE".to_string());
+            }
+        }
+        self.indexes
+            .retain(|i| !i.name.eq_ignore_ascii_case(&index.name));
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    /// Apply one statement of data.sql
+    fn apply(&mut self, statement: &str) -> Result<(), String> {
+        let words: Vec<String> = statement
+            .split_whitespace()
+            .take(4)
+            .map(|w| w.to_uppercase())
+            .collect();
+        let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+        let if_exists = words.get(2) == Some(&"IF");
+
+        match words[..] {
+            ["CREATE", "TABLE", ..] => {
+                let table = parse_create_table(statement);
+                let exists = self.tables.iter().any(|t| {
+                    t.qualified_name()
+                        .eq_ignore_ascii_case(&table.qualified_name())
+                });
+                if !exists {
+                    self.tables.push(table);
+                } else if !if_exists {
+                    return Err(format!(
+                        "Table '{}' is created twice",
+                        table.qualified_name()
+                    ));
+                }
+            }
+            ["CREATE", "INDEX", ..] | ["CREATE", "UNIQUE", "INDEX", ..] => {
+                self.create_index(parse_create_index(statement)?)?
+            }
+            ["CREATE", "VIEW", ..] | ["CREATE", "OR", "REPLACE", "VIEW"] => {
+                let view = parse_create_view(statement)?;
+                self.views
+                    .retain(|v| !v.name.eq_ignore_ascii_case(&view.name));
+                self.views.push(view);
+            }
+            ["ALTER", "TABLE", ..] => {
+                for change in parse_alter_table(statement)? {
+                    self.apply_change(&change)?;
+                }
+            }
+            ["DROP", kind @ ("TABLE" | "INDEX" | "VIEW"), ..] => {
+                let names = statement
+                    .split_whitespace()
+                    .skip(if if_exists { 4 } else { 2 })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                for name in split_top_level(&names, ',') {
+                    let name = match read_name(name) {
+                        Some((name, _)) => name,
+                        None => continue,
+                    };
+                    let name = name.as_str();
+                    let found = match kind {
+                        "TABLE" => match Table::position(&self.tables, name)? {
+                            Some(i) => {
+                                let table = self.tables.remove(i).qualified_name();
+                                self.indexes
+                                    .retain(|index| !index.table.eq_ignore_ascii_case(&table));
+                                true
+                            }
+                            None => false,
+                        },
+                        "INDEX" => {
+                            let before = self.indexes.len();
+                            self.indexes.retain(|i| !i.name.eq_ignore_ascii_case(name));
+                            self.indexes.len() < before
+                        }
+                        _ => {
+                            let before = self.views.len();
+                            self.views.retain(|v| !v.name.eq_ignore_ascii_case(name));
+                            self.views.len() < before
+                        }
+                    };
+                    if !found && !if_exists {
+                        return Err(format!(
+                            "Cannot drop {} '{}', it does not exist",
+                            kind.to_lowercase(),
+                            name
+                        ));
+                    }
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unsupported statement '{}'",
+                    statement.lines().next().unwrap_or_default().trim()
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a data.sql, applying `CREATE TABLE`, `ALTER TABLE`, `CREATE INDEX`,
+/// `CREATE VIEW` and `DROP TABLE/INDEX/VIEW` statements in order. `--`
+/// comments are ignored, including the header line the wizard writes and
+/// notes trailing a column.
+pub fn parse_schema(sql_content: &str) -> Result<Schema, String> {
+    let sql_content = strip_comments(sql_content);
+    let mut schema = Schema::default();
+    for statement in split_top_level(&sql_content, ';')
+        .into_iter()
         .map(|stmt| stmt.trim())
         .filter(|stmt| !stmt.is_empty())
-        .collect();
+    {
+        schema.apply(statement)?;
+    }
+    Ok(schema)
+}
 
-    statements
-        .into_iter()
-        .map(|stmt| parse_create_table(stmt))
-        .collect()
+/// Drop every `--` comment up to the end of its line, except inside quoted
+/// strings and identifiers
+fn strip_comments(content: &str) -> String {
+    let mut stripped = String::with_capacity(content.len());
+    let mut quote = None;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            '-' if quote.is_none() && chars.peek() == Some(&'-') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            _ => {}
+        }
+        stripped.push(c);
+    }
+    stripped
 }
 
+/// Parse `CREATE TABLE [IF NOT EXISTS] [namespace.]name (...)`, where each
+/// part of the name may be quoted
 pub fn parse_create_table(statement: &str) -> Table {
-    let table_name = extract_table_name(statement);
-    let columns = extract_columns(statement);
+    let (mut parts, rest) = extract_table_name(statement);
+    let name = parts.pop().unwrap_or_default();
+    let columns = extract_columns(rest);
 
     Table {
-        name: table_name,
+        name,
+        namespace: parts,
+        columns,
+    }
+}
+
+/// Parse `CREATE [UNIQUE] INDEX [IF NOT EXISTS] <name> ON <table>
+/// [USING <method>] (<column> [ASC|DESC], ...)`. MySQL's trailing
+/// `USING <method>` is accepted as well.
+fn parse_create_index(statement: &str) -> Result<Index, String> {
+    let invalid = || format!("Invalid CREATE INDEX statement '{}'", statement);
+    let prefix = Regex::new(r"(?is)^CREATE\s+(UNIQUE\s+)?INDEX\s+(?:IF\s+NOT\s+EXISTS\s+)?")
+        .unwrap()
+        .captures(statement)
+        .ok_or_else(invalid)?;
+    let (name, rest) = read_name(&statement[prefix[0].len()..]).ok_or_else(invalid)?;
+    let rest = Regex::new(r"(?i)^\s*ON\s")
+        .unwrap()
+        .find(rest)
+        .map(|m| &rest[m.end()..])
+        .ok_or_else(invalid)?;
+    let (table, rest) = read_name(rest).ok_or_else(invalid)?;
+    let cap = Regex::new(r"(?is)^\s*(?:USING\s+(\w+)\s*)?\((.*)\)\s*(?:USING\s+(\w+))?\s*$")
+        .unwrap()
+        .captures(rest)
+        .ok_or_else(invalid)?;
+
+    let columns: Vec<IndexColumn> = split_top_level(&cap[2], ',')
+        .into_iter()
+        .filter_map(|col| {
+            let (name, rest) = read_name(col)?;
+            Some(IndexColumn {
+                name,
+                descending: rest.trim().eq_ignore_ascii_case("DESC"),
+            })
+        })
+        .collect();
+    if columns.is_empty() {
+        return Err(format!("Index '{}' has no columns", name));
+    }
+    Ok(Index {
+        name,
+        table,
         columns,
+        unique: prefix.get(1).is_some(),
+        method: cap
+            .get(1)
+            .or_else(|| cap.get(3))
+            .map(|m| m.as_str().to_lowercase()),
+    })
+}
+
+/// Parse `CREATE [OR REPLACE] VIEW <name> AS <query>`
+fn parse_create_view(statement: &str) -> Result<View, String> {
+    let invalid = || format!("Invalid CREATE VIEW statement '{}'", statement);
+    let prefix = Regex::new(r"(?is)^CREATE\s+(?:OR\s+REPLACE\s+)?VIEW\s+")
+        .unwrap()
+        .find(statement)
+        .ok_or_else(invalid)?;
+    let (name, rest) = read_name(&statement[prefix.end()..]).ok_or_else(invalid)?;
+    let cap = Regex::new(r"(?is)^\s*AS\s+(.+)$")
+        .unwrap()
+        .captures(rest)
+        .ok_or_else(invalid)?;
+    Ok(View {
+        name,
+        query: cap[1].trim().to_string(),
+    })
+}
+
+/// Parse an `ALTER TABLE` statement into its changes. Supported actions,
+/// separated by commas: `ADD [COLUMN] <definition>`, `DROP [COLUMN] <name>`,
+/// `RENAME COLUMN <old> TO <new>`, `ALTER COLUMN <name> [SET DATA] TYPE <type>`
+/// and `MODIFY [COLUMN] <name> <type>`.
+pub fn parse_alter_table(statement: &str) -> Result<Vec<SchemaChange>, String> {
+    let not_alter = || format!("Not an ALTER TABLE statement: '{}'", statement);
+    let prefix = Regex::new(r"(?is)^\s*ALTER\s+TABLE\s+")
+        .unwrap()
+        .find(statement)
+        .ok_or_else(not_alter)?;
+    let (table, actions) = read_name(&statement[prefix.end()..]).ok_or_else(not_alter)?;
+    if actions.trim().is_empty() {
+        return Err(not_alter());
+    }
+
+    split_top_level(actions, ',')
+        .into_iter()
+        .map(|action| parse_alter_action(&table, action.trim()))
+        .collect()
+}
+
+fn parse_alter_action(table: &str, action: &str) -> Result<SchemaChange, String> {
+    let (verb, rest) = action
+        .split_once(char::is_whitespace)
+        .unwrap_or((action, ""));
+    // Skip the optional COLUMN keyword after the verb
+    let rest = rest.trim_start();
+    let rest = match rest.split_once(char::is_whitespace) {
+        Some((word, after)) if word.eq_ignore_ascii_case("COLUMN") => after,
+        _ => rest,
+    };
+    let (name, after_name) =
+        read_name(rest).ok_or_else(|| format!("Missing column name in '{}'", action))?;
+    let after_words: Vec<&str> = after_name.split_whitespace().collect();
+    let table = table.to_string();
+
+    if verb.eq_ignore_ascii_case("ADD") {
+        Ok(SchemaChange::AddColumn {
+            table,
+            column: parse_column(rest),
+        })
+    } else if verb.eq_ignore_ascii_case("DROP") {
+        Ok(SchemaChange::DropColumn {
+            table,
+            column: name,
+        })
+    } else if verb.eq_ignore_ascii_case("RENAME")
+        && after_words
+            .first()
+            .is_some_and(|w| w.eq_ignore_ascii_case("TO"))
+    {
+        // Past the `TO` keyword
+        let (to, _) = read_name(&after_name.trim_start()[2..])
+            .ok_or_else(|| format!("Missing new column name in '{}'", action))?;
+        Ok(SchemaChange::RenameColumn {
+            table,
+            from: name,
+            to,
+        })
+    } else if verb.eq_ignore_ascii_case("ALTER") || verb.eq_ignore_ascii_case("MODIFY") {
+        let type_at = after_words
+            .iter()
+            .position(|w| w.eq_ignore_ascii_case("TYPE"))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let type_str = after_words[type_at..].join(" ");
+        let (data_type, _) = parse_data_type_and_constraints(&type_str)
+            .ok_or_else(|| format!("Unsupported data type in '{}'", action))?;
+        Ok(SchemaChange::ChangeType {
+            table,
+            column: name,
+            data_type,
+        })
+    } else {
+        Err(format!("Unsupported ALTER TABLE action '{}'", action))
+    }
+}
+
+/// Parse a file of `;`-separated ALTER TABLE statements, ignoring `--` comments
+pub fn parse_alter_statements(content: &str) -> Result<Vec<SchemaChange>, String> {
+    let content = strip_comments(content);
+    let mut changes = Vec::new();
+    for statement in split_top_level(&content, ';')
+        .into_iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        changes.extend(parse_alter_table(statement)?);
     }
+    Ok(changes)
 }
 
-fn extract_table_name(statement: &str) -> String {
-    statement
-        .split_whitespace()
-        .nth(2)
-        .unwrap_or("")
-        .trim_end_matches('(')
-        .to_string()
+fn extract_table_name(statement: &str) -> (Vec<String>, &str) {
+    let re = Regex::new(r"(?is)^\s*CREATE\s+TABLE\s+(?:IF\s+NOT\s+EXISTS\s+)?").unwrap();
+    let rest = match re.find(statement) {
+        Some(m) => &statement[m.end()..],
+        None => statement,
+    };
+    read_identifier(rest).unwrap_or((Vec::new(), rest))
 }
 
 fn extract_columns(statement: &str) -> Vec<Column> {
@@ -76,23 +779,42 @@ fn extract_columns(statement: &str) -> Vec<Column> {
         .and_then(|(_, cols)| cols.rsplit_once(')').map(|(cols, _)| cols))
         .unwrap_or("");
 
-    let re = Regex::new(r"(?m)\s*([^,]+(?:\([^)]*\))?[^,]*)(?:,|$)").unwrap();
-    re.captures_iter(column_definitions)
-        .filter_map(|cap| {
-            let col_def = cap.get(1).unwrap().as_str().trim();
-            if col_def.is_empty() {
-                None
-            } else {
-                Some(parse_column(col_def))
-            }
-        })
+    split_top_level(column_definitions, ',')
+        .into_iter()
+        .map(|col_def| col_def.trim())
+        .filter(|col_def| !col_def.is_empty())
+        .map(parse_column)
         .collect()
 }
 
+/// Split on `separator` outside parentheses, strings and quoted names, so
+/// `ENUM('a', 'b')` stays in one piece
+fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quote, mut start) = (0usize, None, 0);
+    for (i, c) in input.char_indices() {
+        let quoted = quote.is_some();
+        match c {
+            '\'' | '"' | '`' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth = depth.saturating_sub(1),
+            c if c == separator && !quoted && depth == 0 => {
+                parts.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
 fn parse_column(col_def: &str) -> Column {
-    let parts: Vec<&str> = col_def.split_whitespace().collect();
-    let name = parts[0].to_string();
-    let data_type_str = parts[1..].join(" ");
+    let (name, rest) = read_identifier(col_def)
+        .map(|(mut parts, rest)| (parts.pop().unwrap_or_default(), rest))
+        .unwrap_or_default();
+    let data_type_str = rest.split_whitespace().collect::<Vec<_>>().join(" ");
 
     let mut data_type = DataType::String(String::new());
     let mut constraints = vec![];
@@ -109,15 +831,23 @@ fn parse_column(col_def: &str) -> Column {
     }
 }
 
+/// Parse a bare column type such as `VARCHAR(20)` or `BIGINT`
+pub fn parse_data_type(input: &str) -> Option<DataType> {
+    parse_data_type_and_constraints(input.trim()).map(|(data_type, _)| data_type)
+}
+
 fn parse_data_type_and_constraints(input: &str) -> Option<(DataType, Vec<String>)> {
-    let re =
-        Regex::new(r"(?i)^(VARCHAR\((\d+)\)|INT|FLOAT|TEXT|DATE|DATETIME|UUID|BOOLEAN)\s*(.*)$")
-            .unwrap();
+    let re = Regex::new(
+        r"(?i)^(VARCHAR\((\d+)\)|ENUM\(([^)]*)\)|BIGINT|INT|FLOAT|DOUBLE|TEXT|DATE|DATETIME|UUID|BOOLEAN)\s*(.*)$",
+    )
+    .unwrap();
 
     re.captures(input).map(|cap| {
         let data_type = match &cap[1].to_uppercase()[..] {
             "INT" => DataType::Int(0),
+            "BIGINT" => DataType::BigInt(0),
             "FLOAT" => DataType::Float(0.0),
+            "DOUBLE" => DataType::Double(0.0),
             "TEXT" => DataType::String(String::new()),
             "DATE" | "DATETIME" => DataType::DateTime(
                 NaiveDateTime::parse_from_str("2000-01-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
@@ -126,6 +856,13 @@ fn parse_data_type_and_constraints(input: &str) -> Option<(DataType, Vec<String>
                 DataType::UUID(Uuid::parse_str("00000000-0000-0000-0000-000000000000").unwrap())
             }
             "BOOLEAN" => DataType::Boolean(false),
+            enumeration if enumeration.starts_with("ENUM") => DataType::Enum(
+                split_top_level(&cap[3], ',')
+                    .into_iter()
+                    .map(|v| v.trim().trim_matches('\'').replace("''", "'"))
+                    .filter(|v| !v.is_empty())
+                    .collect(),
+            ),
             varchar if varchar.starts_with("VARCHAR") => {
                 let len = cap[2].parse::<usize>().unwrap_or(255);
                 DataType::VarChar(len)
@@ -133,7 +870,7 @@ fn parse_data_type_and_constraints(input: &str) -> Option<(DataType, Vec<String>
             _ => panic!("Unsupported data type: {}", &cap[1]),
         };
 
-        let constraints: Vec<String> = cap[3]
+        let constraints: Vec<String> = cap[4]
             .split_whitespace()
             .filter(|c| !c.eq_ignore_ascii_case("TIME")) // Filter out unwanted "TIME" constraint
             .map(|c| c.to_string())
@@ -160,6 +897,7 @@ mod tests {
 
         let expected_table = Table {
             name: "Foo".to_string(),
+            namespace: Vec::new(),
             columns: vec![
                 Column {
                     name: "ID".to_string(),
@@ -231,6 +969,7 @@ mod tests {
         let expected_tables = vec![
             Table {
                 name: "Lanisters".to_string(),
+                namespace: Vec::new(),
                 columns: vec![
                     Column {
                         name: "ID".to_string(),
@@ -260,6 +999,7 @@ mod tests {
             },
             Table {
                 name: "Starks".to_string(),
+                namespace: Vec::new(),
                 columns: vec![
                     Column {
                         name: "ID".to_string(),
@@ -289,7 +1029,239 @@ mod tests {
             },
         ];
 
-        let parsed_tables = parse_sql_file(sql_content);
+        let parsed_tables = parse_schema(sql_content).unwrap().tables;
         assert_eq!(parsed_tables, expected_tables);
     }
+
+    #[test]
+    fn test_parse_schema_applies_statements_in_order() {
+        let schema = parse_schema(
+            "CREATE TABLE Teams (ID INT PRIMARY KEY, Name TEXT);
+            CREATE TABLE Players (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                Nick VARCHAR(20),
+                Email TEXT,
+                Status ENUM('a;b', 'c')
+            );
+            CREATE TABLE Scratch (ID INT);
+            ALTER TABLE Players ADD COLUMN TeamID INT REFERENCES Teams(ID);
+            ALTER TABLE Players RENAME COLUMN Nick TO Handle;
+            CREATE UNIQUE INDEX players_email ON Players (Email);
+            CREATE INDEX players_recent ON Players (TeamID, ID DESC);
+            CREATE INDEX players_handle ON Players USING bloom (Handle);
+            ALTER TABLE Players DROP COLUMN Email;
+            CREATE OR REPLACE VIEW Rosters AS
+                SELECT t.Name, p.Handle FROM Players p JOIN Teams t ON p.TeamID = t.ID;
+            DROP TABLE IF EXISTS Scratch, Missing;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema
+                .tables
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Teams", "Players"]
+        );
+        let players = &schema.tables[1];
+        assert_eq!(
+            players
+                .columns
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "ID INT AUTO_INCREMENT PRIMARY KEY",
+                "Handle VARCHAR(20)",
+                "Status ENUM('a;b', 'c')",
+                "TeamID INT REFERENCES Teams(ID)",
+            ]
+        );
+
+        // The unique index went with its column, the others follow renames
+        assert_eq!(schema.indexes.len(), 2);
+        assert_eq!(
+            schema.indexes[0].columns,
+            vec![
+                IndexColumn {
+                    name: "TeamID".to_string(),
+                    descending: false
+                },
+                IndexColumn {
+                    name: "ID".to_string(),
+                    descending: true
+                },
+            ]
+        );
+        assert_eq!(schema.indexes[1].columns[0].name, "Handle");
+        assert_eq!(schema.indexes[1].method.as_deref(), Some("bloom"));
+
+        assert_eq!(schema.views[0].name, "Rosters");
+        assert!(schema.views[0].query.starts_with("SELECT t.Name, p.Handle"));
+
+        assert_eq!(
+            parse_schema("CREATE TABLE A (ID INT); DROP TABLE B;").unwrap_err(),
+            "Cannot drop table 'B', it does not exist"
+        );
+        assert!(parse_schema("INSERT INTO A VALUES (1);").is_err());
+        let unique =
+            parse_schema("CREATE TABLE A (Email TEXT); CREATE UNIQUE INDEX a ON A (Email);")
+                .unwrap();
+        assert!(unique.tables[0].columns[0].is_unique());
+    }
+
+    #[test]
+    fn test_qualified_and_quoted_names() {
+        let schema = parse_schema(
+            r#"CREATE TABLE IF NOT EXISTS analytics.events (ID INT PRIMARY KEY, "select" TEXT);
+            CREATE TABLE IF NOT EXISTS analytics.events (Ignored INT);
+            CREATE TABLE "Sales DB"."Order Items" (
+                "Item Id" INT PRIMARY KEY,
+                `Event` INT REFERENCES analytics.events(ID),
+                "Qty, Ordered" INT
+            );
+            ALTER TABLE "Sales DB"."Order Items" RENAME COLUMN "Qty, Ordered" TO Quantity;
+            CREATE INDEX items_event ON "Sales DB"."Order Items" ("Item Id" DESC);"#,
+        )
+        .unwrap();
+
+        let events = &schema.tables[0];
+        assert_eq!(events.name, "events");
+        assert_eq!(events.namespace, vec!["analytics"]);
+        assert_eq!(events.columns.len(), 2);
+        assert_eq!(events.columns[1].name, "select");
+
+        let items = &schema.tables[1];
+        assert_eq!(items.qualified_name(), "Sales DB.Order Items");
+        assert!(items.is_named("order items") && items.is_named("sales db.order items"));
+        assert_eq!(
+            items
+                .columns
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Item Id", "Event", "Quantity"]
+        );
+        assert_eq!(
+            items.columns[1].references(),
+            Some(("analytics.events".to_string(), "ID".to_string()))
+        );
+        assert_eq!(schema.indexes[0].table, "Sales DB.Order Items");
+        assert_eq!(schema.indexes[0].columns[0].name, "Item Id");
+
+        // Written back out, the names are quoted again
+        assert_eq!(
+            items.to_string(),
+            "CREATE TABLE \"Sales DB\".\"Order Items\" (\n    \"Item Id\" INT PRIMARY KEY,\n    Event INT REFERENCES analytics.events(ID),\n    Quantity INT\n);"
+        );
+        assert_eq!(parse_schema(&items.to_string()).unwrap().tables[0], *items);
+        assert_eq!(quote_identifier("select", '`'), "`select`");
+
+        // A bare name shared by two namespaces must be qualified
+        let both = parse_schema(
+            "CREATE TABLE analytics.events (ID INT);
+            CREATE TABLE raw.events (ID INT);",
+        )
+        .unwrap()
+        .tables;
+        let error = Table::position(&both, "events").unwrap_err();
+        assert!(error.contains("analytics.events, raw.events"), "{}", error);
+        assert_eq!(Table::position(&both, "RAW.events"), Ok(Some(1)));
+        assert!(parse_schema(
+            "CREATE TABLE analytics.events (ID INT);
+            CREATE TABLE raw.events (ID INT);
+            DROP TABLE events;"
+        )
+        .unwrap_err()
+        .contains("Ambiguous"));
+    }
+
+    #[test]
+    fn test_comment_markers_inside_quotes_are_kept() {
+        let schema = parse_schema(
+            "-- Shipments
+            CREATE TABLE Shipments ( -- one row per parcel
+                \"my--col\" INT PRIMARY KEY,
+                Status ENUM('in--transit', 'done') -- current state
+            );",
+        )
+        .unwrap();
+        let columns = &schema.tables[0].columns;
+        assert_eq!(columns[0].name, "my--col");
+        assert_eq!(
+            columns[1].data_type,
+            DataType::Enum(vec!["in--transit".to_string(), "done".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_column_references() {
+        let table = parse_create_table(
+            "CREATE TABLE Players (
+            ID INT AUTO_INCREMENT PRIMARY KEY,
+            TeamID INT NOT NULL REFERENCES Teams(ID),
+            CoachID INT REFERENCES Coaches (ID),
+            Name TEXT
+        )",
+        );
+
+        assert!(table.columns[0].is_primary_key());
+        assert_eq!(table.primary_key().unwrap().name, "ID");
+        assert_eq!(
+            table.columns[1].references(),
+            Some(("Teams".to_string(), "ID".to_string()))
+        );
+        assert_eq!(
+            table.columns[2].references(),
+            Some(("Coaches".to_string(), "ID".to_string()))
+        );
+        assert_eq!(table.columns[3].references(), None);
+    }
+
+    #[test]
+    fn test_parse_alter_table() {
+        let changes = parse_alter_statements(
+            "-- evolve Foo
+            ALTER TABLE Foo ADD COLUMN Rating DOUBLE NOT NULL, DROP Legacy;
+            ALTER TABLE Foo RENAME COLUMN Name TO FullName;
+            ALTER TABLE Foo ALTER COLUMN ID SET DATA TYPE BIGINT;
+            ALTER TABLE Foo MODIFY Alias VARCHAR(200);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::AddColumn {
+                    table: "Foo".to_string(),
+                    column: Column {
+                        name: "Rating".to_string(),
+                        data_type: DataType::Double(0.0),
+                        constraints: vec!["NOT".to_string(), "NULL".to_string()],
+                    },
+                },
+                SchemaChange::DropColumn {
+                    table: "Foo".to_string(),
+                    column: "Legacy".to_string(),
+                },
+                SchemaChange::RenameColumn {
+                    table: "Foo".to_string(),
+                    from: "Name".to_string(),
+                    to: "FullName".to_string(),
+                },
+                SchemaChange::ChangeType {
+                    table: "Foo".to_string(),
+                    column: "ID".to_string(),
+                    data_type: DataType::BigInt(0),
+                },
+                SchemaChange::ChangeType {
+                    table: "Foo".to_string(),
+                    column: "Alias".to_string(),
+                    data_type: DataType::VarChar(200),
+                },
+            ]
+        );
+        assert!(parse_alter_table("ALTER TABLE Foo TRUNCATE").is_err());
+    }
 }
@@ -0,0 +1,609 @@
+//! Object storage behind one trait, so uploads and checks run the same way
+//! against S3, a local directory or memory (for tests).
+use bytes::Bytes;
+#[cfg(test)]
+use bytes::BytesMut;
+use md5::{Digest, Md5};
+#[cfg(test)]
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use uuid::Uuid;
+
+/// The future returned by every `ObjectStore` call
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// A stored object as returned by `list`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    /// S3's ETag: the MD5 of the content, or for multipart uploads the MD5 of
+    /// the parts' MD5s followed by `-<part count>`
+    pub etag: Option<String>,
+    /// S3's storage class, such as STANDARD or DEEP_ARCHIVE, `None` for local files
+    pub storage_class: Option<String>,
+}
+
+/// The S3 storage classes uploads can choose, from instant access to the
+/// cheapest archive that needs a restore before reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    Standard,
+    IntelligentTiering,
+    GlacierIr,
+    DeepArchive,
+}
+
+impl StorageClass {
+    pub fn parse(name: &str) -> Result<StorageClass, String> {
+        match name.to_uppercase().replace('-', "_").as_str() {
+            "STANDARD" => Ok(StorageClass::Standard),
+            "INTELLIGENT_TIERING" => Ok(StorageClass::IntelligentTiering),
+            "GLACIER_IR" => Ok(StorageClass::GlacierIr),
+            "DEEP_ARCHIVE" => Ok(StorageClass::DeepArchive),
+            _ => Err(format!(
+                "Unknown storage class '{}', use STANDARD, INTELLIGENT_TIERING, GLACIER_IR \
+                 or DEEP_ARCHIVE",
+                name
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::IntelligentTiering => "INTELLIGENT_TIERING",
+            StorageClass::GlacierIr => "GLACIER_IR",
+            StorageClass::DeepArchive => "DEEP_ARCHIVE",
+        }
+    }
+}
+
+/// Hex MD5 of `bytes`
+pub fn md5_hex(bytes: &[u8]) -> String {
+    hex::encode(Md5::digest(bytes))
+}
+
+/// The ETag S3 gives an object completed from `parts`
+#[cfg(test)]
+pub fn multipart_etag<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut digests = Md5::new();
+    let mut count = 0;
+    for part in parts {
+        digests.update(Md5::digest(part));
+        count += 1;
+    }
+    format!("{}-{}", hex::encode(digests.finalize()), count)
+}
+
+/// A finished part of a multipart upload
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub number: i32,
+    pub etag: String,
+}
+
+/// Keys are `/` separated paths, a multipart upload is created, filled with
+/// numbered parts and either completed into one object or aborted. Only S3
+/// has storage classes, the other stores ignore them.
+pub trait ObjectStore: Send + Sync {
+    /// Where the keys live, `s3://bucket` or a directory
+    fn location(&self) -> String;
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        body: Bytes,
+        storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, ()>;
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Bytes>;
+    /// The bytes of `key` in `range`, cut short at the end of the object
+    fn get_range<'a>(&'a self, key: &'a str, range: Range<u64>) -> StoreFuture<'a, Bytes>;
+    /// Every object whose key starts with `prefix`, ordered by key
+    fn list<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, Vec<ObjectMeta>>;
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()>;
+    /// Start a multipart upload and return its id
+    fn create_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, String>;
+    fn put_part<'a>(
+        &'a self,
+        key: &'a str,
+        upload_id: &'a str,
+        number: i32,
+        body: Bytes,
+    ) -> StoreFuture<'a, Part>;
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: Vec<Part>,
+    ) -> StoreFuture<'a, ()>;
+    fn abort_multipart<'a>(&'a self, key: &'a str, upload_id: &'a str) -> StoreFuture<'a, ()>;
+}
+
+/// Open the store for `s3://bucket/prefix` or a local directory, with the
+/// key prefix inside it
+pub async fn open(destination: &str) -> Result<(Arc<dyn ObjectStore>, String), String> {
+    let prefix = key_prefix(destination)?;
+    if destination.starts_with("s3://") {
+        let (bucket, _) = crate::upload::parse_s3_uri(destination)?;
+        let store = crate::aws::S3Store::connect(&bucket).await?;
+        return Ok((Arc::new(store), prefix));
+    }
+    let root = destination.strip_prefix("file://").unwrap_or(destination);
+    Ok((Arc::new(LocalStore::new(root)), prefix))
+}
+
+/// The key prefix `open` returns for `destination`, without connecting.
+/// A local directory is the store root itself.
+pub fn key_prefix(destination: &str) -> Result<String, String> {
+    if destination.starts_with("s3://") {
+        Ok(crate::upload::parse_s3_uri(destination)?.1)
+    } else {
+        Ok(String::new())
+    }
+}
+
+fn missing(key: &str) -> String {
+    format!("No object '{}'", key)
+}
+
+fn unknown_upload(upload_id: &str) -> String {
+    format!("No multipart upload '{}'", upload_id)
+}
+
+/// Objects as files under a root directory, in-progress multipart uploads
+/// as part files under `<root>/.multipart/<upload id>`
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> LocalStore {
+        LocalStore { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        let parts: Vec<&str> = key.split('/').filter(|p| !p.is_empty()).collect();
+        if parts.is_empty() || parts.iter().any(|p| *p == ".." || *p == ".") {
+            return Err(format!("Invalid object key '{}'", key));
+        }
+        Ok(parts.iter().fold(self.root.clone(), |path, p| path.join(p)))
+    }
+
+    fn upload_dir(&self, upload_id: &str) -> PathBuf {
+        self.root.join(".multipart").join(upload_id)
+    }
+
+    /// Remove an upload's parts, and `.multipart` once no upload is left
+    async fn remove_upload(&self, upload_id: &str) {
+        let _ = tokio::fs::remove_dir_all(self.upload_dir(upload_id)).await;
+        let _ = tokio::fs::remove_dir(self.root.join(".multipart")).await;
+    }
+
+    async fn write(path: &Path, body: &[u8]) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Error creating '{}': {}", parent.display(), e))?;
+        }
+        tokio::fs::write(path, body)
+            .await
+            .map_err(|e| format!("Error writing '{}': {}", path.display(), e))
+    }
+
+    /// Files under `dir` whose keys start with `prefix`, only those are read
+    fn walk(&self, dir: &Path, prefix: &str, objects: &mut Vec<ObjectMeta>) -> Result<(), String> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Unable to read '{}': {}", dir.display(), e)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let path = entry.path();
+            if path == self.root.join(".multipart") {
+                continue;
+            }
+            if path.is_dir() {
+                self.walk(&path, prefix, objects)?;
+            } else {
+                let relative = path.strip_prefix(&self.root).map_err(|e| e.to_string())?;
+                let key = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if !key.starts_with(prefix) {
+                    continue;
+                }
+                let body = std::fs::read(&path)
+                    .map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+                objects.push(ObjectMeta {
+                    key,
+                    size: body.len() as u64,
+                    etag: Some(md5_hex(&body)),
+                    storage_class: None,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore for LocalStore {
+    fn location(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        body: Bytes,
+        _storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move { LocalStore::write(&self.path(key)?, &body).await })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Bytes> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            match tokio::fs::read(&path).await {
+                Ok(body) => Ok(Bytes::from(body)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(missing(key)),
+                Err(e) => Err(format!("Unable to read '{}': {}", path.display(), e)),
+            }
+        })
+    }
+
+    fn get_range<'a>(&'a self, key: &'a str, range: Range<u64>) -> StoreFuture<'a, Bytes> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            let read_error = |e: std::io::Error| match e.kind() {
+                std::io::ErrorKind::NotFound => missing(key),
+                _ => format!("Unable to read '{}': {}", path.display(), e),
+            };
+            let mut file = tokio::fs::File::open(&path).await.map_err(read_error)?;
+            file.seek(SeekFrom::Start(range.start))
+                .await
+                .map_err(read_error)?;
+            let mut body = Vec::new();
+            file.take(range.end.saturating_sub(range.start))
+                .read_to_end(&mut body)
+                .await
+                .map_err(read_error)?;
+            Ok(Bytes::from(body))
+        })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, Vec<ObjectMeta>> {
+        Box::pin(async move {
+            // Only the directory holding the prefix can have matching keys
+            let dir = match prefix.rsplit_once('/') {
+                Some((dir, _)) if !dir.is_empty() => self.path(dir)?,
+                _ => self.root.clone(),
+            };
+            let mut objects = Vec::new();
+            self.walk(&dir, prefix, &mut objects)?;
+            objects.sort_by(|a, b| a.key.cmp(&b.key));
+            Ok(objects)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("Error deleting '{}': {}", path.display(), e))
+                }
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn create_multipart<'a>(
+        &'a self,
+        _key: &'a str,
+        _storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, String> {
+        Box::pin(async move {
+            let upload_id = Uuid::new_v4().to_string();
+            let dir = self.upload_dir(&upload_id);
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .map_err(|e| format!("Error creating '{}': {}", dir.display(), e))?;
+            Ok(upload_id)
+        })
+    }
+
+    fn put_part<'a>(
+        &'a self,
+        _key: &'a str,
+        upload_id: &'a str,
+        number: i32,
+        body: Bytes,
+    ) -> StoreFuture<'a, Part> {
+        Box::pin(async move {
+            let dir = self.upload_dir(upload_id);
+            if !dir.is_dir() {
+                return Err(unknown_upload(upload_id));
+            }
+            LocalStore::write(&dir.join(number.to_string()), &body).await?;
+            Ok(Part {
+                number,
+                etag: format!("{}-{}", number, body.len()),
+            })
+        })
+    }
+
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        upload_id: &'a str,
+        mut parts: Vec<Part>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let dir = self.upload_dir(upload_id);
+            parts.sort_by_key(|p| p.number);
+            let mut body = Vec::new();
+            for part in &parts {
+                let path = dir.join(part.number.to_string());
+                let bytes = tokio::fs::read(&path)
+                    .await
+                    .map_err(|_| format!("Part {} of '{}' was not uploaded", part.number, key))?;
+                body.extend_from_slice(&bytes);
+            }
+            LocalStore::write(&self.path(key)?, &body).await?;
+            self.remove_upload(upload_id).await;
+            Ok(())
+        })
+    }
+
+    fn abort_multipart<'a>(&'a self, _key: &'a str, upload_id: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            self.remove_upload(upload_id).await;
+            Ok(())
+        })
+    }
+}
+
+/// A multipart upload's storage class and numbered parts
+#[cfg(test)]
+type MemoryUpload = (StorageClass, BTreeMap<i32, Bytes>);
+
+/// Objects kept in memory with S3's ETags, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    objects: Mutex<BTreeMap<String, (Bytes, String, StorageClass)>>,
+    uploads: Mutex<HashMap<String, MemoryUpload>>,
+}
+
+#[cfg(test)]
+impl ObjectStore for MemoryStore {
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        body: Bytes,
+        storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let etag = md5_hex(&body);
+            let class = storage_class.unwrap_or(StorageClass::Standard);
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (body, etag, class));
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Bytes> {
+        Box::pin(async move {
+            self.objects
+                .lock()
+                .unwrap()
+                .get(key)
+                .map(|(body, _, _)| body.clone())
+                .ok_or_else(|| missing(key))
+        })
+    }
+
+    fn get_range<'a>(&'a self, key: &'a str, range: Range<u64>) -> StoreFuture<'a, Bytes> {
+        Box::pin(async move {
+            let body = self.get(key).await?;
+            let end = (range.end as usize).min(body.len());
+            Ok(body.slice((range.start as usize).min(end)..end))
+        })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, Vec<ObjectMeta>> {
+        Box::pin(async move {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .range(prefix.to_string()..)
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .map(|(key, (body, etag, class))| ObjectMeta {
+                    key: key.clone(),
+                    size: body.len() as u64,
+                    etag: Some(etag.clone()),
+                    storage_class: Some(class.as_str().to_string()),
+                })
+                .collect())
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        })
+    }
+
+    fn create_multipart<'a>(
+        &'a self,
+        _key: &'a str,
+        storage_class: Option<StorageClass>,
+    ) -> StoreFuture<'a, String> {
+        Box::pin(async move {
+            let class = storage_class.unwrap_or(StorageClass::Standard);
+            let upload_id = Uuid::new_v4().to_string();
+            self.uploads
+                .lock()
+                .unwrap()
+                .insert(upload_id.clone(), (class, BTreeMap::new()));
+            Ok(upload_id)
+        })
+    }
+
+    fn put_part<'a>(
+        &'a self,
+        _key: &'a str,
+        upload_id: &'a str,
+        number: i32,
+        body: Bytes,
+    ) -> StoreFuture<'a, Part> {
+        Box::pin(async move {
+            let etag = format!("{}-{}", number, body.len());
+            self.uploads
+                .lock()
+                .unwrap()
+                .get_mut(upload_id)
+                .ok_or_else(|| unknown_upload(upload_id))?
+                .1
+                .insert(number, body);
+            Ok(Part { number, etag })
+        })
+    }
+
+    fn complete_multipart<'a>(
+        &'a self,
+        key: &'a str,
+        upload_id: &'a str,
+        mut parts: Vec<Part>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let (class, uploaded) = self
+                .uploads
+                .lock()
+                .unwrap()
+                .remove(upload_id)
+                .ok_or_else(|| unknown_upload(upload_id))?;
+            parts.sort_by_key(|p| p.number);
+            let mut bodies = Vec::new();
+            for part in &parts {
+                bodies.push(uploaded.get(&part.number).ok_or_else(|| {
+                    format!("Part {} of '{}' was not uploaded", part.number, key)
+                })?);
+            }
+            let etag = multipart_etag(bodies.iter().map(|b| b.as_ref()));
+            let mut body = BytesMut::new();
+            for bytes in bodies {
+                body.extend_from_slice(bytes);
+            }
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (body.freeze(), etag, class));
+            Ok(())
+        })
+    }
+
+    fn abort_multipart<'a>(&'a self, _key: &'a str, upload_id: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            self.uploads.lock().unwrap().remove(upload_id);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    async fn exercise(store: &dyn ObjectStore) {
+        store
+            .put("a/1.txt", Bytes::from("one"), None)
+            .await
+            .unwrap();
+        store
+            .put("a/2.txt", Bytes::from("two!"), None)
+            .await
+            .unwrap();
+        store
+            .put("b/3.txt", Bytes::from("three"), None)
+            .await
+            .unwrap();
+        assert_eq!(store.get("a/2.txt").await.unwrap(), Bytes::from("two!"));
+        assert!(store.get("a/9.txt").await.is_err());
+        assert_eq!(store.get_range("b/3.txt", 1..3).await.unwrap(), "hr");
+        assert_eq!(store.get_range("b/3.txt", 3..10).await.unwrap(), "ee");
+        assert!(store.get_range("a/9.txt", 0..1).await.is_err());
+
+        let listed: Vec<(String, u64, Option<String>)> = store
+            .list("a/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|o| (o.key, o.size, o.etag))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("a/1.txt".to_string(), 3, Some(md5_hex(b"one"))),
+                ("a/2.txt".to_string(), 4, Some(md5_hex(b"two!"))),
+            ]
+        );
+
+        let upload_id = store.create_multipart("c/big.bin", None).await.unwrap();
+        let second = store
+            .put_part("c/big.bin", &upload_id, 2, Bytes::from("world"))
+            .await
+            .unwrap();
+        let first = store
+            .put_part("c/big.bin", &upload_id, 1, Bytes::from("hello "))
+            .await
+            .unwrap();
+        assert!(store.list("c/").await.unwrap().is_empty());
+        assert_eq!(store.list("a/1").await.unwrap()[0].key, "a/1.txt");
+        assert_eq!(store.list("b").await.unwrap().len(), 1);
+        store
+            .complete_multipart("c/big.bin", &upload_id, vec![second, first])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("c/big.bin").await.unwrap(),
+            Bytes::from("hello world")
+        );
+
+        store.delete("a/1.txt").await.unwrap();
+        store.delete("a/1.txt").await.unwrap();
+        assert_eq!(store.list("").await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_local_and_memory_stores_behave_alike() {
+        exercise(&MemoryStore::default()).await;
+        let dir = TempDir::new("store").unwrap();
+        exercise(&LocalStore::new(dir.path())).await;
+        assert!(LocalStore::new(dir.path()).path("../escape").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Incremental uploads: compare a local dataset with what is already stored
+//! and only send new or changed files.
+use crate::store::ObjectMeta;
+use crate::upload::UploadObject;
+use colored::*;
+use md5::{Digest, Md5};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// What syncing does with a file or object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncAction {
+    /// Not stored yet
+    Add,
+    /// Stored with a different size or checksum
+    Update,
+}
+
+impl SyncAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncAction::Add => "new",
+            SyncAction::Update => "changed",
+        }
+    }
+}
+
+/// The uploads and deletions that bring the stored dataset in line with the local one
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    pub uploads: Vec<(SyncAction, UploadObject)>,
+    /// Stored keys with no local file, deleted only when asked to
+    pub extras: Vec<String>,
+    pub unchanged: usize,
+}
+
+/// The ETags S3 could have given `path`: the MD5 of a single `put`, and the
+/// multipart ETag when the file is larger than `part_size`
+pub fn file_etags(path: &Path, part_size: u64) -> Result<Vec<String>, String> {
+    let read_error = |e: std::io::Error| format!("Unable to read '{}': {}", path.display(), e);
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut whole = Md5::new();
+    let mut part_digests = Md5::new();
+    let mut parts = 0;
+    let mut buffer = Vec::with_capacity(part_size as usize);
+    loop {
+        buffer.clear();
+        (&mut file)
+            .take(part_size)
+            .read_to_end(&mut buffer)
+            .map_err(read_error)?;
+        if buffer.is_empty() {
+            break;
+        }
+        whole.update(&buffer);
+        part_digests.update(Md5::digest(&buffer));
+        parts += 1;
+    }
+    let mut etags = vec![hex::encode(whole.finalize())];
+    if parts > 1 {
+        etags.push(format!(
+            "{}-{}",
+            hex::encode(part_digests.finalize()),
+            parts
+        ));
+    }
+    Ok(etags)
+}
+
+/// Compare `local` files with the `remote` objects by size, then by ETag.
+/// Objects without an ETag are compared by size alone. `manifest_key` is
+/// rewritten by every sync and never counted as an extra.
+pub fn plan_sync(
+    local: &[UploadObject],
+    remote: &[ObjectMeta],
+    part_size: u64,
+    manifest_key: &str,
+) -> Result<SyncPlan, String> {
+    let stored: BTreeMap<&str, &ObjectMeta> = remote.iter().map(|o| (o.key.as_str(), o)).collect();
+    let mut plan = SyncPlan::default();
+    for object in local {
+        let action = match stored.get(object.key.as_str()) {
+            None => Some(SyncAction::Add),
+            Some(meta) if meta.size != object.bytes => Some(SyncAction::Update),
+            Some(meta) => match &meta.etag {
+                Some(etag) if !file_etags(&object.path, part_size)?.contains(etag) => {
+                    Some(SyncAction::Update)
+                }
+                _ => None,
+            },
+        };
+        match action {
+            Some(action) => plan.uploads.push((action, object.clone())),
+            None => plan.unchanged += 1,
+        }
+    }
+
+    let local_keys: BTreeSet<&str> = local.iter().map(|o| o.key.as_str()).collect();
+    plan.extras = remote
+        .iter()
+        .map(|o| o.key.clone())
+        .filter(|key| key != manifest_key && !local_keys.contains(key.as_str()))
+        .collect();
+    Ok(plan)
+}
+
+impl SyncPlan {
+    /// Every change, then a count of each kind
+    pub fn print(&self, delete_extras: bool) {
+        for (action, object) in &self.uploads {
+            let line = format!("  ⬆️  {} ({})", object.key, action.as_str());
+            match action {
+                SyncAction::Add => println!("{}", line.green()),
+                _ => println!("{}", line.yellow()),
+            }
+        }
+        for key in &self.extras {
+            if delete_extras {
+                println!("{}", format!("  🗑️  {} (extra)", key).red());
+            } else {
+                println!("  ·  {} (extra, kept without --delete)", key);
+            }
+        }
+        let count = |wanted: SyncAction| {
+            self.uploads
+                .iter()
+                .filter(|(action, _)| *action == wanted)
+                .count()
+        };
+        println!(
+            "🔄 {} new, {} changed, {} unchanged, {} extra{}",
+            count(SyncAction::Add).to_string().bold().green(),
+            count(SyncAction::Update).to_string().bold().yellow(),
+            self.unchanged.to_string().bold().cyan(),
+            self.extras.len().to_string().bold().red(),
+            if delete_extras { " to delete" } else { "" }
+        );
+    }
+
+    pub fn is_empty(&self, delete_extras: bool) -> bool {
+        self.uploads.is_empty() && (!delete_extras || self.extras.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{MemoryStore, ObjectStore};
+    use crate::upload::{self, UploadOptions};
+    use bytes::Bytes;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[tokio::test]
+    async fn test_sync_sends_only_new_and_changed_files() {
+        let dir = TempDir::new("sync").unwrap();
+        let big: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(dir.path().join("big.bin"), &big).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "aaaa").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "bbbb").unwrap();
+        let store = Arc::new(MemoryStore::default());
+        let options = UploadOptions {
+            part_size: 1000,
+            ..UploadOptions::default()
+        };
+        let objects = upload::plan_upload(dir.path(), "raw").unwrap();
+        let manifest = upload::manifest(dir.path(), "memory/raw", &objects);
+        let manifest_key = upload::manifest_key("raw");
+        upload::upload_objects(store.clone(), &objects, &manifest_key, &manifest, &options)
+            .await
+            .unwrap();
+
+        // big.bin went up in 3 parts and still matches its multipart ETag
+        let remote = store.list("raw/").await.unwrap();
+        let plan = plan_sync(&objects, &remote, 1000, &manifest_key).unwrap();
+        assert_eq!(plan.unchanged, 3);
+        assert!(plan.uploads.is_empty() && plan.extras.is_empty());
+
+        std::fs::write(dir.path().join("a.txt"), "AAAA").unwrap();
+        std::fs::write(dir.path().join("c.txt"), "new").unwrap();
+        std::fs::remove_file(dir.path().join("b.txt")).unwrap();
+        store
+            .put("raw/old.txt", Bytes::from("old"), None)
+            .await
+            .unwrap();
+        let objects = upload::plan_upload(dir.path(), "raw").unwrap();
+        let remote = store.list("raw/").await.unwrap();
+        let plan = plan_sync(&objects, &remote, 1000, &manifest_key).unwrap();
+
+        let uploads: Vec<(SyncAction, &str)> = plan
+            .uploads
+            .iter()
+            .map(|(action, o)| (*action, o.key.as_str()))
+            .collect();
+        assert_eq!(
+            uploads,
+            vec![
+                (SyncAction::Update, "raw/a.txt"),
+                (SyncAction::Add, "raw/c.txt"),
+            ]
+        );
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.extras, vec!["raw/b.txt", "raw/old.txt"]);
+
+        // A journal left by an upload of the edited a.txt does not stop the resend
+        let journal = dir.path().join(upload::JOURNAL);
+        let leftover = upload::Journal {
+            destination: "memory/raw/_manifest.json".to_string(),
+            completed: BTreeMap::from([(
+                "raw/a.txt".to_string(),
+                upload::FileStamp::of(&dir.path().join("a.txt")).unwrap(),
+            )]),
+            ..upload::Journal::default()
+        };
+        std::fs::write(&journal, serde_json::to_string(&leftover).unwrap()).unwrap();
+        let options = UploadOptions {
+            journal: Some(journal),
+            resume_completed: false,
+            ..options
+        };
+        let changed: Vec<UploadObject> = plan.uploads.iter().map(|(_, o)| o.clone()).collect();
+        upload::upload_objects(store.clone(), &changed, &manifest_key, &manifest, &options)
+            .await
+            .unwrap();
+        assert_eq!(store.get("raw/a.txt").await.unwrap(), "AAAA");
+        upload::verify_upload(store.as_ref(), "raw", &objects, 1000)
+            .await
+            .unwrap();
+    }
+}
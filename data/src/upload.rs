@@ -0,0 +1,868 @@
+//! Lays out a generated dataset as S3 objects under
+//! `<prefix>/<table>/<partition>/part-*.parquet` and describes it in a manifest.
+use crate::inspect;
+use crate::model::{self, FileFormat};
+use crate::sql::Table;
+use crate::store::{md5_hex, ObjectMeta, ObjectStore, Part, StorageClass};
+use crate::sync;
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use colored::*;
+use parquet::file::reader::FileReader;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Name of the manifest written at the root of an uploaded dataset
+pub const MANIFEST: &str = "_manifest.json";
+
+/// Name of the journal kept in a dataset directory while it is uploaded
+pub const JOURNAL: &str = ".upload-journal.json";
+
+/// A local file and the key it is uploaded to
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadObject {
+    pub path: PathBuf,
+    pub key: String,
+    /// The table a Parquet file belongs to, None for sidecar files
+    pub table: Option<String>,
+    pub partition: Option<String>,
+    pub bytes: u64,
+    pub rows: Option<i64>,
+}
+
+/// Split `s3://bucket/some/prefix` into the bucket and a prefix without slashes at either end
+pub fn parse_s3_uri(uri: &str) -> Result<(String, String), String> {
+    let rest = uri
+        .strip_prefix("s3://")
+        .ok_or_else(|| format!("'{}' is not an s3://bucket/prefix URI", uri))?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(format!("'{}' has no bucket name", uri));
+    }
+    Ok((bucket.to_string(), prefix.trim_matches('/').to_string()))
+}
+
+fn join_key(prefix: &str, path: &str) -> String {
+    if prefix.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", prefix, path)
+    }
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Unable to read '{}': {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The table and partition of a Parquet file `<stem>.parquet` in `dirs`:
+/// directories named `key=value` are partitions, those above them the table,
+/// and a file that is not a `part-*` file outside partitions is its own table
+pub fn table_and_partition(dirs: &[String], stem: &str) -> (String, Option<String>) {
+    let split = dirs.iter().position(|d| d.contains('='));
+    let (mut table, partition) = match split {
+        Some(at) => (dirs[..at].to_vec(), Some(dirs[at..].join("/"))),
+        None => (dirs.to_vec(), None),
+    };
+    if table.is_empty() || (split.is_none() && !stem.starts_with("part-")) {
+        table.push(stem.to_string());
+    }
+    (table.join("/"), partition)
+}
+
+/// Map every file under `dir` to its key under `prefix`.
+///
+/// Directories named `key=value` are partitions and the directories above
+/// them name the table, so `analytics/events/dt=2024-05-01/a.parquet` is
+/// uploaded as `<prefix>/analytics/events/dt=2024-05-01/part-00000.parquet`.
+/// A loose `Teams.parquet` becomes `<prefix>/Teams/part-00000.parquet`.
+/// Parquet files are numbered per partition in name order; every other file,
+/// such as `_evolution.json`, keeps its relative path.
+pub fn plan_upload(dir: &Path, prefix: &str) -> Result<Vec<UploadObject>, String> {
+    let mut files = Vec::new();
+    walk(dir, &mut files)?;
+    files.sort();
+
+    let mut parts: BTreeMap<(String, Option<String>), usize> = BTreeMap::new();
+    let mut objects = Vec::new();
+    for path in files {
+        let relative = path.strip_prefix(dir).map_err(|e| e.to_string())?;
+        let components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let (file, dirs) = components.split_last().unwrap();
+        let bytes = std::fs::metadata(&path)
+            .map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?
+            .len();
+        if dirs.is_empty() && file == MANIFEST {
+            continue;
+        }
+
+        let stem = match file.strip_suffix(".parquet") {
+            Some(stem) => stem,
+            None => {
+                objects.push(UploadObject {
+                    key: join_key(prefix, &components.join("/")),
+                    path,
+                    table: None,
+                    partition: None,
+                    bytes,
+                    rows: None,
+                });
+                continue;
+            }
+        };
+
+        let (table, partition) = table_and_partition(dirs, stem);
+
+        let index = parts.entry((table.clone(), partition.clone())).or_insert(0);
+        let name = format!("part-{:05}.parquet", index);
+        *index += 1;
+        let key = match &partition {
+            Some(partition) => format!("{}/{}/{}", table, partition, name),
+            None => format!("{}/{}", table, name),
+        };
+        let rows = inspect::open_parquet(&path)
+            .map(|reader| reader.metadata().file_metadata().num_rows())
+            .ok();
+
+        objects.push(UploadObject {
+            key: join_key(prefix, &key),
+            path,
+            table: Some(table),
+            partition,
+            bytes,
+            rows,
+        });
+    }
+    Ok(objects)
+}
+
+/// The manifest describing an uploaded dataset, tables first then every object
+pub fn manifest(source: &Path, destination: &str, objects: &[UploadObject]) -> serde_json::Value {
+    let mut tables: BTreeMap<&str, (Vec<&str>, usize, u64, i64)> = BTreeMap::new();
+    for object in objects {
+        if let Some(table) = &object.table {
+            let entry = tables.entry(table).or_default();
+            if let Some(partition) = &object.partition {
+                if !entry.0.contains(&partition.as_str()) {
+                    entry.0.push(partition);
+                }
+            }
+            entry.1 += 1;
+            entry.2 += object.bytes;
+            entry.3 += object.rows.unwrap_or(0);
+        }
+    }
+
+    json!({
+        "source": source.display().to_string(),
+        "destination": destination,
+        "created_at": Utc::now().to_rfc3339(),
+        "objects": objects.len(),
+        "bytes": objects.iter().map(|o| o.bytes).sum::<u64>(),
+        "tables": tables.iter().map(|(name, (partitions, objects, bytes, rows))| json!({
+            "name": name,
+            "location": format!("{}/{}", destination.trim_end_matches('/'), name),
+            "partitions": partitions,
+            "objects": objects,
+            "bytes": bytes,
+            "rows": rows,
+        })).collect::<Vec<_>>(),
+        "files": objects.iter().map(|o| json!({
+            "key": o.key,
+            "bytes": o.bytes,
+            "rows": o.rows,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// What every key under `prefix` starts with, for listing
+pub fn list_prefix(prefix: &str) -> String {
+    join_key(prefix, "")
+}
+
+/// Key of the manifest for a dataset uploaded under `prefix`
+pub fn manifest_key(prefix: &str) -> String {
+    join_key(prefix, MANIFEST)
+}
+
+/// How files are split into parts and how many transfers run at once
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    /// Files and parts in flight at once, across all files
+    pub concurrency: usize,
+    /// Files larger than this are streamed from disk in parts of this size
+    pub part_size: u64,
+    /// Where progress is recorded so an interrupted upload can resume
+    pub journal: Option<PathBuf>,
+    /// Storage class of the data files, the manifest stays in S3's default
+    pub storage_class: Option<StorageClass>,
+    /// Skip the files the journal records as uploaded. Sync turns this off:
+    /// it already compared them with the store and found them different.
+    pub resume_completed: bool,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            concurrency: 8,
+            part_size: 8 * 1024 * 1024,
+            journal: None,
+            storage_class: None,
+            resume_completed: true,
+        }
+    }
+}
+
+/// The size and modification time of a local file, a journal entry whose
+/// file no longer has them is stale
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch
+    pub modified: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Result<FileStamp, String> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos() as u64);
+        Ok(FileStamp {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// A finished part: the ETag the store gave it and the MD5 of the bytes sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalPart {
+    pub etag: String,
+    pub md5: String,
+}
+
+/// A multipart upload in progress: its id, the file it was started from and
+/// every finished part
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultipartProgress {
+    pub upload_id: String,
+    pub file: FileStamp,
+    pub part_size: u64,
+    pub parts: BTreeMap<i32, JournalPart>,
+}
+
+/// What an upload has finished so far, keyed by object key
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    /// The manifest the upload ends with, a journal for another destination is ignored
+    pub destination: String,
+    /// The file every object uploaded whole or completed from its parts was read from
+    pub completed: BTreeMap<String, FileStamp>,
+    pub multipart: BTreeMap<String, MultipartProgress>,
+}
+
+/// A journal saved to disk after every change
+struct JournalFile {
+    path: Option<PathBuf>,
+    journal: Mutex<Journal>,
+}
+
+impl JournalFile {
+    fn open(path: Option<PathBuf>, destination: &str) -> Result<JournalFile, String> {
+        let saved = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str::<Journal>(&content).ok())
+            .filter(|journal| journal.destination == destination);
+        let journal = saved.unwrap_or_else(|| Journal {
+            destination: destination.to_string(),
+            ..Journal::default()
+        });
+        Ok(JournalFile {
+            path,
+            journal: Mutex::new(journal),
+        })
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Journal) -> T) -> T {
+        f(&self.journal.lock().unwrap())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Journal)) -> Result<(), String> {
+        let mut journal = self.journal.lock().unwrap();
+        f(&mut journal);
+        match &self.path {
+            Some(path) => {
+                let temp = path.with_extension("tmp");
+                std::fs::write(&temp, serde_json::to_string_pretty(&*journal).unwrap())
+                    .and_then(|_| std::fs::rename(&temp, path))
+                    .map_err(|e| format!("Error writing '{}': {}", path.display(), e))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self) {
+        if let Some(path) = &self.path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+async fn acquire(semaphore: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit, String> {
+    semaphore
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Read `len` bytes of `path` starting at `offset`
+async fn read_range(path: &Path, offset: u64, len: u64) -> Result<Bytes, String> {
+    let read_error = |e: std::io::Error| format!("Unable to read '{}': {}", path.display(), e);
+    let mut file = tokio::fs::File::open(path).await.map_err(read_error)?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(read_error)?;
+    let mut body = Vec::with_capacity(len as usize);
+    file.take(len)
+        .read_to_end(&mut body)
+        .await
+        .map_err(read_error)?;
+    Ok(Bytes::from(body))
+}
+
+/// Whether a journaled multipart upload can go on: the file is unchanged and
+/// every finished part still holds the bytes that were sent
+async fn resumable(
+    progress: &MultipartProgress,
+    path: &Path,
+    file: FileStamp,
+    part_size: u64,
+) -> Result<bool, String> {
+    if progress.file != file || progress.part_size != part_size {
+        return Ok(false);
+    }
+    for (number, part) in &progress.parts {
+        let body = read_range(path, (*number - 1) as u64 * part_size, part_size).await?;
+        if md5_hex(&body) != part.md5 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Upload one file in parts, reusing the parts a journaled earlier run
+/// finished unless the file changed since
+async fn upload_multipart(
+    store: Arc<dyn ObjectStore>,
+    object: UploadObject,
+    file: FileStamp,
+    part_size: u64,
+    storage_class: Option<StorageClass>,
+    semaphore: Arc<Semaphore>,
+    journal: Arc<JournalFile>,
+) -> Result<(), String> {
+    let key = object.key.clone();
+    let saved = journal.read(|j| j.multipart.get(&key).cloned());
+    let resume = match &saved {
+        Some(progress) => resumable(progress, &object.path, file, part_size).await?,
+        None => false,
+    };
+    let progress = match saved {
+        Some(progress) if resume => progress,
+        stale => {
+            if let Some(stale) = stale {
+                let _ = store.abort_multipart(&key, &stale.upload_id).await;
+            }
+            let progress = MultipartProgress {
+                upload_id: store.create_multipart(&key, storage_class).await?,
+                file,
+                part_size,
+                parts: BTreeMap::new(),
+            };
+            journal.update(|j| {
+                j.multipart.insert(key.clone(), progress.clone());
+            })?;
+            progress
+        }
+    };
+
+    let part_count = object.bytes.div_ceil(part_size) as i32;
+    let mut part_tasks = Vec::new();
+    for number in (1..=part_count).filter(|n| !progress.parts.contains_key(n)) {
+        let permit = acquire(&semaphore).await?;
+        let (store, journal, key) = (store.clone(), journal.clone(), key.clone());
+        let (path, upload_id) = (object.path.clone(), progress.upload_id.clone());
+        part_tasks.push(tokio::spawn(async move {
+            let _permit = permit;
+            let body = read_range(&path, (number - 1) as u64 * part_size, part_size).await?;
+            let md5 = md5_hex(&body);
+            let part = store.put_part(&key, &upload_id, number, body).await?;
+            journal.update(|j| {
+                if let Some(progress) = j.multipart.get_mut(&key) {
+                    let etag = part.etag.clone();
+                    progress
+                        .parts
+                        .insert(part.number, JournalPart { etag, md5 });
+                }
+            })?;
+            Ok::<Part, String>(part)
+        }));
+    }
+
+    let mut parts: Vec<Part> = progress
+        .parts
+        .iter()
+        .map(|(&number, part)| Part {
+            number,
+            etag: part.etag.clone(),
+        })
+        .collect();
+    for part_task in part_tasks {
+        parts.push(
+            part_task
+                .await
+                .map_err(|e| format!("Error joining upload task: {}", e))??,
+        );
+    }
+    store
+        .complete_multipart(&key, &progress.upload_id, parts)
+        .await?;
+    journal.update(|j| {
+        j.multipart.remove(&key);
+        j.completed.insert(key.clone(), file);
+    })?;
+    println!(
+        "  ⬆️  {} ({} parts)",
+        key.green(),
+        part_count.to_string().cyan()
+    );
+    Ok(())
+}
+
+/// Upload every object, then the manifest so its presence marks a complete
+/// upload. Files above the part size go up in parts, with at most
+/// `concurrency` files or parts in flight. With a journal, a rerun after an
+/// interruption skips the files and parts that already finished, as long as
+/// their file has the same size and modification time and the parts the same MD5.
+pub async fn upload_objects(
+    store: Arc<dyn ObjectStore>,
+    objects: &[UploadObject],
+    manifest_key: &str,
+    manifest: &serde_json::Value,
+    options: &UploadOptions,
+) -> Result<(), String> {
+    let destination = format!("{}/{}", store.location(), manifest_key);
+    let journal = Arc::new(JournalFile::open(options.journal.clone(), &destination)?);
+    if !options.resume_completed {
+        journal.update(|j| j.completed.clear())?;
+    }
+    let (done, parts) = journal.read(|j| {
+        (
+            j.completed.len(),
+            j.multipart.values().map(|m| m.parts.len()).sum::<usize>(),
+        )
+    });
+    if done + parts > 0 {
+        println!(
+            "♻️  Resuming: {} files and {} parts were already uploaded",
+            done.to_string().cyan(),
+            parts.to_string().cyan()
+        );
+    }
+
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut upload_tasks = Vec::new();
+    for object in objects {
+        let file = FileStamp::of(&object.path)?;
+        let finished = journal.read(|j| j.completed.get(&object.key) == Some(&file));
+        if finished {
+            continue;
+        }
+        let (store, journal, object) = (store.clone(), journal.clone(), object.clone());
+        if object.bytes > options.part_size {
+            upload_tasks.push(tokio::spawn(upload_multipart(
+                store,
+                object,
+                file,
+                options.part_size,
+                options.storage_class,
+                semaphore.clone(),
+                journal,
+            )));
+            continue;
+        }
+        let permit = acquire(&semaphore).await?;
+        let storage_class = options.storage_class;
+        upload_tasks.push(tokio::spawn(async move {
+            let _permit = permit;
+            let body = tokio::fs::read(&object.path)
+                .await
+                .map_err(|e| format!("Unable to read '{}': {}", object.path.display(), e))?;
+            store
+                .put(&object.key, Bytes::from(body), storage_class)
+                .await?;
+            journal.update(|j| {
+                j.completed.insert(object.key.clone(), file);
+            })?;
+            println!("  ⬆️  {}", object.key.green());
+            Ok::<(), String>(())
+        }));
+    }
+    for upload_task in upload_tasks {
+        upload_task
+            .await
+            .map_err(|e| format!("Error joining upload task: {}", e))??;
+    }
+
+    let manifest = serde_json::to_string_pretty(manifest).unwrap();
+    store.put(manifest_key, Bytes::from(manifest), None).await?;
+    journal.remove();
+    Ok(())
+}
+
+/// Check that every object is stored under its key with the local file's
+/// size and, when the store gives one, an ETag the file could have had when
+/// uploaded in parts of `part_size`
+pub async fn verify_upload(
+    store: &dyn ObjectStore,
+    prefix: &str,
+    objects: &[UploadObject],
+    part_size: u64,
+) -> Result<(), String> {
+    let stored: BTreeMap<String, ObjectMeta> = store
+        .list(&list_prefix(prefix))
+        .await?
+        .into_iter()
+        .map(|o| (o.key.clone(), o))
+        .collect();
+    let mut problems = Vec::new();
+    for object in objects {
+        match stored.get(&object.key) {
+            None => problems.push(format!("'{}' is missing", object.key)),
+            Some(meta) if meta.size != object.bytes => problems.push(format!(
+                "'{}' has {} bytes, expected {}",
+                object.key, meta.size, object.bytes
+            )),
+            Some(ObjectMeta {
+                etag: Some(etag), ..
+            }) if !sync::file_etags(&object.path, part_size)?.contains(etag) => {
+                problems.push(format!(
+                    "'{}' has ETag {}, not that of the local file",
+                    object.key, etag
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Upload to {} is incomplete:\n  {}",
+            store.location(),
+            problems.join("\n  ")
+        ))
+    }
+}
+
+/// Write every table as `partitions` daily `dt=YYYY-MM-DD` partitions of
+/// `rows` rows each under `<dir>/<table>`, ending yesterday
+pub fn generate_dataset(
+    tables: &[Table],
+    rows: usize,
+    partitions: usize,
+    dir: &Path,
+) -> Result<(), String> {
+    let first_day = Utc::now().date_naive() - Duration::days(partitions as i64);
+    for table in tables {
+        let table_dir = model::table_dir(dir, table).join(model::file_name(&table.name));
+        for p in 0..partitions {
+            let day = first_day + Duration::days(p as i64);
+            let partition_dir = table_dir.join(format!("dt={}", day.format("%Y-%m-%d")));
+            std::fs::create_dir_all(&partition_dir)
+                .map_err(|e| format!("Error creating '{}': {}", partition_dir.display(), e))?;
+            let values = model::generate_table_rows(table, rows)?;
+            model::write_rows(
+                &partition_dir.join("part-00000.parquet"),
+                table,
+                &values,
+                FileFormat::Parquet,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes as B, KB, MB or GB
+pub fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Print the objects written for each table
+pub fn print_summary(objects: &[UploadObject], destination: &str, dry_run: bool) {
+    let mut tables: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+    for object in objects {
+        let entry = tables
+            .entry(object.table.as_deref().unwrap_or("(sidecar files)"))
+            .or_default();
+        entry.0 += 1;
+        entry.1 += object.bytes;
+    }
+    for (table, (count, bytes)) in &tables {
+        println!(
+            "  {} {} objects, {}",
+            table.bold().yellow(),
+            count.to_string().cyan(),
+            human_bytes(*bytes)
+        );
+    }
+    let total: u64 = objects.iter().map(|o| o.bytes).sum();
+    println!(
+        "{} {} objects ({}) {} {}",
+        if dry_run { "📋" } else { "☁️ " },
+        (objects.len() + 1).to_string().bold().cyan(),
+        human_bytes(total).bold().cyan(),
+        if dry_run {
+            "would be written to"
+        } else {
+            "written to"
+        },
+        destination.bold().green()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql;
+    use crate::store::MemoryStore;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_plan_upload_keeps_tables_and_partitions() {
+        let dir = TempDir::new("upload").unwrap();
+        let tables =
+            sql::parse_schema("CREATE TABLE crm.Teams (ID INT PRIMARY KEY, Name VARCHAR(20));")
+                .unwrap()
+                .tables;
+        generate_dataset(&tables, 10, 2, dir.path()).unwrap();
+        let loose = dir.path().join("Players.parquet");
+        model::write_rows(
+            &loose,
+            &tables[0],
+            &model::generate_table_rows(&tables[0], 5).unwrap(),
+            FileFormat::Parquet,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("crm/Teams/_evolution.json"), "{}").unwrap();
+
+        let objects = plan_upload(dir.path(), "raw/v1").unwrap();
+        let keys: Vec<&str> = objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(keys[0], "raw/v1/Players/part-00000.parquet");
+        assert_eq!(keys[1], "raw/v1/crm/Teams/_evolution.json");
+        assert!(keys[2].starts_with("raw/v1/crm/Teams/dt="));
+        assert!(keys[2].ends_with("/part-00000.parquet"));
+        assert_eq!(objects[2].table.as_deref(), Some("crm/Teams"));
+        assert_eq!(objects[2].rows, Some(10));
+
+        let manifest = manifest(dir.path(), "s3://bucket/raw/v1", &objects);
+        assert_eq!(manifest["objects"], 4);
+        assert_eq!(manifest["tables"][1]["name"], "crm/Teams");
+        assert_eq!(manifest["tables"][1]["rows"], 20);
+        assert_eq!(
+            manifest["tables"][1]["location"],
+            "s3://bucket/raw/v1/crm/Teams"
+        );
+        assert_eq!(
+            parse_s3_uri("s3://bucket/raw/v1/").unwrap(),
+            ("bucket".to_string(), "raw/v1".to_string())
+        );
+        assert!(parse_s3_uri("bucket/raw").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upload_and_verify_against_memory_store() {
+        let dir = TempDir::new("upload").unwrap();
+        let tables = sql::parse_schema("CREATE TABLE Teams (ID INT PRIMARY KEY);")
+            .unwrap()
+            .tables;
+        generate_dataset(&tables, 10, 3, dir.path()).unwrap();
+        let objects = plan_upload(dir.path(), "raw").unwrap();
+        let store = Arc::new(MemoryStore::default());
+        let options = UploadOptions {
+            concurrency: 2,
+            storage_class: Some(StorageClass::DeepArchive),
+            ..UploadOptions::default()
+        };
+
+        assert!(
+            verify_upload(store.as_ref(), "raw", &objects, options.part_size)
+                .await
+                .is_err()
+        );
+        let manifest = manifest(dir.path(), "memory://raw", &objects);
+        upload_objects(
+            store.clone(),
+            &objects,
+            &manifest_key("raw"),
+            &manifest,
+            &options,
+        )
+        .await
+        .unwrap();
+        verify_upload(store.as_ref(), "raw", &objects, options.part_size)
+            .await
+            .unwrap();
+        let classes: Vec<(String, Option<String>)> = store
+            .list("raw/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|o| (o.key, o.storage_class))
+            .collect();
+        assert_eq!(classes.len(), 4);
+        for (key, class) in classes {
+            let expected = if key.ends_with(MANIFEST) {
+                "STANDARD"
+            } else {
+                "DEEP_ARCHIVE"
+            };
+            assert_eq!(class.as_deref(), Some(expected), "{}", key);
+        }
+        let stored: serde_json::Value =
+            serde_json::from_slice(&store.get("raw/_manifest.json").await.unwrap()).unwrap();
+        assert_eq!(stored["tables"][0]["rows"], 30);
+
+        store
+            .put(&objects[0].key, Bytes::from("truncated"), None)
+            .await
+            .unwrap();
+        let error = verify_upload(store.as_ref(), "raw", &objects, options.part_size)
+            .await
+            .unwrap_err();
+        assert!(error.contains(&objects[0].key));
+
+        // Same size, other bytes
+        let corrupt = Bytes::from(vec![0; objects[0].bytes as usize]);
+        store.put(&objects[0].key, corrupt, None).await.unwrap();
+        let error = verify_upload(store.as_ref(), "raw", &objects, options.part_size)
+            .await
+            .unwrap_err();
+        assert!(error.contains("ETag"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_resumes_from_journal_unless_files_changed() {
+        let dir = TempDir::new("upload").unwrap();
+        let big: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(dir.path().join("big.bin"), &big).unwrap();
+        std::fs::write(dir.path().join("edited.txt"), "edited").unwrap();
+        std::fs::write(dir.path().join("small.txt"), "small").unwrap();
+        let objects = plan_upload(dir.path(), "raw").unwrap();
+        let store = Arc::new(MemoryStore::default());
+        let journal = dir.path().join(".journal.json");
+        let stamp = |name: &str| FileStamp::of(&dir.path().join(name)).unwrap();
+
+        // An earlier run finished small.txt, edited.txt before it was edited and
+        // the first part of big.bin before its bytes changed in place
+        let upload_id = store.create_multipart("raw/big.bin", None).await.unwrap();
+        let stale = Bytes::from(vec![b'x'; 1000]);
+        let first = store
+            .put_part("raw/big.bin", &upload_id, 1, stale.clone())
+            .await
+            .unwrap();
+        let earlier = Journal {
+            destination: "memory/raw/_manifest.json".to_string(),
+            completed: BTreeMap::from([
+                ("raw/small.txt".to_string(), stamp("small.txt")),
+                (
+                    "raw/edited.txt".to_string(),
+                    FileStamp {
+                        modified: 0,
+                        ..stamp("edited.txt")
+                    },
+                ),
+            ]),
+            multipart: BTreeMap::from([(
+                "raw/big.bin".to_string(),
+                MultipartProgress {
+                    upload_id,
+                    file: stamp("big.bin"),
+                    part_size: 1000,
+                    parts: BTreeMap::from([(
+                        1,
+                        JournalPart {
+                            etag: first.etag,
+                            md5: md5_hex(&stale),
+                        },
+                    )]),
+                },
+            )]),
+        };
+        std::fs::write(&journal, serde_json::to_string(&earlier).unwrap()).unwrap();
+
+        let options = UploadOptions {
+            concurrency: 2,
+            part_size: 1000,
+            journal: Some(journal.clone()),
+            ..UploadOptions::default()
+        };
+        let manifest = manifest(dir.path(), "memory/raw", &objects);
+        upload_objects(
+            store.clone(),
+            &objects,
+            &manifest_key("raw"),
+            &manifest,
+            &options,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(store.get("raw/big.bin").await.unwrap(), Bytes::from(big));
+        assert_eq!(store.get("raw/edited.txt").await.unwrap(), "edited");
+        assert!(store.get("raw/small.txt").await.is_err());
+        assert!(!journal.exists());
+    }
+}
@@ -0,0 +1,518 @@
+//! Validate Parquet datasets against the tables declared in data.sql:
+//! column layout, Arrow types, NOT NULL, UNIQUE and PRIMARY KEY, VARCHAR
+//! lengths and foreign keys between the datasets validated together.
+use colored::Colorize;
+use serde_json::json;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use arrow::datatypes::DataType as ArrowType;
+
+use crate::infer::{self, InferOptions};
+use crate::inspect;
+use crate::model::{self, Value};
+use crate::sql::{DataType, Table};
+
+/// Examples kept per rule, every violation is still counted
+const MAX_EXAMPLES: usize = 5;
+
+/// A single check run against every dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Columns,
+    Types,
+    NotNull,
+    Unique,
+    VarCharLength,
+    ForeignKey,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::Columns,
+        Rule::Types,
+        Rule::NotNull,
+        Rule::Unique,
+        Rule::VarCharLength,
+        Rule::ForeignKey,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rule::Columns => "columns",
+            Rule::Types => "types",
+            Rule::NotNull => "not_null",
+            Rule::Unique => "unique",
+            Rule::VarCharLength => "varchar_length",
+            Rule::ForeignKey => "foreign_key",
+        }
+    }
+}
+
+/// One place where the data breaks a rule
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub rule: Rule,
+    pub file: String,
+    /// Row within `file`, absent for file level problems
+    pub row: Option<usize>,
+    pub column: String,
+    pub message: String,
+}
+
+impl Violation {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "rule": self.rule.as_str(),
+            "file": self.file,
+            "row": self.row,
+            "column": self.column,
+            "message": self.message,
+        })
+    }
+}
+
+/// Outcome of one rule for a dataset
+#[derive(Debug, Clone)]
+pub struct RuleSummary {
+    pub rule: Rule,
+    pub violations: usize,
+    pub examples: Vec<Violation>,
+    /// Why the rule could not be checked, for foreign keys to datasets that
+    /// were not part of the run
+    pub skipped: Option<String>,
+}
+
+/// Every rule's outcome for one dataset
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    pub table: String,
+    pub path: String,
+    pub num_rows: usize,
+    pub rules: Vec<RuleSummary>,
+}
+
+impl ValidationReport {
+    fn new(table: &str, path: &Path) -> ValidationReport {
+        ValidationReport {
+            table: table.to_string(),
+            path: path.display().to_string(),
+            num_rows: 0,
+            rules: Rule::ALL
+                .iter()
+                .map(|&rule| RuleSummary {
+                    rule,
+                    violations: 0,
+                    examples: Vec::new(),
+                    skipped: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn summary_mut(&mut self, rule: Rule) -> &mut RuleSummary {
+        self.rules.iter_mut().find(|s| s.rule == rule).unwrap()
+    }
+
+    fn record(&mut self, violation: Violation) {
+        let summary = self.summary_mut(violation.rule);
+        summary.violations += 1;
+        if summary.examples.len() < MAX_EXAMPLES {
+            summary.examples.push(violation);
+        }
+    }
+
+    fn skip(&mut self, rule: Rule, reason: String) {
+        let summary = self.summary_mut(rule);
+        if summary.skipped.is_none() {
+            summary.skipped = Some(reason);
+        }
+    }
+
+    pub fn violations(&self) -> usize {
+        self.rules.iter().map(|s| s.violations).sum()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.violations() == 0
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "table": self.table,
+            "path": self.path,
+            "num_rows": self.num_rows,
+            "passed": self.passed(),
+            "rules": self.rules.iter().map(|s| json!({
+                "rule": s.rule.as_str(),
+                "violations": s.violations,
+                "skipped": s.skipped,
+                "examples": s.examples.iter().map(|v| v.to_json()).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn print(&self) {
+        let verdict = if self.passed() {
+            "PASSED".green().bold()
+        } else {
+            "FAILED".red().bold()
+        };
+        println!(
+            "\n🧪 {} against {} ({} rows): {}",
+            self.path.bold(),
+            self.table.bold().yellow(),
+            self.num_rows.to_string().cyan(),
+            verdict
+        );
+        for summary in &self.rules {
+            match &summary.skipped {
+                Some(reason) if summary.violations == 0 => {
+                    println!("  ⏭️  {:<15} {}", summary.rule.as_str(), reason.dimmed())
+                }
+                _ if summary.violations == 0 => {
+                    println!("  ✅ {:<15} ok", summary.rule.as_str())
+                }
+                _ => {
+                    println!(
+                        "  ❌ {:<15} {} violations",
+                        summary.rule.as_str(),
+                        summary.violations.to_string().red().bold()
+                    );
+                    for example in &summary.examples {
+                        let at = match example.row {
+                            Some(row) => format!("{} row {}", example.file, row),
+                            None => example.file.clone(),
+                        };
+                        println!(
+                            "       {} {}: {}",
+                            at.dimmed(),
+                            example.column.bold(),
+                            example.message
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// True when Parquet data of type `found` can be read as the SQL `expected`
+/// type without losing values
+pub fn is_compatible(expected: &DataType, found: &ArrowType) -> bool {
+    use ArrowType::*;
+    match expected {
+        DataType::Int(_) => matches!(found, Int8 | Int16 | Int32 | UInt8 | UInt16),
+        DataType::BigInt(_) => {
+            matches!(
+                found,
+                Int8 | Int16 | Int32 | Int64 | UInt8 | UInt16 | UInt32
+            )
+        }
+        DataType::Float(_) => matches!(found, Float32),
+        DataType::Double(_) => matches!(found, Float32 | Float64),
+        DataType::String(_) | DataType::VarChar(_) | DataType::UUID(_) | DataType::Enum(_) => {
+            matches!(found, Utf8 | LargeUtf8)
+        }
+        DataType::DateTime(_) => matches!(found, Date32 | Date64 | Timestamp(_, _)),
+        DataType::Boolean(_) => matches!(found, Boolean),
+    }
+}
+
+/// Foreign key values seen in a child dataset, with where each was first seen
+type ForeignKeyValues = HashMap<String, (String, usize, usize)>;
+
+/// Scan one dataset, recording every rule except foreign keys, which need
+/// all datasets. Returns the child values for each foreign key column.
+fn scan_dataset(
+    path: &Path,
+    table: &Table,
+    referenced: &HashSet<(String, String)>,
+    keys: &mut HashMap<(String, String), HashSet<String>>,
+) -> Result<(ValidationReport, HashMap<usize, ForeignKeyValues>), String> {
+    let mut report = ValidationReport::new(&table.name, path);
+    let files = inspect::find_parquet_files(path)?;
+    if files.is_empty() {
+        return Err(format!("No Parquet files found in '{}'", path.display()));
+    }
+
+    let mut seen: Vec<HashSet<String>> = vec![HashSet::new(); table.columns.len()];
+    let mut foreign_keys: HashMap<usize, ForeignKeyValues> = HashMap::new();
+
+    for file in &files {
+        let file_name = file.display().to_string();
+        let mut file_checked = false;
+        let mut mapping: Vec<Option<usize>> = Vec::new();
+        let mut row_offset = 0;
+
+        infer::for_each_batch(file, &InferOptions::default(), |batch| {
+            let schema = batch.schema();
+            if !file_checked {
+                file_checked = true;
+                let names: Vec<&String> = schema.fields().iter().map(|f| f.name()).collect();
+                mapping = table
+                    .columns
+                    .iter()
+                    .map(|col| names.iter().position(|n| n.eq_ignore_ascii_case(&col.name)))
+                    .collect();
+                check_layout(&mut report, table, &names, &mapping, &file_name);
+                for (c, col) in table.columns.iter().enumerate() {
+                    if let Some(i) = mapping[c] {
+                        let found = schema.field(i).data_type();
+                        if !is_compatible(&col.data_type, found) {
+                            report.record(Violation {
+                                rule: Rule::Types,
+                                file: file_name.clone(),
+                                row: None,
+                                column: col.name.clone(),
+                                message: format!(
+                                    "expected {} ({:?}), found {:?}",
+                                    col.data_type,
+                                    model::map_sql_to_arrow_type(&col.data_type),
+                                    found
+                                ),
+                            });
+                            // Values of the wrong type are not checked further
+                            mapping[c] = None;
+                        }
+                    }
+                }
+            }
+
+            for (c, col) in table.columns.iter().enumerate() {
+                let array = match mapping[c] {
+                    Some(i) => batch.column(i),
+                    None => continue,
+                };
+                let key = (
+                    table.qualified_name().to_lowercase(),
+                    col.name.to_lowercase(),
+                );
+                for row in 0..array.len() {
+                    let at = row_offset + row;
+                    let value = model::array_value(array.as_ref(), row);
+                    if value == Value::Null {
+                        if col.is_not_null() || col.is_primary_key() {
+                            report.record(Violation {
+                                rule: Rule::NotNull,
+                                file: file_name.clone(),
+                                row: Some(at),
+                                column: col.name.clone(),
+                                message: "null in a NOT NULL column".to_string(),
+                            });
+                        }
+                        continue;
+                    }
+                    let text = value.to_string();
+                    if let DataType::VarChar(max) = col.data_type {
+                        let len = text.chars().count();
+                        if len > max {
+                            report.record(Violation {
+                                rule: Rule::VarCharLength,
+                                file: file_name.clone(),
+                                row: Some(at),
+                                column: col.name.clone(),
+                                message: format!("{} characters, limit is {}", len, max),
+                            });
+                        }
+                    }
+                    if col.is_unique() && !seen[c].insert(text.clone()) {
+                        report.record(Violation {
+                            rule: Rule::Unique,
+                            file: file_name.clone(),
+                            row: Some(at),
+                            column: col.name.clone(),
+                            message: format!("duplicate value '{}'", text),
+                        });
+                    }
+                    if referenced.contains(&key) {
+                        keys.entry(key.clone()).or_default().insert(text.clone());
+                    }
+                    if col.references().is_some() {
+                        let values = foreign_keys.entry(c).or_default();
+                        let first = values
+                            .entry(text)
+                            .or_insert_with(|| (file_name.clone(), at, 0));
+                        first.2 += 1;
+                    }
+                }
+            }
+            row_offset += batch.num_rows();
+        })?;
+        report.num_rows += row_offset;
+    }
+
+    Ok((report, foreign_keys))
+}
+
+/// Record missing, unexpected and reordered columns of one file
+fn check_layout(
+    report: &mut ValidationReport,
+    table: &Table,
+    names: &[&String],
+    mapping: &[Option<usize>],
+    file: &str,
+) {
+    let mut violation = |column: &str, message: String| {
+        report.record(Violation {
+            rule: Rule::Columns,
+            file: file.to_string(),
+            row: None,
+            column: column.to_string(),
+            message,
+        })
+    };
+    for (col, index) in table.columns.iter().zip(mapping) {
+        if index.is_none() {
+            violation(&col.name, "missing column".to_string());
+        }
+    }
+    for name in names {
+        if table.column_index(name).is_none() {
+            violation(name, "column not declared in data.sql".to_string());
+        }
+    }
+    let present: Vec<usize> = mapping.iter().flatten().copied().collect();
+    if present.windows(2).any(|w| w[0] > w[1]) {
+        let expected: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+        violation(
+            "*",
+            format!("columns out of order, expected {}", expected.join(", ")),
+        );
+    }
+}
+
+/// Validate each `(path, table)` dataset. Foreign keys are checked against
+/// the parent table's dataset when it is part of the same run and skipped
+/// otherwise.
+pub fn validate_datasets(datasets: &[(PathBuf, Table)]) -> Result<Vec<ValidationReport>, String> {
+    // References name their parent bare or qualified, keys use the qualified name
+    let tables: Vec<Table> = datasets.iter().map(|(_, table)| table.clone()).collect();
+    let resolve = |parent: &str| -> Result<String, String> {
+        let name = Table::position(&tables, parent)?
+            .map_or_else(|| parent.to_string(), |i| tables[i].qualified_name());
+        Ok(name.to_lowercase())
+    };
+    let referenced = tables
+        .iter()
+        .flat_map(|table| table.columns.iter().filter_map(|col| col.references()))
+        .map(|(t, c)| Ok((resolve(&t)?, c.to_lowercase())))
+        .collect::<Result<HashSet<(String, String)>, String>>()?;
+
+    let mut keys = HashMap::new();
+    let mut scanned = Vec::new();
+    for (path, table) in datasets {
+        scanned.push(scan_dataset(path, table, &referenced, &mut keys)?);
+    }
+
+    let validated: HashSet<String> = datasets
+        .iter()
+        .map(|(_, table)| table.qualified_name().to_lowercase())
+        .collect();
+    let mut reports = Vec::new();
+    for ((_, table), (mut report, foreign_keys)) in datasets.iter().zip(scanned) {
+        let mut columns: Vec<_> = foreign_keys.into_iter().collect();
+        columns.sort_by_key(|(c, _)| *c);
+        for (c, values) in columns {
+            let col = &table.columns[c];
+            let (parent, parent_column) = col.references().unwrap();
+            let parent_name = resolve(&parent)?;
+            if !validated.contains(&parent_name) {
+                report.skip(
+                    Rule::ForeignKey,
+                    format!("{} references {} which was not validated", col.name, parent),
+                );
+                continue;
+            }
+            let parent_keys = keys.get(&(parent_name, parent_column.to_lowercase()));
+            let mut orphans: Vec<_> = values
+                .into_iter()
+                .filter(|(value, _)| !parent_keys.is_some_and(|k| k.contains(value)))
+                .collect();
+            orphans.sort_by(|a, b| (&a.1 .0, a.1 .1).cmp(&(&b.1 .0, b.1 .1)));
+            for (value, (file, row, count)) in orphans {
+                // One example per orphaned value, but every row is counted
+                report.summary_mut(Rule::ForeignKey).violations += count - 1;
+                report.record(Violation {
+                    rule: Rule::ForeignKey,
+                    file,
+                    row: Some(row),
+                    column: col.name.clone(),
+                    message: format!(
+                        "'{}' has no match in {}.{} ({} rows)",
+                        value, parent, parent_column, count
+                    ),
+                });
+            }
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::FileFormat;
+    use crate::sql;
+
+    #[test]
+    fn test_validate_reports_each_rule() {
+        let tables = sql::parse_schema(
+            "CREATE TABLE Teams (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                Name VARCHAR(5) NOT NULL
+            );
+            CREATE TABLE Players (
+                ID INT AUTO_INCREMENT PRIMARY KEY,
+                TeamID INT REFERENCES Teams(ID),
+                Nick TEXT
+            );",
+        )
+        .unwrap()
+        .tables;
+        let dir = tempdir::TempDir::new("validate").unwrap();
+        let teams_path = dir.path().join("Teams.parquet");
+        let players_path = dir.path().join("Players.parquet");
+        let teams = vec![
+            vec![Value::Int(1), Value::Str("Lions".to_string())],
+            vec![Value::Int(2), Value::Str("Tigers".to_string())],
+            vec![Value::Int(2), Value::Null],
+        ];
+        let players = vec![
+            vec![Value::Int(1), Value::Int(1), Value::Str("a".to_string())],
+            vec![Value::Int(2), Value::Int(9), Value::Null],
+            vec![Value::Int(3), Value::Int(9), Value::Null],
+        ];
+        model::write_rows(&teams_path, &tables[0], &teams, FileFormat::Parquet).unwrap();
+        model::write_rows(&players_path, &tables[1], &players, FileFormat::Parquet).unwrap();
+
+        // Players is validated against a schema that expects another column
+        let mut expected_players = tables[1].clone();
+        expected_players
+            .columns
+            .push(sql::parse_create_table("CREATE TABLE X (Rating DOUBLE)").columns[0].clone());
+        let reports = validate_datasets(&[
+            (teams_path, tables[0].clone()),
+            (players_path, expected_players),
+        ])
+        .unwrap();
+
+        let count = |report: &ValidationReport, rule: Rule| {
+            report
+                .rules
+                .iter()
+                .find(|s| s.rule == rule)
+                .unwrap()
+                .violations
+        };
+        assert_eq!(count(&reports[0], Rule::Unique), 1);
+        assert_eq!(count(&reports[0], Rule::NotNull), 1);
+        assert_eq!(count(&reports[0], Rule::VarCharLength), 1);
+        assert_eq!(count(&reports[1], Rule::Columns), 1);
+        assert_eq!(count(&reports[1], Rule::ForeignKey), 2);
+        assert_eq!(count(&reports[1], Rule::Types), 0);
+        assert!(!reports[0].passed() && !reports[1].passed());
+    }
+}